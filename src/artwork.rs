@@ -0,0 +1,81 @@
+use super::*;
+use id3::frame::PictureType;
+use serde::Serialize;
+use std::path::Path;
+
+/// The picture embedded in the ID3v2 tag of a file as its cover art.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Artwork {
+    /// The MIME type declared by the picture frame, e.g. "image/jpeg".
+    pub mime: String,
+
+    /// The width of the image in pixels, if its header could be read.
+    pub width: Option<u32>,
+
+    /// The height of the image in pixels, if its header could be read.
+    pub height: Option<u32>,
+}
+
+/// Reads the cover art embedded in the ID3v2 tag of a file, preferring the front cover over any other picture. A file
+/// without pictures or without an ID3v2 tag has no artwork.
+pub fn read_artwork(path: &Path) -> Result<Option<Artwork>, LsError> {
+    let tag = match id3::Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(id3::Error {
+            kind: id3::ErrorKind::NoTag,
+            ..
+        }) => return Ok(None),
+        Err(err) => return Err(LsError::Id3Error(path.as_os_str().to_owned(), err)),
+    };
+    let picture = tag
+        .pictures()
+        .find(|p| p.picture_type == PictureType::CoverFront)
+        .or_else(|| tag.pictures().next());
+    Ok(picture.map(|picture| {
+        let dimensions = image_dimensions(&picture.data);
+        Artwork {
+            mime: picture.mime_type.clone(),
+            width: dimensions.map(|(width, _)| width),
+            height: dimensions.map(|(_, height)| height),
+        }
+    }))
+}
+
+/// Reads the width and height of a PNG, JPEG, GIF or BMP image from its header, without decoding the image.
+fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let be_u16 = |i: usize| data.get(i..i + 2).map(|b| u32::from(u16::from_be_bytes([b[0], b[1]])));
+    let le_u16 = |i: usize| data.get(i..i + 2).map(|b| u32::from(u16::from_le_bytes([b[0], b[1]])));
+    let be_u32 = |i: usize| data.get(i..i + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+    let le_i32 = |i: usize| data.get(i..i + 4).map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // The IHDR chunk comes first, starting with the width and height.
+        Some((be_u32(16)?, be_u32(20)?))
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some((le_u16(6)?, le_u16(8)?))
+    } else if data.starts_with(b"BM") {
+        // The height is negative for images stored top-down.
+        Some((le_i32(18)?.unsigned_abs(), le_i32(22)?.unsigned_abs()))
+    } else if data.starts_with(&[0xff, 0xd8]) {
+        // Walk the segments until the start of frame, which holds the height and width.
+        let mut pos = 2;
+        loop {
+            if *data.get(pos)? != 0xff {
+                return None;
+            }
+            let marker = *data.get(pos + 1)?;
+            match marker {
+                // Fill bytes before a marker.
+                0xff => pos += 1,
+                // Markers without a segment.
+                0x01 | 0xd0..=0xd7 => pos += 2,
+                // Every start of frame but the ones that share a range with other markers.
+                0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                    return Some((be_u16(pos + 7)?, be_u16(pos + 5)?))
+                }
+                _ => pos += 2 + be_u16(pos + 2)? as usize,
+            }
+        }
+    } else {
+        None
+    }
+}
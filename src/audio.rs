@@ -0,0 +1,498 @@
+use crate::LsError;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+use symphonia::{
+    core::{
+        codecs::{CodecParameters, Decoder, DecoderOptions, CODEC_TYPE_MP1, CODEC_TYPE_MP2, CODEC_TYPE_MP3},
+        formats::Packet,
+    },
+    default::codecs::MpaDecoder,
+};
+
+/// How many bytes after the ID3v2 tag are searched for the first MPEG frame header.
+const MAX_FRAME_SEARCH: u64 = 64 * 1024;
+
+/// The channel mode of an MPEG audio stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChannelMode {
+    /// Two independent channels.
+    Stereo,
+
+    /// Two channels encoded together, exploiting the redundancy between them.
+    JointStereo,
+
+    /// Two unrelated mono channels, e.g. a bilingual broadcast.
+    DualChannel,
+
+    /// A single channel.
+    Mono,
+}
+
+impl ChannelMode {
+    /// All channel modes, in the order they are encoded in a frame header.
+    pub const ALL: [ChannelMode; 4] = [
+        ChannelMode::Stereo,
+        ChannelMode::JointStereo,
+        ChannelMode::DualChannel,
+        ChannelMode::Mono,
+    ];
+
+    /// Returns the name of the channel mode, as used in the JSON output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelMode::Stereo => "stereo",
+            ChannelMode::JointStereo => "joint-stereo",
+            ChannelMode::DualChannel => "dual-channel",
+            ChannelMode::Mono => "mono",
+        }
+    }
+}
+
+impl fmt::Display for ChannelMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The container format of a listed file.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    /// An MPEG audio stream, usually preceded by an ID3v2 tag.
+    #[default]
+    Mp3,
+
+    /// A WAV file with an ID3 chunk.
+    Wav,
+
+    /// An AIFF or AIFF-C file with an ID3 chunk.
+    Aiff,
+}
+
+impl AudioFormat {
+    /// Returns the name of the format, as used in the JSON output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Wav => "wav",
+            AudioFormat::Aiff => "aiff",
+        }
+    }
+
+    /// Returns the format of a file by its extension, in any case, if it's one of the listed formats.
+    pub fn from_extension(path: &Path) -> Option<AudioFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "mp3" => Some(AudioFormat::Mp3),
+            "wav" | "wave" => Some(AudioFormat::Wav),
+            "aif" | "aiff" | "aifc" => Some(AudioFormat::Aiff),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name().to_uppercase())
+    }
+}
+
+/// The bitrates in kbit/s by bitrate index, for MPEG-1 layer I, II and III and for MPEG-2/2.5 layer I and II/III.
+const BITRATES: [[u32; 15]; 5] = [
+    [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+    [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+/// The properties of the audio stream, read from the header of its first frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct AudioProperties {
+    pub(crate) sample_rate: u32,
+    pub(crate) channel_mode: ChannelMode,
+    /// The duration in milliseconds, if the frames were walked. Computed from the number of samples of all the frames.
+    pub(crate) duration_ms: Option<u32>,
+    /// The number of audio frames, if the frames were walked, not counting a Xing or Info frame.
+    pub(crate) frame_count: Option<u64>,
+    /// The number of decoded samples per channel, if the frames were walked, without the encoder delay and padding
+    /// recorded in a LAME tag.
+    pub(crate) sample_count: Option<u64>,
+}
+
+/// The frames and samples of the audio, counted by walking its frames.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+struct FrameCount {
+    /// The number of frames, including a Xing or Info frame.
+    frames: u64,
+    /// The number of samples per channel of all the frames.
+    samples: u64,
+}
+
+/// A parsed MPEG audio frame header.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct FrameHeader {
+    /// The MPEG audio layer, from 1 to 3.
+    layer: u8,
+    sample_rate: u32,
+    channel_mode: ChannelMode,
+    /// The length of the whole frame in bytes, including the header, or `None` for a free format bitrate.
+    len: Option<usize>,
+    /// The number of samples per channel in the frame.
+    samples: u32,
+    /// The length of the side information following the header of a layer III frame, which is where a Xing or Info
+    /// tag starts, or zero for the other layers.
+    side_info_len: usize,
+    /// Whether the header is followed by a CRC of the frame.
+    protected: bool,
+}
+
+/// Returns whether a file is a WAV or AIFF file, which stores its ID3 tag in a chunk and doesn't contain MPEG audio,
+/// by checking its header. The file is rewound to its start afterwards.
+pub(crate) fn is_chunked_audio(file: &mut (impl Read + Seek)) -> io::Result<bool> {
+    Ok(chunked_audio_format(file)?.is_some())
+}
+
+/// Returns the format of a WAV or AIFF file by checking its header, or `None` for any other file. The file is rewound
+/// to its start afterwards.
+pub(crate) fn chunked_audio_format(file: &mut (impl Read + Seek)) -> io::Result<Option<AudioFormat>> {
+    let mut header = [0; 12];
+    let read = file.read(&mut header)?;
+    file.rewind()?;
+    if read < header.len() {
+        return Ok(None);
+    }
+    Ok(match (&header[..4], &header[8..]) {
+        (b"RIFF", b"WAVE") => Some(AudioFormat::Wav),
+        (b"FORM", b"AIFF") | (b"FORM", b"AIFC") => Some(AudioFormat::Aiff),
+        _ => None,
+    })
+}
+
+/// Reads the header of the ID3v2 tag at the start of a file, returning the length of the whole tag in bytes including
+/// its header and footer, or `None` if the file doesn't start with a tag.
+pub(crate) fn read_id3v2_len(file: &mut impl Read) -> io::Result<Option<u64>> {
+    let mut header = [0; 10];
+    let read = file.read(&mut header)?;
+    Ok((read == header.len() && &header[..3] == b"ID3").then(|| {
+        // The tag size is a 28 bit synchsafe integer that excludes the header and the optional footer.
+        let size = header[6..10]
+            .iter()
+            .fold(0, |size, b| (size << 7) | u64::from(b & 0x7f));
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        10 + size + footer
+    }))
+}
+
+/// Reads the audio properties from the first MPEG frame header following the ID3v2 tag, if one can be found. WAV and AIFF
+/// files have no MPEG frames. With `walk`, the frames are walked until the end of the audio to compute its duration and
+/// to count its frames and samples, which reads the whole file.
+pub(crate) fn read_audio_properties(path: &Path, walk: bool) -> io::Result<Option<AudioProperties>> {
+    let mut file = File::open(path)?;
+    if is_chunked_audio(&mut file)? {
+        return Ok(None);
+    }
+    let offset = read_id3v2_len(&mut file)?.unwrap_or(0);
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut buf = Vec::new();
+    if walk {
+        file.read_to_end(&mut buf)?;
+    } else {
+        file.take(MAX_FRAME_SEARCH).read_to_end(&mut buf)?;
+    }
+    let search = &buf[..buf.len().min(MAX_FRAME_SEARCH as usize)];
+    let first = search
+        .windows(4)
+        .enumerate()
+        .find_map(|(i, bytes)| parse_frame_header(bytes).map(|h| (i, h)));
+    Ok(first.map(|(start, header)| {
+        let count = walk.then(|| count_frames(&buf[start..]));
+        // A Xing or Info frame describes the stream and holds no audio, so decoders skip it.
+        let info_frame = read_info_frame(&buf[start..], &header);
+        let (frames, samples) = match (count, info_frame) {
+            (Some(count), Some(gapless)) => (
+                Some(count.frames.saturating_sub(1)),
+                Some(
+                    count
+                        .samples
+                        .saturating_sub(u64::from(header.samples))
+                        .saturating_sub(gapless.map_or(0, |(delay, padding)| u64::from(delay + padding))),
+                ),
+            ),
+            (Some(count), None) => (Some(count.frames), Some(count.samples)),
+            (None, _) => (None, None),
+        };
+        AudioProperties {
+            sample_rate: header.sample_rate,
+            channel_mode: header.channel_mode,
+            duration_ms: count.map(|c| (c.samples * 1000 / u64::from(header.sample_rate)) as u32),
+            frame_count: frames,
+            sample_count: samples,
+        }
+    }))
+}
+
+/// A defect in the MPEG audio of a file, found by `verify_audio`. Tags are read regardless of the audio, so a file can
+/// have valid tags but audio that players skip over or fail on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioDefect {
+    /// No MPEG frame was found after the ID3v2 tag.
+    NoFrames,
+
+    /// Bytes that aren't MPEG frames were found before or between the frames. Contains the offset of the bytes in the
+    /// file and their number.
+    Garbage(u64, u64),
+
+    /// The last frame is cut off by the end of the file. Contains the offset of the frame in the file and the number of
+    /// missing bytes.
+    Truncated(u64, u64),
+
+    /// The CRC of a layer III frame doesn't match its side information. Contains the offset of the frame in the file.
+    CrcMismatch(u64),
+
+    /// A frame can't be decoded, e.g. because its side information or its Huffman coded data is invalid. Contains the
+    /// offset of the frame in the file.
+    Undecodable(u64),
+}
+
+impl fmt::Display for AudioDefect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioDefect::NoFrames => write!(f, "no MPEG audio frames found"),
+            AudioDefect::Garbage(offset, len) => write!(f, "{} bytes of non-audio data at offset {}", len, offset),
+            AudioDefect::Truncated(offset, missing) => {
+                write!(f, "frame at offset {} is truncated by {} bytes", offset, missing)
+            }
+            AudioDefect::CrcMismatch(offset) => write!(f, "frame at offset {} fails its CRC check", offset),
+            AudioDefect::Undecodable(offset) => write!(f, "frame at offset {} can't be decoded", offset),
+        }
+    }
+}
+
+/// Verifies the MPEG audio of a file by walking all its frames and decoding them, returning the defects found, in the
+/// order of their offsets. Besides decoding, the CRCs of protected layer III frames are checked. WAV and AIFF files,
+/// and streams with a free format bitrate, can't be walked and have no defects.
+pub fn verify_audio(path: &Path) -> Result<Vec<AudioDefect>, LsError> {
+    let read_err = |err| LsError::IoReadError(path.as_os_str().to_owned(), err);
+    let mut file = File::open(path).map_err(read_err)?;
+    if is_chunked_audio(&mut file).map_err(read_err)? {
+        return Ok(Vec::new());
+    }
+    let offset = read_id3v2_len(&mut file).map_err(read_err)?.unwrap_or(0);
+    file.seek(SeekFrom::Start(offset)).map_err(read_err)?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).map_err(read_err)?;
+    let audio = &buf[..audio_end(&buf)];
+
+    let header_at = |pos: usize| audio.get(pos..pos + 4).and_then(parse_frame_header);
+    // A header is only trusted after garbage if the next frame follows it, as audio data can look like a header.
+    let is_frame_at = |pos: usize| {
+        header_at(pos).is_some_and(|h| {
+            h.len
+                .is_some_and(|len| pos + len >= audio.len() || header_at(pos + len).is_some())
+        })
+    };
+    let mut defects = Vec::new();
+    let Some(mut pos) = (0..audio.len().min(MAX_FRAME_SEARCH as usize)).find(|pos| is_frame_at(*pos)) else {
+        return Ok(vec![AudioDefect::NoFrames]);
+    };
+    if pos > 0 {
+        defects.push(AudioDefect::Garbage(offset, pos as u64));
+    }
+    // The frames are decoded one at a time, so that a frame that fails to decode can be told apart.
+    let mut decoder = header_at(pos).and_then(|header| frame_decoder(header.layer));
+    while pos < audio.len() {
+        let file_pos = offset + pos as u64;
+        match header_at(pos) {
+            Some(FrameHeader { len: None, .. }) => break,
+            Some(header @ FrameHeader { len: Some(len), .. }) => {
+                if pos + len > audio.len() {
+                    defects.push(AudioDefect::Truncated(file_pos, (pos + len - audio.len()) as u64));
+                    break;
+                }
+                let frame = &audio[pos..pos + len];
+                if !has_valid_crc(frame, &header) {
+                    defects.push(AudioDefect::CrcMismatch(file_pos));
+                }
+                let packet = Packet::new_from_slice(0, 0, 0, frame);
+                if decoder.as_mut().is_some_and(|d| d.decode(&packet).is_err()) {
+                    defects.push(AudioDefect::Undecodable(file_pos));
+                }
+                pos += len;
+            }
+            None => {
+                let next = (pos + 1..audio.len())
+                    .find(|pos| is_frame_at(*pos))
+                    .unwrap_or(audio.len());
+                defects.push(AudioDefect::Garbage(file_pos, (next - pos) as u64));
+                pos = next;
+            }
+        }
+    }
+    Ok(defects)
+}
+
+/// Returns a decoder for the frames of an MPEG audio layer.
+fn frame_decoder(layer: u8) -> Option<MpaDecoder> {
+    let codec = match layer {
+        1 => CODEC_TYPE_MP1,
+        2 => CODEC_TYPE_MP2,
+        _ => CODEC_TYPE_MP3,
+    };
+    MpaDecoder::try_new(CodecParameters::new().for_codec(codec), &DecoderOptions::default()).ok()
+}
+
+/// Returns the end of the audio in the bytes following the ID3v2 tag, which is before an APEv2 tag and an ID3v1 tag at
+/// the end of the file, if there are any.
+fn audio_end(buf: &[u8]) -> usize {
+    let mut end = buf.len();
+    if end >= 128 && &buf[end - 128..end - 125] == b"TAG" {
+        end -= 128;
+    }
+    // The footer of an APEv2 tag has the size of the tag without its header, and whether there is a header.
+    if let Some(footer) = buf[..end]
+        .get(end.saturating_sub(32)..)
+        .filter(|f| f.starts_with(b"APETAGEX"))
+    {
+        let size = u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]) as usize;
+        let header = if footer[23] & 0x80 != 0 { 32 } else { 0 };
+        end = end.saturating_sub(size + header);
+    }
+    end
+}
+
+/// Returns whether the CRC of a frame matches, which for layer III covers the last two bytes of the header and the side
+/// information. The CRCs of the other layers also cover parts of the audio data, and aren't checked.
+fn has_valid_crc(frame: &[u8], header: &FrameHeader) -> bool {
+    if !header.protected || header.side_info_len == 0 {
+        return true;
+    }
+    match (frame.get(4..6), frame.get(6..6 + header.side_info_len)) {
+        (Some(crc), Some(side_info)) => {
+            crc16(frame[2..4].iter().chain(side_info)) == u16::from_be_bytes([crc[0], crc[1]])
+        }
+        _ => false,
+    }
+}
+
+/// Computes the CRC-16 used by MPEG audio frames, with the polynomial 0x8005 and an initial value of 0xffff.
+fn crc16<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u16 {
+    bytes.into_iter().fold(0xffff, |crc, byte| {
+        (0..8).fold(crc ^ (u16::from(*byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Counts the frames and their samples per channel of the consecutive frames at the start of the audio. Counting stops
+/// at the first bytes that aren't a complete frame, e.g. an ID3v1 tag at the end of the file.
+fn count_frames(audio: &[u8]) -> FrameCount {
+    let mut count = FrameCount::default();
+    let mut pos = 0;
+    while let Some(header) = audio.get(pos..pos + 4).and_then(parse_frame_header) {
+        match header.len {
+            Some(len) if pos + len <= audio.len() => {
+                count.frames += 1;
+                count.samples += u64::from(header.samples);
+                pos += len;
+            }
+            _ => break,
+        }
+    }
+    count
+}
+
+/// Reads the Xing or Info tag of the first frame of the audio, returning `None` if it doesn't have one. Otherwise returns
+/// the encoder delay and padding in samples recorded in the LAME extension of the tag, if there is one.
+fn read_info_frame(frame: &[u8], header: &FrameHeader) -> Option<Option<(u32, u32)>> {
+    let start = 4 + header.side_info_len;
+    match frame.get(start..start + 8) {
+        Some(tag) if header.side_info_len > 0 && (&tag[..4] == b"Xing" || &tag[..4] == b"Info") => {
+            let flags = tag[7];
+            // The optional fields for the number of frames, of bytes, the seek table and the quality.
+            let lame = start
+                + 8
+                + [(0x01, 4), (0x02, 4), (0x04, 100), (0x08, 4)]
+                    .iter()
+                    .filter(|(flag, _)| flags & flag != 0)
+                    .map(|(_, len)| len)
+                    .sum::<usize>();
+            // The delay and padding are 12 bits each, after the encoder version and 12 other bytes.
+            Some(
+                frame
+                    .get(lame..lame + 24)
+                    .filter(|ext| &ext[..4] == b"LAME")
+                    .map(|ext| {
+                        let bits = ext[21..24].iter().fold(0, |n, b| (n << 8) | u32::from(*b));
+                        (bits >> 12, bits & 0xfff)
+                    }),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Parses a 4 byte MPEG audio frame header, returning `None` if the bytes aren't a valid header.
+fn parse_frame_header(bytes: &[u8]) -> Option<FrameHeader> {
+    if bytes[0] != 0xff || bytes[1] & 0xe0 != 0xe0 {
+        return None;
+    }
+    let version = (bytes[1] >> 3) & 0b11;
+    let layer = (bytes[1] >> 1) & 0b11;
+    let bitrate = bytes[2] >> 4;
+    let sample_rate = (bytes[2] >> 2) & 0b11;
+    if version == 0b01 || layer == 0b00 || bitrate == 0b1111 || sample_rate == 0b11 {
+        return None;
+    }
+    let base = [44100, 48000, 32000][usize::from(sample_rate)];
+    let mpeg1 = version == 0b11;
+    let sample_rate = match version {
+        0b11 => base,     // MPEG-1
+        0b10 => base / 2, // MPEG-2
+        _ => base / 4,    // MPEG-2.5
+    };
+    let padding = u32::from((bytes[2] >> 1) & 1);
+    let bitrates = match (mpeg1, layer) {
+        (true, 0b11) => &BITRATES[0],
+        (true, 0b10) => &BITRATES[1],
+        (true, _) => &BITRATES[2],
+        (false, 0b11) => &BITRATES[3],
+        (false, _) => &BITRATES[4],
+    };
+    let bitrate = bitrates[usize::from(bitrate)] * 1000;
+    // Layer I frames are made of 4 byte slots, the other layers of single bytes.
+    let (samples, len) = match layer {
+        0b11 => (384, (12 * bitrate / sample_rate + padding) * 4),
+        0b10 => (1152, 144 * bitrate / sample_rate + padding),
+        _ if mpeg1 => (1152, 144 * bitrate / sample_rate + padding),
+        _ => (576, 72 * bitrate / sample_rate + padding),
+    };
+    let channel_mode = ChannelMode::ALL[usize::from(bytes[3] >> 6)];
+    let mono = channel_mode == ChannelMode::Mono;
+    let side_info_len = match (layer, mpeg1, mono) {
+        (0b01, true, false) => 32,
+        (0b01, true, true) | (0b01, false, false) => 17,
+        (0b01, false, true) => 9,
+        _ => 0,
+    };
+    Some(FrameHeader {
+        layer: 4 - layer,
+        sample_rate,
+        channel_mode,
+        len: Some(len as usize).filter(|_| bitrate > 0),
+        samples,
+        side_info_len,
+        protected: bytes[1] & 1 == 0,
+    })
+}
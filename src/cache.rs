@@ -0,0 +1,116 @@
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::UNIX_EPOCH,
+};
+
+/// The options that change the tags read from a file, which must be the same for cached tags to be reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ReadSettings {
+    /// Whether the ID3v1 tag was merged into the ID3v2 tag.
+    pub merged: bool,
+    /// Whether the tags of WAV and AIFF files were read.
+    pub wav_aiff: bool,
+    /// The size text frames were truncated to.
+    pub max_frame_bytes: usize,
+}
+
+/// The tags of a file along with the size and modification time it had when they were read.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CachedTags {
+    size: u64,
+    modified: (u64, u32),
+    settings: ReadSettings,
+    tags: Entry,
+    /// The sort orders aren't serialized with the rest of the tags, as they're only used for sorting, so they're
+    /// cached separately.
+    title_sort_order: Option<Vec<String>>,
+    artist_sort_order: Option<Vec<String>>,
+    album_sort_order: Option<Vec<String>>,
+}
+
+/// A cache of the tags read from files, keyed by the absolute path of the file. Cached tags are only reused while the
+/// size and modification time of the file are unchanged.
+#[derive(Debug, Default)]
+pub struct Cache {
+    files: Mutex<HashMap<String, CachedTags>>,
+    changed: AtomicBool,
+}
+
+impl Cache {
+    /// Loads a cache saved to a file. A missing or unparsable file results in an empty cache, so that a stale or
+    /// corrupt cache is simply rebuilt.
+    pub fn load(path: &Path) -> Result<Cache, LsError> {
+        let files = match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(LsError::IoReadError(path.as_os_str().to_owned(), err)),
+        };
+        Ok(Cache {
+            files: Mutex::new(files),
+            changed: AtomicBool::new(false),
+        })
+    }
+
+    /// Saves the cache to a file, if anything changed since it was loaded.
+    pub fn save(&self, path: &Path) -> Result<(), LsError> {
+        if !self.changed.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let files = self.files.lock().unwrap_or_else(|err| err.into_inner());
+        let err = |err: io::Error| LsError::IoWriteError(path.as_os_str().to_owned(), err);
+        fs::write(path, serde_json::to_vec(&*files).map_err(|e| err(e.into()))?).map_err(err)
+    }
+
+    /// Returns the cached tags of a file, if the file is unchanged since they were cached and they were read the same way.
+    pub(crate) fn get(&self, path: &Path, meta: &fs::Metadata, settings: ReadSettings) -> Option<Entry> {
+        let files = self.files.lock().unwrap_or_else(|err| err.into_inner());
+        files
+            .get(&cache_key(path)?)
+            .filter(|cached| {
+                cached.size == meta.len() && Some(cached.modified) == modified(meta) && cached.settings == settings
+            })
+            .map(|cached| Entry {
+                title_sort_order: cached.title_sort_order.clone(),
+                artist_sort_order: cached.artist_sort_order.clone(),
+                album_sort_order: cached.album_sort_order.clone(),
+                ..cached.tags.clone()
+            })
+    }
+
+    /// Caches the tags of a file.
+    pub(crate) fn insert(&self, path: &Path, meta: &fs::Metadata, settings: ReadSettings, tags: &Entry) {
+        if let (Some(key), Some(modified)) = (cache_key(path), modified(meta)) {
+            let cached = CachedTags {
+                size: meta.len(),
+                modified,
+                settings,
+                tags: tags.clone(),
+                title_sort_order: tags.title_sort_order.clone(),
+                artist_sort_order: tags.artist_sort_order.clone(),
+                album_sort_order: tags.album_sort_order.clone(),
+            };
+            let mut files = self.files.lock().unwrap_or_else(|err| err.into_inner());
+            files.insert(key, cached);
+            self.changed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Returns the absolute path of a file, so that it is cached the same regardless of how it was listed.
+fn cache_key(path: &Path) -> Option<String> {
+    fs::canonicalize(path).ok().map(|p| p.to_string_lossy().to_string())
+}
+
+/// Returns the modification time of a file as seconds and nanoseconds since the Unix epoch.
+fn modified(meta: &fs::Metadata) -> Option<(u64, u32)> {
+    let since_epoch = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((since_epoch.as_secs(), since_epoch.subsec_nanos()))
+}
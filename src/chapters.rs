@@ -0,0 +1,44 @@
+use super::*;
+use serde::Serialize;
+use std::path::Path;
+
+/// A chapter of a file, as marked by an ID3v2 CHAP frame, e.g. in an audiobook.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Chapter {
+    /// The title of the chapter, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    /// The time the chapter starts at, in milliseconds.
+    pub start_ms: u32,
+
+    /// The time the chapter ends at, in milliseconds.
+    pub end_ms: u32,
+}
+
+/// Reads the chapters of a file, ordered by their start time. A file without a tag has no chapters.
+pub fn read_chapters(path: &Path) -> Result<Vec<Chapter>, LsError> {
+    let tag = match id3::Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(id3::Error {
+            kind: id3::ErrorKind::NoTag,
+            ..
+        }) => return Ok(Vec::new()),
+        Err(err) => return Err(LsError::Id3Error(path.as_os_str().to_owned(), err)),
+    };
+    let mut chapters: Vec<_> = tag
+        .chapters()
+        .map(|chapter| Chapter {
+            title: chapter
+                .frames
+                .iter()
+                .find(|frame| frame.id() == "TIT2")
+                .and_then(|frame| frame.content().text())
+                .map(str::to_owned),
+            start_ms: chapter.start_time,
+            end_ms: chapter.end_time,
+        })
+        .collect();
+    chapters.sort_by_key(|chapter| (chapter.start_ms, chapter.end_ms));
+    Ok(chapters)
+}
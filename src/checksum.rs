@@ -0,0 +1,78 @@
+use crate::audio::read_id3v2_len;
+use clap::clap_derive::ArgEnum;
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// A hash algorithm used to compute a checksum of the full file contents or of its tag.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+pub enum ChecksumAlgorithm {
+    /// CRC-32 (IEEE), displayed as 8 hex digits.
+    Crc32,
+
+    /// 64-bit XXH3, displayed as 16 hex digits.
+    Xxh3,
+}
+
+/// Computes the checksum of the whole file (tags and audio) at the given path, returned as a lowercase hex string.
+pub(crate) fn checksum_file(path: &Path, algorithm: ChecksumAlgorithm) -> io::Result<String> {
+    checksum_reader(File::open(path)?, algorithm)
+}
+
+/// Computes the checksum of only the ID3v2 tag of the file at the given path, including its header, returned as a
+/// lowercase hex string. Returns `None` if the file doesn't start with an ID3v2 tag.
+pub(crate) fn checksum_tag(path: &Path, algorithm: ChecksumAlgorithm) -> io::Result<Option<String>> {
+    let mut file = File::open(path)?;
+    match read_id3v2_len(&mut file)? {
+        Some(len) => {
+            file.seek(SeekFrom::Start(0))?;
+            checksum_reader(file.take(len), algorithm).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// The state of a checksum being computed.
+enum Hasher {
+    Crc32(crc32fast::Hasher),
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+}
+
+impl Hasher {
+    fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+            ChecksumAlgorithm::Xxh3 => Hasher::Xxh3(Box::default()),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Crc32(hasher) => hasher.update(bytes),
+            Hasher::Xxh3(hasher) => hasher.update(bytes),
+        }
+    }
+
+    /// Returns the checksum as a lowercase hex string of the length of the algorithm.
+    fn finish(self) -> String {
+        match self {
+            Hasher::Crc32(hasher) => format!("{:08x}", hasher.finalize()),
+            Hasher::Xxh3(hasher) => format!("{:016x}", hasher.digest()),
+        }
+    }
+}
+
+/// Computes the checksum of everything read from a reader.
+fn checksum_reader(mut file: impl Read, algorithm: ChecksumAlgorithm) -> io::Result<String> {
+    let mut buf = vec![0; 64 * 1024];
+    let mut hasher = Hasher::new(algorithm);
+    loop {
+        match file.read(&mut buf)? {
+            0 => break,
+            n => hasher.update(&buf[..n]),
+        }
+    }
+    Ok(hasher.finish())
+}
@@ -1,6 +1,6 @@
 use super::*;
 use clap::clap_derive::ArgEnum;
-use std::cmp::Ordering;
+use std::{borrow::Cow, cmp::Ordering, ffi::OsString, path::Path};
 
 /// A property to sort by.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
@@ -23,49 +23,220 @@ pub enum SortBy {
     /// Sort by year.
     Year,
 
-    /// Sort by track number.
+    /// Sort by track number, within each disc if both files have a disc number.
     Track,
 
     /// Sort by genre.
     Genre,
+
+    /// Sort by original artist.
+    OriginalArtist,
+
+    /// Sort by original album.
+    OriginalAlbum,
+
+    /// Sort by conductor.
+    Conductor,
+
+    /// Sort by publisher.
+    Publisher,
+
+    /// Sort by file extension (case insensitive). Files without an extension are sorted first.
+    Extension,
+
+    /// Sort by the number of directories between the listed path and the file, so that shallower files come first.
+    Depth,
+
+    /// Sort by the container format of the file, i.e. MP3, WAV or AIFF.
+    Type,
 }
 
-/// Performs a case insensitive comparison. The sort order vectors are used for the comparison if provided.
-#[inline]
-fn cmp_vec_string(
-    a: &Vec<String>,
-    b: &Vec<String>,
-    a_sort_order: &Option<Vec<String>>,
-    b_sort_order: &Option<Vec<String>>,
-) -> Ordering {
-    a_sort_order
-        .as_ref()
-        .unwrap_or(a)
+impl SortBy {
+    /// Returns the direction that suits the property when none is given: from the largest for sizes and years, which
+    /// are most often looked at to find the biggest or newest files, and ascending for the others.
+    pub fn default_direction(&self) -> Direction {
+        match self {
+            SortBy::Size | SortBy::Year => Direction::Descending,
+            _ => Direction::Ascending,
+        }
+    }
+}
+
+/// The direction to sort a property in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Direction {
+    /// Sort from the lowest to the highest value.
+    Ascending,
+
+    /// Sort from the highest to the lowest value.
+    Descending,
+}
+
+/// Options that change how the values of entries are compared.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct CmpConfig<'a> {
+    /// The articles to ignore at the start of titles, artists and albums, e.g. so that "The Beatles" is sorted as
+    /// "Beatles". Compared case insensitively. Values with a sort order are compared as is.
+    pub ignore_articles: &'a [String],
+
+    /// The genres to sort first when sorting by genre, in this order, before all other genres in alphabetical order.
+    /// Entries are ranked by their first genre, compared case insensitively.
+    pub genre_priority: &'a [String],
+}
+
+/// Removes a leading article from a value, if it's followed by more words.
+fn strip_article<'a>(value: &'a str, articles: &[String]) -> &'a str {
+    articles
         .iter()
-        .map(|s| s.to_lowercase())
-        .cmp(b_sort_order.as_ref().unwrap_or(b).iter().map(|s| s.to_lowercase()))
+        .find_map(|article| {
+            let rest = value.get(article.len()..)?;
+            (value[..article.len()].eq_ignore_ascii_case(article) && rest.starts_with(char::is_whitespace))
+                .then(|| rest.trim_start())
+                .filter(|rest| !rest.is_empty())
+        })
+        .unwrap_or(value)
+}
+
+/// Returns the sort order values if present, otherwise the values themselves without any leading article to ignore.
+#[inline]
+fn sort_values<'a>(values: &'a [String], sort_order: &'a Option<Vec<String>>, config: &CmpConfig) -> Cow<'a, [String]> {
+    match sort_order {
+        Some(sort_order) => Cow::Borrowed(sort_order),
+        None if config.ignore_articles.is_empty() => Cow::Borrowed(values),
+        None => Cow::Owned(
+            values
+                .iter()
+                .map(|v| strip_article(v, config.ignore_articles).to_owned())
+                .collect(),
+        ),
+    }
+}
+
+/// Performs a case insensitive comparison. Each value is passed through the normalization function first, if provided.
+#[inline]
+fn cmp_vec_string(a: &[String], b: &[String], normalize: Option<&dyn Fn(&str) -> String>) -> Ordering {
+    let key = |s: &String| match normalize {
+        Some(normalize) => normalize(s).to_lowercase(),
+        None => s.to_lowercase(),
+    };
+    a.iter().map(key).cmp(b.iter().map(key))
+}
+
+/// Returns the position of the first genre of an entry in the priority list, or the length of the list if it isn't in it.
+#[inline]
+fn genre_rank(entry: &Entry, priority: &[String]) -> usize {
+    entry
+        .genre
+        .first()
+        .and_then(|genre| priority.iter().position(|p| p.eq_ignore_ascii_case(genre)))
+        .unwrap_or(priority.len())
+}
+
+/// Returns the lowercased extension of a file name.
+#[inline]
+fn extension(name: &OsString) -> Option<String> {
+    Path::new(name).extension().map(|e| e.to_string_lossy().to_lowercase())
 }
 
 /// Compares the given key for an `Entry`.
 #[inline]
-fn cmp_entry_key(a: &Entry, b: &Entry, key: &SortBy) -> Ordering {
+fn cmp_entry_key(a: &Entry, b: &Entry, key: &SortBy, config: &CmpConfig) -> Ordering {
     match key {
         SortBy::Name => a.name.cmp(&b.name),
         SortBy::Size => a.size.cmp(&b.size),
-        SortBy::Title => cmp_vec_string(&a.title, &b.title, &a.title_sort_order, &b.title_sort_order),
-        SortBy::Artist => cmp_vec_string(&a.artist, &b.artist, &a.artist_sort_order, &b.artist_sort_order),
-        SortBy::Album => cmp_vec_string(&a.album, &b.album, &a.album_sort_order, &b.album_sort_order),
+        SortBy::Title => cmp_vec_string(
+            &sort_values(&a.title, &a.title_sort_order, config),
+            &sort_values(&b.title, &b.title_sort_order, config),
+            None,
+        ),
+        SortBy::Artist => cmp_vec_string(
+            &sort_values(&a.artist, &a.artist_sort_order, config),
+            &sort_values(&b.artist, &b.artist_sort_order, config),
+            None,
+        ),
+        SortBy::Album => cmp_vec_string(
+            &sort_values(&a.album, &a.album_sort_order, config),
+            &sort_values(&b.album, &b.album_sort_order, config),
+            None,
+        ),
         SortBy::Year => a.year.cmp(&b.year),
-        SortBy::Track => a.track.cmp(&b.track),
-        SortBy::Genre => cmp_vec_string(&a.genre, &b.genre, &None, &None),
+        // Track numbers restart on each disc, so the disc comes first when both entries have one.
+        SortBy::Track => match (a.disc, b.disc) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => Ordering::Equal,
+        }
+        .then_with(|| a.track.cmp(&b.track)),
+        SortBy::Genre => genre_rank(a, config.genre_priority)
+            .cmp(&genre_rank(b, config.genre_priority))
+            .then_with(|| cmp_vec_string(&a.genre, &b.genre, None)),
+        SortBy::OriginalArtist => cmp_vec_string(&a.original_artist, &b.original_artist, None),
+        SortBy::OriginalAlbum => cmp_vec_string(&a.original_album, &b.original_album, None),
+        SortBy::Conductor => cmp_vec_string(&a.conductor, &b.conductor, None),
+        SortBy::Publisher => cmp_vec_string(&a.publisher, &b.publisher, None),
+        SortBy::Extension => extension(&a.name).cmp(&extension(&b.name)),
+        SortBy::Depth => a.depth.cmp(&b.depth),
+        SortBy::Type => a.format.cmp(&b.format),
+    }
+}
+
+/// Returns whether an entry has no value for the given key. File properties are always present.
+#[inline]
+fn is_untagged(entry: &Entry, key: &SortBy) -> bool {
+    match key {
+        SortBy::Name | SortBy::Size | SortBy::Extension | SortBy::Depth | SortBy::Type => false,
+        SortBy::Title => entry.title.is_empty(),
+        SortBy::Artist => entry.artist.is_empty(),
+        SortBy::Album => entry.album.is_empty(),
+        SortBy::Year => entry.year.is_none(),
+        SortBy::Track => entry.track.number.is_none(),
+        SortBy::Genre => entry.genre.is_empty(),
+        SortBy::OriginalArtist => entry.original_artist.is_empty(),
+        SortBy::OriginalAlbum => entry.original_album.is_empty(),
+        SortBy::Conductor => entry.conductor.is_empty(),
+        SortBy::Publisher => entry.publisher.is_empty(),
+    }
+}
+
+/// Compares the given key for an `Entry` in the given direction.
+#[inline]
+fn cmp_entry_key_in(a: &Entry, b: &Entry, key: &SortBy, direction: &Direction, config: &CmpConfig) -> Ordering {
+    match direction {
+        Direction::Ascending => cmp_entry_key(a, b, key, config),
+        Direction::Descending => cmp_entry_key(a, b, key, config).reverse(),
     }
 }
 
-/// Compares the given keys for an `Entry` in order. If the comparison for the first key yields an equal result, the
-/// next key is compared and the process repeats until either the result is non-equal or all keys have been compared.
-pub fn cmp_entry(a: &Entry, b: &Entry, keys: &[SortBy]) -> Ordering {
+/// Compares the given keys for an `Entry` in order, each in its own direction. If the comparison for the first key
+/// yields an equal result, the next key is compared and the process repeats until either the result is non-equal or all
+/// keys have been compared.
+pub fn cmp_entry(a: &Entry, b: &Entry, keys: &[(SortBy, Direction)]) -> Ordering {
+    cmp_entry_with(a, b, keys, &CmpConfig::default())
+}
+
+/// Compares the given keys for an `Entry` like [`cmp_entry`], with the values compared according to the config.
+pub fn cmp_entry_with(a: &Entry, b: &Entry, keys: &[(SortBy, Direction)], config: &CmpConfig) -> Ordering {
     if keys.is_empty() {
         return Ordering::Equal;
     }
-    cmp_entry_key(a, b, &keys[0]).then_with(|| cmp_entry(a, b, &keys[1..]))
+    let (key, direction) = &keys[0];
+    cmp_entry_key_in(a, b, key, direction, config).then_with(|| cmp_entry_with(a, b, &keys[1..], config))
+}
+
+/// Compares the given keys for an `Entry` like [`cmp_entry`], except that an entry without a value for a key is always
+/// ordered after one with a value, regardless of the direction. Pass `reversed` if the result is going to be reversed,
+/// so that entries without a value still end up last. The values are compared according to the config.
+pub fn cmp_entry_untagged_last(
+    a: &Entry,
+    b: &Entry,
+    keys: &[(SortBy, Direction)],
+    reversed: bool,
+    config: &CmpConfig,
+) -> Ordering {
+    keys.iter().fold(Ordering::Equal, |ord, (key, direction)| {
+        ord.then_with(|| {
+            let untagged = is_untagged(a, key).cmp(&is_untagged(b, key));
+            if reversed { untagged.reverse() } else { untagged }
+                .then_with(|| cmp_entry_key_in(a, b, key, direction, config))
+        })
+    })
 }
@@ -0,0 +1,89 @@
+use super::*;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A change to a single field of an entry. A missing value is represented as `null`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FieldChange {
+    /// The name of the field, as used in the JSON output.
+    pub field: String,
+
+    /// The value in the old listing.
+    pub old: Value,
+
+    /// The value in the new listing.
+    pub new: Value,
+}
+
+/// An entry that is present in both listings but has different field values.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ChangedEntry {
+    /// The path of the entry.
+    pub path: String,
+
+    /// The fields that changed.
+    pub changes: Vec<FieldChange>,
+}
+
+/// The differences between two listings.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Comparison {
+    /// The paths that are only present in the new listing.
+    pub added: Vec<String>,
+
+    /// The paths that are only present in the old listing.
+    pub removed: Vec<String>,
+
+    /// The entries present in both listings whose fields differ.
+    pub changed: Vec<ChangedEntry>,
+}
+
+impl Comparison {
+    /// Returns whether the two listings were identical.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares two listings of entries keyed by path. Entries are matched by path, and the fields of matching entries are
+/// compared by their JSON representation. All results are ordered by path.
+pub fn compare(old: Vec<(String, Entry)>, new: Vec<(String, Entry)>) -> Comparison {
+    let old: BTreeMap<_, _> = old.into_iter().collect();
+    let mut new: BTreeMap<_, _> = new.into_iter().collect();
+
+    let mut comparison = Comparison::default();
+    for (path, old_entry) in old {
+        match new.remove(&path) {
+            Some(new_entry) => {
+                let changes = compare_fields(&old_entry, &new_entry);
+                if !changes.is_empty() {
+                    comparison.changed.push(ChangedEntry { path, changes });
+                }
+            }
+            None => comparison.removed.push(path),
+        }
+    }
+    comparison.added = new.into_keys().collect();
+    comparison
+}
+
+/// Compares the fields of two entries by their JSON representation, ordered by field name.
+pub(crate) fn compare_fields(old: &Entry, new: &Entry) -> Vec<FieldChange> {
+    let to_map = |entry: &Entry| match serde_json::to_value(entry) {
+        Ok(Value::Object(map)) => map,
+        _ => Default::default(),
+    };
+    let (old, new) = (to_map(old), to_map(new));
+    old.keys()
+        .chain(new.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|field| old.get(*field) != new.get(*field))
+        .map(|field| FieldChange {
+            field: field.to_owned(),
+            old: old.get(field).cloned().unwrap_or_default(),
+            new: new.get(field).cloned().unwrap_or_default(),
+        })
+        .collect()
+}
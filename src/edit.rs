@@ -0,0 +1,94 @@
+use super::*;
+use clap::ValueEnum;
+use id3::TagLike;
+use std::path::Path;
+
+/// Returns the ID3v2 text frame that stores a field, for the fields that are stored as plain text.
+fn text_frame_id(field: SortBy) -> Option<&'static str> {
+    match field {
+        SortBy::Title => Some("TIT2"),
+        SortBy::Artist => Some("TPE1"),
+        SortBy::Album => Some("TALB"),
+        SortBy::Genre => Some("TCON"),
+        SortBy::OriginalArtist => Some("TOPE"),
+        SortBy::OriginalAlbum => Some("TOAL"),
+        SortBy::Conductor => Some("TPE3"),
+        SortBy::Publisher => Some("TPUB"),
+        _ => None,
+    }
+}
+
+/// Sets a field of a tag to a value, or removes it if the value is empty. The year is a number and the track a number
+/// optionally followed by "/" and the total number of tracks, e.g. "3/12".
+fn set_field(tag: &mut id3::Tag, field: SortBy, value: &str) -> Result<(), String> {
+    let value = value.trim();
+    if let Some(id) = text_frame_id(field) {
+        if value.is_empty() {
+            tag.remove(id);
+        } else {
+            tag.set_text(id, value);
+        }
+        return Ok(());
+    }
+    match field {
+        SortBy::Year => {
+            // The year may also have been read from the recording date, which would otherwise take over.
+            tag.remove_year();
+            tag.remove_date_recorded();
+            if !value.is_empty() {
+                tag.set_year(value.parse().map_err(|_| "expected a year".to_owned())?);
+            }
+        }
+        SortBy::Track => {
+            tag.remove_track();
+            tag.remove_total_tracks();
+            if !value.is_empty() {
+                let parse = |n: &str| {
+                    n.trim()
+                        .parse()
+                        .map_err(|_| "expected a track number like 3 or 3/12".to_owned())
+                };
+                let (number, total) = match value.split_once('/') {
+                    Some((number, total)) => (parse(number)?, Some(parse(total)?)),
+                    None => (parse(value)?, None),
+                };
+                tag.set_track(number);
+                if let Some(total) = total {
+                    tag.set_total_tracks(total);
+                }
+            }
+        }
+        _ => return Err("it isn't stored in the tag".to_owned()),
+    }
+    Ok(())
+}
+
+/// Sets fields of the ID3v2 tag of a file to the given values, or removes them where the value is empty. A file without
+/// a tag gets a new ID3v2.4 tag. Returns the fields that change, in the same form as `compare`, which are only written
+/// to the file if `dry_run` is false. The file is left untouched if nothing changes.
+pub fn set_tags(path: &Path, values: &[(SortBy, String)], dry_run: bool) -> Result<Vec<FieldChange>, LsError> {
+    let mut tag = match id3::Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(id3::Error {
+            kind: id3::ErrorKind::NoTag,
+            ..
+        }) => id3::Tag::with_version(id3::Version::Id3v24),
+        Err(err) => return Err(LsError::Id3Error(path.as_os_str().to_owned(), err)),
+    };
+    let old = entry_from_tag(&tag, usize::MAX);
+    for (field, value) in values {
+        set_field(&mut tag, *field, value).map_err(|reason| {
+            let name = field
+                .to_possible_value()
+                .map(|v| v.get_name().to_owned())
+                .unwrap_or_default();
+            LsError::InvalidTagValue(name, value.clone(), reason)
+        })?;
+    }
+    let changes = compare_fields(&old, &entry_from_tag(&tag, usize::MAX));
+    if !dry_run && !changes.is_empty() {
+        tag.write_to_path(path, tag.version())
+            .map_err(|err| LsError::Id3Error(path.as_os_str().to_owned(), err))?;
+    }
+    Ok(changes)
+}
@@ -0,0 +1,161 @@
+use super::*;
+use serde::Serialize;
+use std::{fmt, fs::File, io::Read, path::Path};
+
+/// The frames of ID3v2.3 and ID3v2.4 that start with a text encoding byte, besides the text frames starting with "T".
+const ENCODED_FRAMES: &[&str] = &[
+    "COMM", "USLT", "SYLT", "WXXX", "APIC", "GEOB", "USER", "OWNE", "COMR", "IPLS",
+];
+/// The frames of ID3v2.2 that start with a text encoding byte, besides the text frames starting with "T".
+const ENCODED_FRAMES_V22: &[&str] = &["COM", "ULT", "SLT", "WXX", "PIC", "GEO", "IPL"];
+
+/// A text encoding declared by a frame of an ID3v2 tag.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub enum TextEncoding {
+    /// ISO-8859-1.
+    #[serde(rename = "latin1")]
+    Latin1,
+
+    /// UTF-16 with a byte order mark.
+    #[serde(rename = "utf-16")]
+    Utf16,
+
+    /// UTF-16 big endian without a byte order mark, only valid in ID3v2.4.
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+
+    /// UTF-8, only valid in ID3v2.4.
+    #[serde(rename = "utf-8")]
+    Utf8,
+
+    /// An encoding byte that isn't defined by the standard.
+    #[serde(rename = "invalid")]
+    Invalid,
+}
+
+impl TextEncoding {
+    fn from_byte(byte: u8) -> TextEncoding {
+        match byte {
+            0 => TextEncoding::Latin1,
+            1 => TextEncoding::Utf16,
+            2 => TextEncoding::Utf16Be,
+            3 => TextEncoding::Utf8,
+            _ => TextEncoding::Invalid,
+        }
+    }
+
+    /// Returns the name of the encoding, as used in the JSON output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextEncoding::Latin1 => "latin1",
+            TextEncoding::Utf16 => "utf-16",
+            TextEncoding::Utf16Be => "utf-16be",
+            TextEncoding::Utf8 => "utf-8",
+            TextEncoding::Invalid => "invalid",
+        }
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The text encoding declared by a frame of an ID3v2 tag.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FrameEncoding {
+    /// The id of the frame, e.g. "TIT2".
+    pub id: String,
+
+    /// The declared encoding, or `None` if the frame is compressed or encrypted so it can't be read.
+    pub encoding: Option<TextEncoding>,
+}
+
+/// Reads the text encoding declared by each frame of the ID3v2 tag of a file that has one, in the order of the frames.
+/// The encodings are read from the raw frames since they aren't kept when a tag is parsed. A file without an ID3v2 tag
+/// has no frames.
+pub fn read_frame_encodings(path: &Path) -> Result<Vec<FrameEncoding>, LsError> {
+    let err = |err| LsError::IoReadError(path.as_os_str().to_owned(), err);
+    let mut file = File::open(path).map_err(err)?;
+    let mut header = [0; 10];
+    if file.read(&mut header).map_err(err)? < header.len() || &header[..3] != b"ID3" {
+        return Ok(Vec::new());
+    }
+    let version = header[3];
+    let flags = header[5];
+    let size = synchsafe(&header[6..10]);
+    let mut tag = Vec::new();
+    file.take(size as u64).read_to_end(&mut tag).map_err(err)?;
+    // ID3v2.4 unsynchronizes each frame on its own, which leaves the headers and encoding bytes untouched.
+    if flags & 0x80 != 0 && version < 4 {
+        tag = resynchronize(&tag);
+    }
+    let mut pos = match (flags & 0x40 != 0, version) {
+        (true, 3) => 4 + be_u32(tag.get(..4).unwrap_or_default()) as usize,
+        (true, 4) => synchsafe(tag.get(..4).unwrap_or_default()) as usize,
+        _ => 0,
+    };
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    let mut frames = Vec::new();
+    while let Some(frame_header) = tag.get(pos..pos + header_len) {
+        let id = &frame_header[..id_len];
+        if !id.iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit()) {
+            // The rest of the tag is padding.
+            break;
+        }
+        let (len, compressed, data_len_indicator) = match version {
+            2 => (be_u32(&frame_header[3..6]), false, false),
+            3 => (be_u32(&frame_header[4..8]), frame_header[9] & 0xc0 != 0, false),
+            _ => (
+                synchsafe(&frame_header[4..8]),
+                frame_header[9] & 0x0c != 0,
+                frame_header[9] & 0x01 != 0,
+            ),
+        };
+        let body = tag.get(pos + header_len..).unwrap_or_default();
+        let body = &body[..body.len().min(len as usize)];
+        let id = String::from_utf8_lossy(id).into_owned();
+        let encoded = id.starts_with('T')
+            || if version == 2 {
+                ENCODED_FRAMES_V22.contains(&id.as_str())
+            } else {
+                ENCODED_FRAMES.contains(&id.as_str())
+            };
+        if encoded {
+            let offset = if data_len_indicator { 4 } else { 0 };
+            frames.push(FrameEncoding {
+                encoding: body
+                    .get(offset)
+                    .filter(|_| !compressed)
+                    .copied()
+                    .map(TextEncoding::from_byte),
+                id,
+            });
+        }
+        pos += header_len + len as usize;
+    }
+    Ok(frames)
+}
+
+/// Decodes a big endian integer of up to 4 bytes.
+fn be_u32(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |n, b| (n << 8) | u32::from(*b))
+}
+
+/// Decodes a synchsafe integer, where only the lower 7 bits of each byte are used.
+fn synchsafe(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |n, b| (n << 7) | u32::from(b & 0x7f))
+}
+
+/// Reverses the unsynchronization of a tag, removing the zero byte inserted after each 0xff byte.
+fn resynchronize(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    for (i, b) in bytes.iter().enumerate() {
+        if !(*b == 0 && i > 0 && bytes[i - 1] == 0xff) {
+            out.push(*b);
+        }
+    }
+    out
+}
@@ -1,4 +1,10 @@
-use std::{error::Error, ffi::OsString, fmt, io};
+use std::{
+    error::Error,
+    ffi::{OsStr, OsString},
+    fmt,
+    fs::FileType,
+    io,
+};
 
 /// The error type for listing MP3 operations.
 #[derive(Debug)]
@@ -6,11 +12,67 @@ pub enum LsError {
     /// The specified path was invalid.
     InvalidPath(OsString),
 
+    /// The specified path exists, but is neither a file nor a directory.
+    UnsupportedFileType(OsString, FileType),
+
     /// A file was unable to be read.
     IoReadError(OsString, io::Error),
 
+    /// A file was unable to be written.
+    IoWriteError(OsString, io::Error),
+
     /// An MP3 file was unable to be read or parsed.
     Id3Error(OsString, id3::Error),
+
+    /// A file that was listed explicitly has no ID3 tag, so it isn't listed as an MP3.
+    NotAnMp3(OsString),
+
+    /// Reading the tags of a file took longer than the timeout.
+    Timeout(OsString),
+
+    /// A template was unable to be parsed. Contains the template and the reason.
+    InvalidTemplate(String, String),
+
+    /// A value was unable to be written to a field of a tag. Contains the field, the value and the reason.
+    InvalidTagValue(String, String, String),
+
+    /// A remote file was unable to be fetched. Contains the URL and the reason.
+    #[cfg(feature = "remote")]
+    RemoteError(String, String),
+}
+
+impl LsError {
+    /// Returns a short name of the kind of error, e.g. "io" or "invalid_path", for output that is read by programs.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LsError::InvalidPath(_) => "invalid_path",
+            LsError::UnsupportedFileType(..) => "unsupported_file_type",
+            LsError::IoReadError(..) | LsError::IoWriteError(..) => "io",
+            LsError::Id3Error(..) => "id3",
+            LsError::NotAnMp3(_) => "not_an_mp3",
+            LsError::Timeout(_) => "timeout",
+            LsError::InvalidTemplate(..) => "invalid_template",
+            LsError::InvalidTagValue(..) => "invalid_tag_value",
+            #[cfg(feature = "remote")]
+            LsError::RemoteError(..) => "remote",
+        }
+    }
+
+    /// Returns the path or URL the error is about, if any.
+    pub fn path(&self) -> Option<&OsStr> {
+        match self {
+            LsError::InvalidPath(path)
+            | LsError::UnsupportedFileType(path, _)
+            | LsError::IoReadError(path, _)
+            | LsError::IoWriteError(path, _)
+            | LsError::Id3Error(path, _)
+            | LsError::NotAnMp3(path)
+            | LsError::Timeout(path) => Some(path),
+            LsError::InvalidTemplate(..) | LsError::InvalidTagValue(..) => None,
+            #[cfg(feature = "remote")]
+            LsError::RemoteError(url, _) => Some(OsStr::new(url)),
+        }
+    }
 }
 
 impl fmt::Display for LsError {
@@ -20,8 +82,15 @@ impl fmt::Display for LsError {
             "{}",
             match self {
                 LsError::InvalidPath(path) => format!("cannot access {:?}: no such file or directory", path),
+                LsError::UnsupportedFileType(path, file_type) => format!(
+                    "cannot list {:?}: {} is neither a file nor a directory",
+                    path,
+                    file_type_name(file_type)
+                ),
                 LsError::IoReadError(file, err) =>
                     format!("attempting to read {:?} resulted in an error: {}", file, err),
+                LsError::IoWriteError(file, err) =>
+                    format!("attempting to write {:?} resulted in an error: {}", file, err),
                 LsError::Id3Error(file, err) => format!(
                     "attempting to read {:?} resulted in an error: {}",
                     file,
@@ -30,16 +99,52 @@ impl fmt::Display for LsError {
                         _ => format!("{}", err),
                     }
                 ),
+                LsError::NotAnMp3(file) => format!("cannot list {:?}: not an MP3 file with an ID3 tag", file),
+                LsError::Timeout(file) => format!("attempting to read {:?} timed out", file),
+                LsError::InvalidTemplate(template, reason) => format!("invalid template {:?}: {}", template, reason),
+                LsError::InvalidTagValue(field, value, reason) =>
+                    format!("cannot set {} to {:?}: {}", field, value, reason),
+                #[cfg(feature = "remote")]
+                LsError::RemoteError(url, reason) => format!("fetching {:?} resulted in an error: {}", url, reason),
             }
         )
     }
 }
 
+/// Returns a description of a file type that is neither a file nor a directory.
+fn file_type_name(file_type: &FileType) -> &'static str {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_fifo() {
+            return "a named pipe";
+        } else if file_type.is_socket() {
+            return "a socket";
+        } else if file_type.is_block_device() {
+            return "a block device";
+        } else if file_type.is_char_device() {
+            return "a character device";
+        }
+    }
+    if file_type.is_symlink() {
+        "a symlink"
+    } else {
+        "a special file"
+    }
+}
+
 impl Error for LsError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match *self {
-            LsError::InvalidPath(_) => None,
-            LsError::IoReadError(_, ref err) => Some(err),
+            LsError::InvalidPath(_)
+            | LsError::UnsupportedFileType(..)
+            | LsError::NotAnMp3(_)
+            | LsError::Timeout(_)
+            | LsError::InvalidTemplate(..)
+            | LsError::InvalidTagValue(..) => None,
+            #[cfg(feature = "remote")]
+            LsError::RemoteError(..) => None,
+            LsError::IoReadError(_, ref err) | LsError::IoWriteError(_, ref err) => Some(err),
             LsError::Id3Error(_, ref err) => match err.kind {
                 id3::ErrorKind::Io(ref err) => Some(err),
                 _ => Some(err),
@@ -0,0 +1,109 @@
+use super::*;
+use std::str::FromStr;
+
+/// A condition on a field of an entry, used to select the entries to keep. Can be parsed from `FIELD=VALUE`, e.g.
+/// "sample_rate=44100", for the fields other than the modification time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Predicate {
+    /// The sample rate is exactly the given rate, in Hz. Needs the audio properties to be read.
+    SampleRate(u32),
+
+    /// The channel mode is the given mode. Needs the audio properties to be read.
+    ChannelMode(ChannelMode),
+
+    /// One of the ISRCs is the given code, ignoring hyphens and case.
+    Isrc(String),
+
+    /// The file is, or isn't, flagged as part of a compilation.
+    Compilation(bool),
+
+    /// The file was modified at or after the time, in seconds since the Unix epoch. Needs the modification times to be
+    /// read.
+    ModifiedSince(u64),
+
+    /// The file was modified before the time, in seconds since the Unix epoch. Needs the modification times to be read.
+    ModifiedUntil(u64),
+}
+
+/// Normalizes an ISRC for comparison, as they are often written with hyphens, e.g. "US-RC1-76-07839".
+fn normalize_isrc(isrc: &str) -> String {
+    isrc.chars().filter(|c| *c != '-').collect::<String>().to_uppercase()
+}
+
+impl Predicate {
+    /// Returns whether the predicate needs the audio properties to be read, see `ListOptions::audio_properties`.
+    pub fn needs_audio(&self) -> bool {
+        matches!(self, Predicate::SampleRate(_) | Predicate::ChannelMode(_))
+    }
+
+    /// Returns whether the predicate needs the modification times of the files to be read, see
+    /// `ListOptions::modified`.
+    pub fn needs_modified(&self) -> bool {
+        matches!(self, Predicate::ModifiedSince(_) | Predicate::ModifiedUntil(_))
+    }
+
+    /// Returns whether the entry matches the predicate. Entries without the field never match, except for
+    /// compilations, where a missing flag means the file isn't part of one.
+    pub fn matches(&self, entry: &Entry) -> bool {
+        match self {
+            Predicate::SampleRate(rate) => entry.sample_rate == Some(*rate),
+            Predicate::ChannelMode(mode) => entry.channel_mode == Some(*mode),
+            Predicate::Isrc(isrc) => {
+                let isrc = normalize_isrc(isrc);
+                entry.isrc.iter().any(|i| normalize_isrc(i) == isrc)
+            }
+            Predicate::Compilation(compilation) => entry.compilation == *compilation,
+            Predicate::ModifiedSince(time) => entry.modified.is_some_and(|m| m >= *time),
+            Predicate::ModifiedUntil(time) => entry.modified.is_some_and(|m| m < *time),
+        }
+    }
+}
+
+impl FromStr for Predicate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected FIELD=VALUE, found {:?}", s))?;
+        match field.trim() {
+            "sample_rate" => value
+                .trim()
+                .parse()
+                .map(Predicate::SampleRate)
+                .map_err(|_| format!("invalid sample rate {:?}", value)),
+            "channel_mode" => ChannelMode::ALL
+                .into_iter()
+                .find(|m| m.name().eq_ignore_ascii_case(value.trim()))
+                .map(Predicate::ChannelMode)
+                .ok_or_else(|| {
+                    format!(
+                        "invalid channel mode {:?}, expected one of: {}",
+                        value,
+                        ChannelMode::ALL.map(|m| m.name()).join(", ")
+                    )
+                }),
+            "isrc" => Ok(Predicate::Isrc(normalize_isrc(value.trim()))),
+            "compilation" => value
+                .trim()
+                .parse()
+                .map(Predicate::Compilation)
+                .map_err(|_| format!("invalid compilation flag {:?}, expected true or false", value)),
+            _ => Err(format!(
+                "unknown field {:?}, expected one of: sample_rate, channel_mode, isrc, compilation",
+                field
+            )),
+        }
+    }
+}
+
+/// Returns the entries that match all the predicates, in their original order.
+pub fn filter_entries(entries: Vec<Entry>, predicates: &[Predicate]) -> Vec<Entry> {
+    if predicates.is_empty() {
+        return entries;
+    }
+    entries
+        .into_iter()
+        .filter(|entry| predicates.iter().all(|p| p.matches(entry)))
+        .collect()
+}
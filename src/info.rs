@@ -1,8 +1,12 @@
+use super::{AudioFormat, ChannelMode};
 use serde::{
     ser::{SerializeSeq, Serializer},
-    Deserialize, Serialize,
+    Deserialize, Deserializer, Serialize,
+};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
 };
-use std::ffi::OsString;
 use tabled::Tabled;
 
 fn display_os_string(os_str: &OsString) -> String {
@@ -16,6 +20,13 @@ where
     s.serialize_str(&display_os_string(os_str))
 }
 
+fn deserialize_os_string<'de, D>(d: D) -> Result<OsString, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(d).map(OsString::from)
+}
+
 fn display_option_i32(op_i32: &Option<i32>) -> String {
     match *op_i32 {
         Some(i) => i.to_string(),
@@ -44,6 +55,25 @@ where
     }
 }
 
+/// Deserializes the output of `serialize_vec_string`, accepting a missing value, a single string or a list of strings.
+fn deserialize_vec_string<'de, D>(d: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Option::<OneOrMany>::deserialize(d)? {
+        None => vec![],
+        Some(OneOrMany::One(s)) => vec![s],
+        Some(OneOrMany::Many(v)) => v,
+    })
+}
+
 fn display_track(track: &Track) -> String {
     match track.number {
         Some(n) => {
@@ -62,9 +92,17 @@ fn is_track_empty(track: &Track) -> bool {
     track.number.is_none()
 }
 
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+/// The version of the shape of the JSON output of entries. It's bumped whenever a field is removed, renamed or changes
+/// its meaning, so that saved listings can be told apart from those of other versions. Adding a field doesn't bump it.
+pub const SCHEMA_VERSION: u32 = 1;
+
 /// Converts a size to a human readable size. Borrowed from https://github.com/dustin/go-humanize, licensed under the
 /// MIT license.
-fn human_readable_size(s: &u64) -> String {
+pub fn human_readable_size(s: &u64) -> String {
     const SUFFIXES: &[&str] = &["B", "kiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
     const BASE: f64 = 1024.0;
     if *s < 10 {
@@ -97,10 +135,35 @@ pub struct Info {
 
     /// The results of the list operation.
     pub entries: Vec<Entry>,
+
+    /// The files in the directory that were skipped because they couldn't be parsed as MP3s, if requested.
+    pub skipped: Vec<Skipped>,
+}
+
+impl Info {
+    /// Returns the full path of each entry, in the order of the entries. Entries of a listed directory are joined to
+    /// its path, while the entry of a listed file is the path itself.
+    pub fn entry_paths(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.entries.iter().map(|entry| match self.path_type {
+            PathType::File => PathBuf::from(&self.path),
+            PathType::Directory => Path::new(&self.path).join(&entry.name),
+        })
+    }
+}
+
+/// A file that was skipped while listing a directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Skipped {
+    /// The name of the file.
+    #[serde(serialize_with = "serialize_os_string")]
+    pub name: OsString,
+
+    /// The reason the file was skipped.
+    pub reason: String,
 }
 
 /// The track metadata for a file.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Track {
     /// The track number.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -112,12 +175,14 @@ pub struct Track {
 }
 
 /// A result from a list operation.
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Tabled)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, Tabled)]
+#[serde(default)]
 pub struct Entry {
     /// The name of the file.
     #[tabled(rename = "NAME")]
     #[tabled(display_with = "display_os_string")]
     #[serde(serialize_with = "serialize_os_string")]
+    #[serde(deserialize_with = "deserialize_os_string")]
     pub name: OsString,
 
     /// The size of the file.
@@ -125,11 +190,16 @@ pub struct Entry {
     #[tabled(display_with = "human_readable_size")]
     pub size: u64,
 
+    /// The container format of the file, from its header, or from its extension if the tags weren't read.
+    #[tabled(skip)]
+    pub format: AudioFormat,
+
     /// The track title.
     #[tabled(rename = "TITLE")]
     #[tabled(display_with = "display_vec_string")]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(serialize_with = "serialize_vec_string")]
+    #[serde(deserialize_with = "deserialize_vec_string")]
     pub title: Vec<String>,
 
     /// The track title sort order (only used for sorting, if present).
@@ -142,6 +212,7 @@ pub struct Entry {
     #[tabled(display_with = "display_vec_string")]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(serialize_with = "serialize_vec_string")]
+    #[serde(deserialize_with = "deserialize_vec_string")]
     pub artist: Vec<String>,
 
     /// The artist sort order (only used for sorting, if present).
@@ -154,6 +225,7 @@ pub struct Entry {
     #[tabled(display_with = "display_vec_string")]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(serialize_with = "serialize_vec_string")]
+    #[serde(deserialize_with = "deserialize_vec_string")]
     pub album: Vec<String>,
 
     /// The album sort order (only used for sorting, if present).
@@ -167,16 +239,205 @@ pub struct Entry {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub year: Option<i32>,
 
+    /// All the years found in the tag, in the order they appear, if there is more than one, e.g. the dates of several
+    /// releases of a reissue.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub years: Vec<i32>,
+
     /// The track number.
     #[tabled(rename = "TRACK")]
     #[tabled(display_with = "display_track")]
     #[serde(skip_serializing_if = "is_track_empty")]
     pub track: Track,
 
+    /// The disc number, for albums spanning multiple discs.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disc: Option<u32>,
+
+    /// The number of chapters marked in the tag, e.g. of an audiobook, if it has any.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chapter_count: Option<usize>,
+
+    /// The ids of the text frames whose text was truncated because it was too long.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub truncated_frames: Vec<String>,
+
+    /// The length of the audio in milliseconds as tagged in the TLEN frame, which may not match the actual duration.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tagged_length_ms: Option<u32>,
+
+    /// The peak amplitude of the track as tagged in the REPLAYGAIN_TRACK_PEAK TXXX frame, where 1.0 is full scale.
+    /// Kept as the tagged text, since it's only parsed to be validated.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replaygain_track_peak: Option<String>,
+
     /// The genre.
     #[tabled(rename = "GENRE")]
     #[tabled(display_with = "display_vec_string")]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(serialize_with = "serialize_vec_string")]
+    #[serde(deserialize_with = "deserialize_vec_string")]
     pub genre: Vec<String>,
+
+    /// The original artist or performer, e.g. of a cover song.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(serialize_with = "serialize_vec_string")]
+    #[serde(deserialize_with = "deserialize_vec_string")]
+    pub original_artist: Vec<String>,
+
+    /// The original album, e.g. of a cover song or reissue.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(serialize_with = "serialize_vec_string")]
+    #[serde(deserialize_with = "deserialize_vec_string")]
+    pub original_album: Vec<String>,
+
+    /// The conductor.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(serialize_with = "serialize_vec_string")]
+    #[serde(deserialize_with = "deserialize_vec_string")]
+    pub conductor: Vec<String>,
+
+    /// The remixer, or whoever else interpreted or modified the track.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(serialize_with = "serialize_vec_string")]
+    #[serde(deserialize_with = "deserialize_vec_string")]
+    pub remixer: Vec<String>,
+
+    /// The International Standard Recording Code (ISRC).
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(serialize_with = "serialize_vec_string")]
+    #[serde(deserialize_with = "deserialize_vec_string")]
+    pub isrc: Vec<String>,
+
+    /// The publisher or record label.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(serialize_with = "serialize_vec_string")]
+    #[serde(deserialize_with = "deserialize_vec_string")]
+    pub publisher: Vec<String>,
+
+    /// The copyright message.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(serialize_with = "serialize_vec_string")]
+    #[serde(deserialize_with = "deserialize_vec_string")]
+    pub copyright: Vec<String>,
+
+    /// Whether the file is part of a compilation, as flagged by iTunes.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub compilation: bool,
+
+    /// Whether some tag text isn't valid in the encoding declared by its frame, e.g. mislabeled UTF-8. The frames after
+    /// the first invalid one couldn't be read.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub invalid_text_encoding: bool,
+
+    /// The sample rate of the audio in Hz, if requested.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<u32>,
+
+    /// The channel mode of the audio, if requested.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel_mode: Option<ChannelMode>,
+
+    /// The duration of the audio in milliseconds, computed from its frames, if requested.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u32>,
+
+    /// The number of audio frames, not counting a Xing or Info frame, if requested.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_count: Option<u64>,
+
+    /// The number of decoded samples per channel, without the encoder delay and padding recorded by LAME for gapless
+    /// playback, if requested.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_count: Option<u64>,
+
+    /// The checksum of the whole file (tags and audio), if requested.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+
+    /// The checksum of only the ID3v2 tag of the file, if requested and the file has one.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag_hash: Option<String>,
+
+    /// The resolved target path if the file is a symlink, if requested.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+
+    /// The time the file was last modified, in seconds since the Unix epoch, if requested.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+
+    /// The number of directories between the listed path and the file, i.e. zero for a file given directly or listed
+    /// in a given directory (only used for sorting).
+    #[tabled(skip)]
+    #[serde(skip_serializing)]
+    pub depth: usize,
+
+    /// The directory of the path the file was given as, when files given separately from more than one directory are
+    /// listed together, so that files with the same name from different directories can be told apart.
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_dir: Option<String>,
+}
+
+impl Entry {
+    /// Creates an entry for a file with the given name, with a size of zero and no tags. Other fields can be filled in
+    /// with struct update syntax, e.g. `Entry { year: Some(2002), ..Entry::named("a.mp3") }`.
+    pub fn named(name: impl Into<OsString>) -> Entry {
+        Entry {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the values of all text tag fields, including the sort orders, for modifying them in place.
+    pub fn text_values_mut(&mut self) -> impl Iterator<Item = &mut Vec<String>> {
+        [
+            &mut self.title,
+            &mut self.artist,
+            &mut self.album,
+            &mut self.genre,
+            &mut self.original_artist,
+            &mut self.original_album,
+            &mut self.conductor,
+            &mut self.remixer,
+            &mut self.isrc,
+            &mut self.publisher,
+            &mut self.copyright,
+        ]
+        .into_iter()
+        .chain(
+            [
+                &mut self.title_sort_order,
+                &mut self.artist_sort_order,
+                &mut self.album_sort_order,
+            ]
+            .into_iter()
+            .flatten(),
+        )
+    }
 }
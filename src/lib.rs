@@ -4,12 +4,44 @@
 //!
 //! This module contains basic methods to list and compare (for sorting) MP3 files from the local filesystem.
 
+mod artwork;
+mod audio;
+mod cache;
+mod chapters;
+mod checksum;
 mod cmp;
+mod compare;
+mod edit;
+mod encodings;
 mod error;
+mod filter;
 mod info;
 mod list;
+mod organize;
+#[cfg(feature = "remote")]
+mod remote;
+mod search;
+mod stats;
+mod template;
+mod validate;
 
+pub use artwork::*;
+pub use audio::*;
+pub use cache::*;
+pub use chapters::*;
+pub use checksum::*;
 pub use cmp::*;
+pub use compare::*;
+pub use edit::*;
+pub use encodings::*;
 pub use error::*;
+pub use filter::*;
 pub use info::*;
 pub use list::*;
+pub use organize::*;
+#[cfg(feature = "remote")]
+pub use remote::*;
+pub use search::*;
+pub use stats::*;
+pub use template::*;
+pub use validate::*;
@@ -1,62 +1,479 @@
 use super::*;
+use clap::clap_derive::ArgEnum;
 use id3::TagLike;
-use itertools::{Either, Itertools};
-use std::{ffi::OsString, iter, path::PathBuf};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{
+    borrow::Cow,
+    cell::Cell,
+    collections::HashSet,
+    ffi::OsString,
+    fs,
+    io::{self, Seek},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, UNIX_EPOCH},
+};
 use walkdir::WalkDir;
 
+/// The default length in bytes above which the text of a frame is truncated.
+pub const DEFAULT_MAX_FRAME_BYTES: usize = 64 * 1024;
+
+/// The length in bytes above which the ID3v2 tag of a file isn't read, so that a malformed file declaring a huge tag
+/// can't exhaust the memory. Leaves room for large embedded pictures.
+pub const MAX_TAG_BYTES: u64 = 32 * 1024 * 1024;
+
 /// The options for listing MP3s.
 pub struct ListOptions<'a> {
-    /// The list of properties to sort by, in order of priority.
-    pub sort_by: &'a [SortBy],
-    /// Whether to reverse the order while sorting.
+    /// The list of properties to sort by and their directions, in order of priority.
+    pub sort_by: &'a [(SortBy, Direction)],
+    /// Whether to reverse the order while sorting, on top of the direction of each property.
     pub reverse: &'a bool,
     /// Whether to list subdirectories recursively.
     pub recursive: &'a bool,
+    /// The algorithm used to compute a checksum of each file, if any.
+    pub checksum: &'a Option<ChecksumAlgorithm>,
+    /// The algorithm used to compute a checksum of only the ID3v2 tag of each file, if any.
+    pub tag_hash: &'a Option<ChecksumAlgorithm>,
+    /// Whether to resolve the target path of symlinked files.
+    pub resolve_links: &'a bool,
+    /// Whether to keep implausible years (below 1 or above 9999) instead of treating them as missing.
+    pub strict_year: &'a bool,
+    /// Which year is listed when a tag has more than one.
+    pub year_policy: &'a YearPolicy,
+    /// Whether to order the results by full path and break sorting ties by file name, so that the output doesn't depend
+    /// on the order in which the filesystem returns entries.
+    pub deterministic: &'a bool,
+    /// Whether to read the sample rate and channel mode from the first audio frame of each file.
+    pub audio_properties: &'a bool,
+    /// Whether to compute the duration of the audio of each file from all its frames, which reads the whole file. Also
+    /// reads the other audio properties.
+    pub duration: &'a bool,
+    /// Whether to count the audio frames of each file and their decoded samples, which reads the whole file. Also reads
+    /// the other audio properties.
+    pub frame_count: &'a bool,
+    /// Whether to read the time each file was last modified.
+    pub modified: &'a bool,
+    /// Whether to record the files in each directory that were skipped because they couldn't be parsed as MP3s.
+    pub skipped: &'a bool,
+    /// Whether to skip reading tags and only list the name and size of files with an .mp3 extension.
+    pub no_tags: &'a bool,
+    /// The cache of previously read tags to reuse for unchanged files, if any. It is updated with the tags of new and
+    /// changed files.
+    pub cache: &'a Option<Cache>,
+    /// The length in bytes above which the text of a frame is truncated in the entry, to keep malformed files with huge
+    /// frames from flooding the output. The whole tag is still read, up to `MAX_TAG_BYTES`.
+    pub max_frame_bytes: &'a usize,
+    /// Whether to also read the ID3 chunk of WAV and AIFF files, recognized by their extension.
+    pub wav_aiff: &'a bool,
+    /// Whether to also read the ID3v1 tag and fall back to its fields where the ID3v2 tag is missing them.
+    pub merge_tags: &'a bool,
+    /// Whether to always sort entries without a value for a sort key after those with one, regardless of direction.
+    pub untagged_last: &'a bool,
+    /// Whether to read the tags of paths that are HTTP or HTTPS URLs with range requests.
+    #[cfg(feature = "remote")]
+    pub remote: &'a bool,
+    /// Whether to trim text tag values and collapse runs of whitespace within them, so that values differing only in
+    /// whitespace compare equal. Values that are only whitespace are dropped.
+    pub trim: &'a bool,
+    /// The characters that separate several values stored in a single text value, e.g. ';' for "A; B". Each value is
+    /// split on them into its parts, without the whitespace around them, which are kept if they aren't empty.
+    pub split_on: &'a [char],
+    /// The maximum number of entries to list across all paths, if any. Listing stops as soon as this many have been
+    /// found, so these are the first entries encountered rather than the first after sorting.
+    pub max_results: &'a Option<usize>,
+    /// Whether to list only the first file, by name, of the files in a directory that resolve to the same target, e.g.
+    /// several symlinks into a shared pool of files.
+    pub dedup_targets: &'a bool,
+    /// Whether to follow only the symlinks given as paths, like `find -H`, skipping the symlinks found while walking
+    /// directories.
+    pub follow_first: &'a bool,
+    /// Whether to list only the first path of the files that are hard links to the same file, across all the listed
+    /// paths.
+    #[cfg(unix)]
+    pub dedup_inodes: &'a bool,
+    /// The articles to ignore at the start of titles, artists and albums without a sort order while sorting, e.g. "the".
+    pub ignore_articles: &'a [String],
+    /// The genres to sort first when sorting by genre, in this order.
+    pub genre_priority: &'a [String],
+    /// The part of the files found in directories to list, if only a sample is listed. Files that aren't sampled are
+    /// skipped without being read.
+    pub sample: &'a Option<Sample>,
+    /// The seed that decides which files are sampled. The same seed samples the same files.
+    pub sample_seed: &'a u64,
+    /// The longest time to wait for the tags of a file to be read, if limited. A file that takes longer fails with a
+    /// timeout error, while its read carries on in the background since it can't be interrupted.
+    pub file_timeout: &'a Option<Duration>,
+}
+
+/// How many of the files found in directories are sampled.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Sample {
+    /// Roughly the given percentage of the files.
+    Percent(f64),
+    /// The given number of files with an .mp3 extension, or all of them if there are fewer.
+    Count(usize),
+}
+
+/// Which of the years of a tag with several, e.g. of a reissue, is listed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, ArgEnum)]
+pub enum YearPolicy {
+    /// The year (TYER) if present, or else the first recording date (TDRC).
+    #[default]
+    First,
+
+    /// The earliest year.
+    Earliest,
+
+    /// The latest year.
+    Latest,
+}
+
+impl YearPolicy {
+    /// Selects the year of an entry according to the policy.
+    pub fn select(&self, entry: &Entry) -> Option<i32> {
+        match self {
+            YearPolicy::First => entry.year,
+            YearPolicy::Earliest => entry.years.iter().copied().min().or(entry.year),
+            YearPolicy::Latest => entry.years.iter().copied().max().or(entry.year),
+        }
+    }
+}
+
+impl Default for ListOptions<'_> {
+    fn default() -> Self {
+        ListOptions {
+            sort_by: &[(SortBy::Name, Direction::Ascending)],
+            reverse: &false,
+            recursive: &false,
+            checksum: &None,
+            tag_hash: &None,
+            resolve_links: &false,
+            strict_year: &false,
+            year_policy: &YearPolicy::First,
+            deterministic: &false,
+            audio_properties: &false,
+            duration: &false,
+            frame_count: &false,
+            modified: &false,
+            skipped: &false,
+            no_tags: &false,
+            cache: &None,
+            merge_tags: &false,
+            max_frame_bytes: &DEFAULT_MAX_FRAME_BYTES,
+            wav_aiff: &false,
+            untagged_last: &false,
+            #[cfg(feature = "remote")]
+            remote: &false,
+            trim: &false,
+            split_on: &[],
+            max_results: &None,
+            dedup_targets: &false,
+            follow_first: &false,
+            #[cfg(unix)]
+            dedup_inodes: &false,
+            ignore_articles: &[],
+            genre_priority: &[],
+            sample: &None,
+            sample_seed: &0,
+            file_timeout: &None,
+        }
+    }
+}
+
+/// An entry found while walking a path.
+enum WalkEntry {
+    File(Box<ParsedFile>),
+    Dir(PathBuf),
+    Skipped(Skipped),
+}
+
+/// The state of a listing that is shared by all the listed paths.
+#[derive(Default)]
+struct ListState {
+    /// The number of entries left to list, if limited.
+    remaining: Option<usize>,
+    /// The device and inode of each file found so far, if hard links are deduplicated.
+    inodes: HashSet<(u64, u64)>,
+}
+
+/// A file that was successfully parsed while walking a path.
+struct ParsedFile {
+    path: PathBuf,
+    name: OsString,
+    size: u64,
+    /// The fields read from the tag, all other fields are left empty.
+    tags: Entry,
+    link_target: Option<PathBuf>,
+    /// The modification time in seconds since the Unix epoch, if requested.
+    modified: Option<u64>,
 }
 
 /// Lists MP3s for all the given paths. The paths can be either files or directories. If no paths are provided, the
 /// current working directory is used.
+#[allow(clippy::ptr_arg)]
 pub fn list(paths: &Vec<String>, options: &ListOptions) -> Result<Vec<Info>, LsError> {
-    if paths.is_empty() {
-        list_path(PathBuf::from("."), options)
+    let mut results = Vec::new();
+    list_each(paths, options, |info| results.push(info))?;
+    if *options.deterministic {
+        results.sort_by(|a, b| Path::new(&a.path).cmp(Path::new(&b.path)));
+    }
+    Ok(results)
+}
+
+/// Lists MP3s for all the given paths like `list`, but passes each result to `f` as soon as its path has been listed
+/// instead of collecting them. Results are passed in the order they are listed, even in deterministic mode.
+#[allow(clippy::ptr_arg)]
+pub fn list_each(paths: &Vec<String>, options: &ListOptions, mut f: impl FnMut(Info)) -> Result<(), LsError> {
+    list_paths(paths, options, &mut f, &mut Err)
+}
+
+/// Lists MP3s for all the given paths like `list`, but instead of stopping at the first error, collects the errors and
+/// continues with the remaining files and directories. Errors for files that can't be read or parsed, and for
+/// directories that can't be walked, are returned alongside the results. A path that doesn't exist or isn't a file or
+/// directory is still fatal and fails the whole listing.
+#[allow(clippy::ptr_arg)]
+pub fn list_lenient(paths: &Vec<String>, options: &ListOptions) -> Result<(Vec<Info>, Vec<LsError>), LsError> {
+    let (mut results, mut errors) = (Vec::new(), Vec::new());
+    list_paths(paths, options, &mut |info| results.push(info), &mut |err| {
+        errors.push(err);
+        Ok(())
+    })?;
+    if *options.deterministic {
+        results.sort_by(|a, b| Path::new(&a.path).cmp(Path::new(&b.path)));
+    }
+    Ok((results, errors))
+}
+
+/// The number and total size of the files that would be listed, as estimated without reading them.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize)]
+pub struct Estimate {
+    /// The number of candidate files.
+    pub files: usize,
+
+    /// The total size of the candidate files in bytes.
+    pub size: u64,
+}
+
+/// Estimates the work of listing the given paths by walking them the same way as `list`, but only counting the files
+/// with an .mp3 extension and adding up their sizes instead of reading them. Files given explicitly are always counted.
+#[allow(clippy::ptr_arg)]
+pub fn estimate(paths: &Vec<String>, options: &ListOptions) -> Result<Estimate, LsError> {
+    let mut estimate = Estimate::default();
+    walk_candidates(paths, options, |_, meta| {
+        estimate.files += 1;
+        estimate.size += meta.len();
+    })?;
+    Ok(estimate)
+}
+
+/// Walks the given paths the same way as `list` without reading any files, passing each file given explicitly and each
+/// file with an .mp3 extension (or a WAV or AIFF extension, if enabled) found in a directory to `f` along with its
+/// metadata.
+fn walk_candidates(
+    paths: &[String],
+    options: &ListOptions,
+    mut f: impl FnMut(&walkdir::DirEntry, &fs::Metadata),
+) -> Result<(), LsError> {
+    for path in paths.iter().map(Path::new) {
+        check_path(path)?;
+        // The root of a walk is followed even when links aren't, so only the links found inside it are skipped.
+        let walk = WalkDir::new(path).follow_links(!*options.follow_first);
+        let walk = if *options.recursive { walk } else { walk.max_depth(1) };
+        for entry in walk {
+            let entry = entry.map_err(|err| {
+                let failed = err.path().unwrap_or(path).as_os_str().to_owned();
+                LsError::IoReadError(failed, err.into())
+            })?;
+            if entry.file_type().is_file() && (entry.depth() == 0 || has_listed_extension(entry.path(), options)) {
+                let meta = entry
+                    .metadata()
+                    .map_err(|err| LsError::IoReadError(entry.path().as_os_str().to_owned(), err.into()))?;
+                f(&entry, &meta);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Lists all the given paths, passing each result to `f`. Errors that don't prevent listing the remaining paths are
+/// passed to `on_error`, which decides whether to continue by returning `Ok` or to stop by returning an error.
+#[allow(clippy::ptr_arg)]
+fn list_paths(
+    paths: &Vec<String>,
+    options: &ListOptions,
+    f: &mut dyn FnMut(Info),
+    on_error: &mut dyn FnMut(LsError) -> Result<(), LsError>,
+) -> Result<(), LsError> {
+    let mut state = ListState {
+        remaining: *options.max_results,
+        ..Default::default()
+    };
+    let default = vec![".".to_owned()];
+    let paths = if paths.is_empty() { &default } else { paths };
+    let threshold = sample_threshold(paths, options)?;
+    paths
+        .iter()
+        .try_for_each(|p| list_path(PathBuf::from(p), 0, options, f, on_error, &mut state, threshold))
+}
+
+/// Returns a hash of a path that decides whether the file is sampled, which is uniformly distributed over all `u64`.
+#[inline]
+fn sample_hash(path: &Path, seed: u64) -> u64 {
+    xxhash_rust::xxh3::xxh3_64_with_seed(path.as_os_str().as_encoded_bytes(), seed)
+}
+
+/// Returns the highest `sample_hash` of the files to sample, if sampling. For a count, the paths are walked first
+/// without reading any files, to find the hashes of all candidates.
+fn sample_threshold(paths: &[String], options: &ListOptions) -> Result<Option<u64>, LsError> {
+    match *options.sample {
+        None => Ok(None),
+        Some(Sample::Percent(percent)) => Ok(Some((u64::MAX as f64 * (percent / 100.0).clamp(0.0, 1.0)) as u64)),
+        Some(Sample::Count(0)) => Ok(Some(0)),
+        Some(Sample::Count(count)) => {
+            let mut hashes = Vec::new();
+            walk_candidates(paths, options, |entry, _| {
+                if entry.depth() > 0 {
+                    hashes.push(sample_hash(entry.path(), *options.sample_seed));
+                }
+            })?;
+            hashes.sort_unstable();
+            Ok(Some(hashes.get(count - 1).copied().unwrap_or(u64::MAX)))
+        }
+    }
+}
+
+/// Sorts entries according to the sort keys and direction of the options. In deterministic mode, entries that compare
+/// equal are ordered by file name.
+pub fn sort_entries(entries: &mut [Entry], options: &ListOptions) {
+    let config = CmpConfig {
+        ignore_articles: options.ignore_articles,
+        genre_priority: options.genre_priority,
+    };
+    let cmp = |a: &Entry, b: &Entry| {
+        let ord = if *options.untagged_last {
+            cmp_entry_untagged_last(a, b, options.sort_by, *options.reverse, &config)
+        } else {
+            cmp_entry_with(a, b, options.sort_by, &config)
+        };
+        let ord = if *options.deterministic {
+            ord.then_with(|| a.name.cmp(&b.name))
+        } else {
+            ord
+        };
+        if *options.reverse {
+            ord.reverse()
+        } else {
+            ord
+        }
+    };
+    if *options.deterministic {
+        entries.sort_by(cmp);
     } else {
-        paths
-            .iter()
-            .map(|p| list_path(PathBuf::from(p), options))
-            .collect::<Result<Vec<_>, _>>()
-            .map(|v| v.into_iter().flatten().collect())
+        entries.sort_unstable_by(cmp);
     }
 }
 
-fn list_path(path: PathBuf, options: &ListOptions) -> Result<Vec<Info>, LsError> {
-    if !path.is_dir() && !path.is_file() {
-        return Err(LsError::InvalidPath(path.into_os_string()));
+/// Lists a single path, passing the result to `f`, followed by its subdirectories if listing recursively. Stops once the
+/// number of remaining entries of the `state`, if any, have been listed. If a sample `threshold` is given, only the files in
+/// directories whose `sample_hash` is at most the threshold are listed. The entries are given the `depth` of the path
+/// below the listed one.
+fn list_path(
+    path: PathBuf,
+    depth: usize,
+    options: &ListOptions,
+    f: &mut dyn FnMut(Info),
+    on_error: &mut dyn FnMut(LsError) -> Result<(), LsError>,
+    state: &mut ListState,
+    threshold: Option<u64>,
+) -> Result<(), LsError> {
+    if state.remaining == Some(0) {
+        return Ok(());
+    }
+    #[cfg(feature = "remote")]
+    if let Some(url) = path.to_str().filter(|p| *options.remote && is_url(p)) {
+        // Only the tags are fetched, so properties that require the audio or the whole file aren't available.
+        let entry = match read_remote_entry(url, *options.max_frame_bytes) {
+            Ok(entry) => entry,
+            Err(err) => return on_error(err),
+        };
+        f(Info {
+            path: url.to_owned(),
+            path_type: PathType::File,
+            entries: vec![normalize_entry(
+                Entry {
+                    year: select_year(&entry, options),
+                    ..entry
+                },
+                options,
+            )],
+            skipped: Vec::new(),
+        });
+        if let Some(n) = &mut state.remaining {
+            *n -= 1;
+        }
+        return Ok(());
     }
+    check_path(&path)?;
 
     let (path_type, walk_entries) = if path.is_dir() {
         // If the given path is a directory, walk through it and attempt to parse all files. Assume the ones that fail
-        // to parse aren't mp3 files and skip them.
+        // to parse aren't mp3 files and skip them. The walk is lazy, so stopping at the limit skips parsing the rest.
+        let (found, limit) = (Cell::new(0), state.remaining);
+        let mut targets = HashSet::new();
         (
             PathType::Directory,
             WalkDir::new(&path)
                 .max_depth(1)
-                .follow_links(true)
+                .follow_links(!*options.follow_first)
                 .sort_by_file_name()
                 .into_iter()
+                .take_while(|_| limit.is_none_or(|n| found.get() < n))
                 .filter_map(|entry| match entry {
                     Ok(dir_entry) => {
                         let file_type = dir_entry.file_type();
-                        if file_type.is_file() {
+                        // Skip the files that aren't sampled, and the files whose target was already listed. The walk
+                        // is sorted by file name, so the first name of each target is the one kept.
+                        if file_type.is_file()
+                            && (threshold.is_some_and(|t| sample_hash(dir_entry.path(), *options.sample_seed) > t)
+                                || *options.dedup_targets
+                                    && resolve_link(dir_entry.path()).is_some_and(|target| !targets.insert(target)))
+                        {
+                            None
+                        } else if file_type.is_file() {
                             match dir_entry.metadata() {
-                                Ok(meta) => match id3::Tag::read_from_path(dir_entry.path()) {
-                                    Ok(tag) => {
-                                        Some(Ok(Either::Left((dir_entry.file_name().to_owned(), meta.len(), tag))))
+                                // Skip the hard links to a file that was already found.
+                                Ok(meta) if is_seen_inode(&meta, options, &mut state.inodes) => None,
+                                Ok(meta) => match read_tags(dir_entry.path(), &meta, options) {
+                                    Ok(tags) => {
+                                        found.set(found.get() + 1);
+                                        Some(Ok(WalkEntry::File(Box::new(ParsedFile {
+                                            path: dir_entry.path().to_owned(),
+                                            name: dir_entry.file_name().to_owned(),
+                                            size: meta.len(),
+                                            modified: modified_secs(&meta, options),
+                                            tags,
+                                            link_target: if *options.resolve_links && dir_entry.path_is_symlink() {
+                                                resolve_link(dir_entry.path())
+                                            } else {
+                                                None
+                                            },
+                                        }))))
                                     }
                                     Err(err) => match err.kind {
+                                        id3::ErrorKind::Io(err) if err.kind() == io::ErrorKind::TimedOut => {
+                                            Some(Err(LsError::Timeout(dir_entry.into_path().into_os_string())))
+                                        }
                                         id3::ErrorKind::Io(err) => {
                                             Some(Err(LsError::IoReadError(dir_entry.into_path().into_os_string(), err)))
                                         }
-                                        _ => None, // Assume it's not an mp3 file and skip.
+                                        // Assume it's not an mp3 file and skip.
+                                        _ if *options.skipped => Some(Ok(WalkEntry::Skipped(Skipped {
+                                            name: dir_entry.file_name().to_owned(),
+                                            reason: err.to_string(),
+                                        }))),
+                                        _ => None,
                                     },
                                 },
                                 Err(err) => Some(Err(LsError::IoReadError(
@@ -66,7 +483,7 @@ fn list_path(path: PathBuf, options: &ListOptions) -> Result<Vec<Info>, LsError>
                             }
                         } else if file_type.is_dir() {
                             if *options.recursive && dir_entry.path() != path {
-                                Some(Ok(Either::Right(dir_entry.into_path())))
+                                Some(Ok(WalkEntry::Dir(dir_entry.into_path())))
                             } else {
                                 None
                             }
@@ -76,70 +493,557 @@ fn list_path(path: PathBuf, options: &ListOptions) -> Result<Vec<Info>, LsError>
                     }
                     Err(err) => Some(Err(LsError::IoReadError(path.as_os_str().to_owned(), err.into()))),
                 })
-                .collect::<Result<Vec<_>, _>>()?,
+                .collect::<Vec<_>>(),
         )
     } else {
-        // If the given path is a file, attempt to parse the file as an mp3.
+        // If the given path is a file, attempt to parse the file as an mp3, unless it's a hard link to a file that was
+        // already found.
+        if path
+            .metadata()
+            .is_ok_and(|meta| is_seen_inode(&meta, options, &mut state.inodes))
+        {
+            return Ok(());
+        }
         (
             PathType::File,
-            vec![Either::Left((
-                OsString::from(path.file_name().unwrap_or_default()),
-                path.metadata()
-                    .map_err(|err| LsError::IoReadError(path.as_os_str().to_owned(), err))?
-                    .len(),
-                id3::Tag::read_from_path(&path).map_err(|err| LsError::Id3Error(path.as_os_str().to_owned(), err))?,
-            ))],
+            vec![read_file(&path, options).map(|file| WalkEntry::File(Box::new(file)))],
         )
     };
+    let walk_entries = walk_entries
+        .into_iter()
+        .filter_map(|entry| entry.map(Some).or_else(|err| on_error(err).map(|_| None)).transpose())
+        .collect::<Result<Vec<_>, _>>()?;
+    if path_type == PathType::File && walk_entries.is_empty() {
+        return Ok(());
+    }
 
-    let (files, mut subdirs): (Vec<_>, Vec<_>) = walk_entries.into_iter().partition_map(|entry| entry);
+    let (mut files, mut subdirs, mut skipped) = (Vec::new(), Vec::new(), Vec::new());
+    for entry in walk_entries {
+        match entry {
+            WalkEntry::File(file) => files.push(*file),
+            WalkEntry::Dir(dir) => subdirs.push(dir),
+            WalkEntry::Skipped(s) => skipped.push(s),
+        }
+    }
     subdirs.sort_unstable();
+    if let Some(n) = &mut state.remaining {
+        *n -= files.len();
+    }
+
+    // Hashing reads every file in full, so only do it when requested and spread the work across threads.
+    let checksums = match options.checksum {
+        Some(algorithm) => files
+            .par_iter()
+            .map(|file| {
+                checksum_file(&file.path, *algorithm)
+                    .map(Some)
+                    .map_err(|err| LsError::IoReadError(file.path.as_os_str().to_owned(), err))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|checksum| checksum.or_else(|err| on_error(err).map(|_| None)))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => vec![None; files.len()],
+    };
+    let tag_hashes = match options.tag_hash {
+        Some(algorithm) => files
+            .par_iter()
+            .map(|file| {
+                checksum_tag(&file.path, *algorithm)
+                    .map_err(|err| LsError::IoReadError(file.path.as_os_str().to_owned(), err))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|tag_hash| tag_hash.or_else(|err| on_error(err).map(|_| None)))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => vec![None; files.len()],
+    };
+    let walk = *options.duration || *options.frame_count;
+    let audio = if *options.audio_properties || walk {
+        files
+            .par_iter()
+            .map(|file| {
+                read_audio_properties(&file.path, walk)
+                    .map_err(|err| LsError::IoReadError(file.path.as_os_str().to_owned(), err))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|audio| audio.or_else(|err| on_error(err).map(|_| None)))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        vec![None; files.len()]
+    };
 
     let mut entries: Vec<_> = files
         .into_iter()
-        .map(|file| Entry {
-            name: file.0,
-            size: file.1,
-            title: tag_string_values(&file.2, "TIT2"),
-            title_sort_order: tag_option_string_values(&file.2, "TSOT"),
-            artist: tag_string_values(&file.2, "TPE1"),
-            artist_sort_order: tag_option_string_values(&file.2, "TSOP"),
-            album: tag_string_values(&file.2, "TALB"),
-            album_sort_order: tag_option_string_values(&file.2, "TSOA"),
-            genre: tag_string_values(&file.2, "TCON"),
-            year: file.2.year().or_else(|| file.2.date_recorded().map(|d| d.year)),
-            track: Track {
-                number: file.2.track(),
-                total: file.2.total_tracks(),
-            },
+        .zip(checksums)
+        .zip(tag_hashes)
+        .zip(audio)
+        .map(|(((file, checksum), tag_hash), audio)| {
+            normalize_entry(
+                Entry {
+                    name: file.name,
+                    size: file.size,
+                    modified: file.modified,
+                    year: select_year(&file.tags, options),
+                    sample_rate: audio.map(|a| a.sample_rate),
+                    channel_mode: audio.map(|a| a.channel_mode),
+                    duration_ms: audio.and_then(|a| a.duration_ms).filter(|_| *options.duration),
+                    frame_count: audio.and_then(|a| a.frame_count).filter(|_| *options.frame_count),
+                    sample_count: audio.and_then(|a| a.sample_count).filter(|_| *options.frame_count),
+                    checksum,
+                    tag_hash,
+                    link_target: file.link_target.map(|p| p.to_string_lossy().to_string()),
+                    depth,
+                    ..file.tags
+                },
+                options,
+            )
         })
         .collect();
-    entries.sort_unstable_by(|a, b| {
-        let ord = cmp_entry(a, b, options.sort_by);
-        if *options.reverse {
-            ord.reverse()
-        } else {
-            ord
-        }
-    });
+    sort_entries(&mut entries, options);
 
-    iter::once(Ok(vec![Info {
+    f(Info {
         path: path.to_string_lossy().to_string(),
         path_type,
         entries,
-    }]))
-    .chain(subdirs.into_iter().map(|p| list_path(p, options)))
-    .collect::<Result<Vec<_>, _>>()
-    .map(|v| v.into_iter().flatten().collect())
+        skipped,
+    });
+    subdirs
+        .into_iter()
+        .try_for_each(|p| list_path(p, depth + 1, options, f, on_error, state, threshold))
+}
+
+/// Returns whether a file is a hard link to a file that was already found, recording its inode otherwise. Always false
+/// unless hard links are deduplicated.
+#[cfg(unix)]
+fn is_seen_inode(meta: &fs::Metadata, options: &ListOptions, inodes: &mut HashSet<(u64, u64)>) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    *options.dedup_inodes && !inodes.insert((meta.dev(), meta.ino()))
+}
+
+/// Hard links can't be detected on this platform, so no file is deduplicated.
+#[cfg(not(unix))]
+fn is_seen_inode(_meta: &fs::Metadata, _options: &ListOptions, _inodes: &mut HashSet<(u64, u64)>) -> bool {
+    false
+}
+
+/// Selects the year of an entry to list according to the year policy, treating implausible years as missing unless
+/// they are kept by the options.
+fn select_year(entry: &Entry, options: &ListOptions) -> Option<i32> {
+    options
+        .year_policy
+        .select(entry)
+        .filter(|y| *options.strict_year || is_valid_year(*y))
+}
+
+/// Applies the normalizations of the options to the text values of an entry. Tags are cached as read, so this is done
+/// after reading them.
+fn normalize_entry(mut entry: Entry, options: &ListOptions) -> Entry {
+    if !options.split_on.is_empty() {
+        for values in entry.text_values_mut() {
+            *values = values
+                .iter()
+                .flat_map(|v| v.split(options.split_on))
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_owned)
+                .collect();
+        }
+    }
+    if *options.trim {
+        for values in entry.text_values_mut() {
+            *values = values
+                .iter()
+                .map(|v| v.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|v| !v.is_empty())
+                .collect();
+        }
+    }
+    entry
+}
+
+/// Checks that a path to list is a file or directory, following symlinks. Paths that exist but are something else, like
+/// a FIFO or a device, are reported separately from paths that don't exist.
+fn check_path(path: &Path) -> Result<(), LsError> {
+    match fs::metadata(path) {
+        Ok(meta) if meta.is_file() || meta.is_dir() => Ok(()),
+        Ok(meta) => Err(LsError::UnsupportedFileType(
+            path.as_os_str().to_owned(),
+            meta.file_type(),
+        )),
+        Err(_) => Err(LsError::InvalidPath(path.as_os_str().to_owned())),
+    }
+}
+
+/// Reads a single file given as a path to list.
+fn read_file(path: &Path, options: &ListOptions) -> Result<ParsedFile, LsError> {
+    let meta = path
+        .metadata()
+        .map_err(|err| LsError::IoReadError(path.as_os_str().to_owned(), err))?;
+    Ok(ParsedFile {
+        path: path.to_owned(),
+        name: OsString::from(path.file_name().unwrap_or_default()),
+        size: meta.len(),
+        modified: modified_secs(&meta, options),
+        // Without tags, a file given explicitly is listed regardless of its extension.
+        tags: if *options.no_tags {
+            Entry {
+                format: AudioFormat::from_extension(path).unwrap_or_default(),
+                ..Default::default()
+            }
+        } else {
+            read_tags(path, &meta, options).map_err(|err| match &err.kind {
+                id3::ErrorKind::Io(io_err) if io_err.kind() == io::ErrorKind::TimedOut => {
+                    LsError::Timeout(path.as_os_str().to_owned())
+                }
+                id3::ErrorKind::NoTag => LsError::NotAnMp3(path.as_os_str().to_owned()),
+                _ => LsError::Id3Error(path.as_os_str().to_owned(), err),
+            })?
+        },
+        link_target: if *options.resolve_links && path.is_symlink() {
+            resolve_link(path)
+        } else {
+            None
+        },
+    })
+}
+
+/// Returns the time a file was last modified in seconds since the Unix epoch, if requested and available.
+fn modified_secs(meta: &fs::Metadata, options: &ListOptions) -> Option<u64> {
+    if !*options.modified {
+        return None;
+    }
+    let since_epoch = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_secs())
+}
+
+/// Reads the fields of an entry from the tag of a file, or from the cache if the file is unchanged since it was cached.
+fn read_tags(path: &Path, meta: &fs::Metadata, options: &ListOptions) -> id3::Result<Entry> {
+    let cache = options.cache.as_ref().filter(|_| !*options.no_tags);
+    let settings = ReadSettings {
+        merged: *options.merge_tags,
+        wav_aiff: *options.wav_aiff,
+        max_frame_bytes: *options.max_frame_bytes,
+    };
+    if let Some(entry) = cache.and_then(|c| c.get(path, meta, settings)) {
+        return Ok(entry);
+    }
+    let entry = match options.file_timeout {
+        Some(timeout) => read_tags_with_timeout(path, options, *timeout)?,
+        None => read_uncached_tags(path, options)?,
+    };
+    if let Some(cache) = cache {
+        cache.insert(path, meta, settings, &entry);
+    }
+    Ok(entry)
+}
+
+/// Reads the fields of an entry from the tags of a file, without the cache.
+fn read_uncached_tags(path: &Path, options: &ListOptions) -> id3::Result<Entry> {
+    if *options.merge_tags && !*options.no_tags {
+        read_merged_tags(path, options)
+    } else {
+        read_tag(path, options)
+    }
+}
+
+/// Reads the tags of a file like `read_uncached_tags` on another thread, failing with a `TimedOut` IO error if that
+/// takes longer than `timeout`. A read that hangs can't be stopped, so it's left running in the background. Only the
+/// options that affect reading tags are passed to the thread.
+fn read_tags_with_timeout(path: &Path, options: &ListOptions, timeout: Duration) -> id3::Result<Entry> {
+    let (sender, receiver) = mpsc::channel();
+    let path = path.to_owned();
+    let (no_tags, merge_tags, wav_aiff, max_frame_bytes) = (
+        *options.no_tags,
+        *options.merge_tags,
+        *options.wav_aiff,
+        *options.max_frame_bytes,
+    );
+    thread::spawn(move || {
+        let options = ListOptions {
+            no_tags: &no_tags,
+            merge_tags: &merge_tags,
+            wav_aiff: &wav_aiff,
+            max_frame_bytes: &max_frame_bytes,
+            ..Default::default()
+        };
+        // The receiver is gone if the read timed out, in which case the result is dropped.
+        _ = sender.send(read_uncached_tags(&path, &options));
+    });
+    receiver
+        .recv_timeout(timeout)
+        .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "reading the tags timed out").into()))
+}
+
+/// Reads both the ID3v2 and the ID3v1 tag of a file. Each field is read from the ID3v2 tag, falling back to the ID3v1
+/// tag where it's missing. Fails if the file has neither tag, or if the ID3v2 tag can't be read for any other reason
+/// than being missing, e.g. an IO error or a WAV or AIFF file that isn't listed.
+fn read_merged_tags(path: &Path, options: &ListOptions) -> id3::Result<Entry> {
+    let mut file = fs::File::open(path)?;
+    let format = listed_chunked_audio_format(&mut file, options)?;
+    let v1 = || id3::v1::Tag::read_from_path(path).map(|v1| entry_from_tag(&v1.into(), *options.max_frame_bytes));
+    match read_v2_tag_from(file, format, options) {
+        Ok(v2) => Ok(match v1() {
+            Ok(v1) => merge_entries(v2, v1),
+            Err(_) => v2,
+        }),
+        // Only a missing ID3v2 tag falls back to the ID3v1 tag.
+        Err(err) if matches!(err.kind, id3::ErrorKind::NoTag) => v1().map_err(|_| err),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns the fields of an entry read from a tag, all other fields are left empty. Text frames longer than
+/// `max_frame_bytes` are truncated.
+pub(crate) fn entry_from_tag(tag: &id3::Tag, max_frame_bytes: usize) -> Entry {
+    let values = |frame_id| tag_string_values(tag, frame_id, max_frame_bytes);
+    let option_values = |frame_id| tag_option_string_values(tag, frame_id, max_frame_bytes);
+    Entry {
+        title: values("TIT2"),
+        title_sort_order: option_values("TSOT"),
+        artist: values("TPE1"),
+        artist_sort_order: option_values("TSOP"),
+        album: values("TALB"),
+        album_sort_order: option_values("TSOA"),
+        genre: values("TCON"),
+        original_artist: values("TOPE"),
+        original_album: values("TOAL"),
+        conductor: values("TPE3"),
+        remixer: values("TPE4"),
+        isrc: values("TSRC"),
+        publisher: values("TPUB"),
+        copyright: values("TCOP"),
+        compilation: tag
+            .get("TCMP")
+            .and_then(|frame| frame.content().text())
+            .is_some_and(|text| text.trim() == "1"),
+        year: tag.year().or_else(|| tag.date_recorded().map(|d| d.year)),
+        years: Some(tag_years(tag)).filter(|years| years.len() > 1).unwrap_or_default(),
+        track: Track {
+            number: tag.track(),
+            total: tag.total_tracks(),
+        },
+        disc: tag.disc(),
+        chapter_count: Some(tag.chapters().count()).filter(|n| *n > 0),
+        truncated_frames: tag
+            .frames()
+            .filter(|frame| frame.content().text().is_some_and(|text| text.len() > max_frame_bytes))
+            .map(|frame| frame.id().to_owned())
+            .collect(),
+        tagged_length_ms: tag
+            .get("TLEN")
+            .and_then(|frame| frame.content().text())
+            .and_then(|text| text.trim().parse().ok()),
+        replaygain_track_peak: tag
+            .extended_texts()
+            .find(|text| text.description.eq_ignore_ascii_case("REPLAYGAIN_TRACK_PEAK"))
+            .map(|text| text.value.trim().to_owned()),
+        ..Default::default()
+    }
+}
+
+/// Merges the tag fields of two entries, taking each field from `primary` unless it's missing there.
+fn merge_entries(primary: Entry, fallback: Entry) -> Entry {
+    fn or(a: Vec<String>, b: Vec<String>) -> Vec<String> {
+        if a.is_empty() {
+            b
+        } else {
+            a
+        }
+    }
+    Entry {
+        title: or(primary.title, fallback.title),
+        title_sort_order: primary.title_sort_order.or(fallback.title_sort_order),
+        artist: or(primary.artist, fallback.artist),
+        artist_sort_order: primary.artist_sort_order.or(fallback.artist_sort_order),
+        album: or(primary.album, fallback.album),
+        album_sort_order: primary.album_sort_order.or(fallback.album_sort_order),
+        genre: or(primary.genre, fallback.genre),
+        original_artist: or(primary.original_artist, fallback.original_artist),
+        original_album: or(primary.original_album, fallback.original_album),
+        conductor: or(primary.conductor, fallback.conductor),
+        remixer: or(primary.remixer, fallback.remixer),
+        isrc: or(primary.isrc, fallback.isrc),
+        publisher: or(primary.publisher, fallback.publisher),
+        copyright: or(primary.copyright, fallback.copyright),
+        compilation: primary.compilation || fallback.compilation,
+        invalid_text_encoding: primary.invalid_text_encoding,
+        year: primary.year.or(fallback.year),
+        years: primary.years,
+        track: if primary.track.number.is_some() {
+            primary.track
+        } else {
+            fallback.track
+        },
+        disc: primary.disc.or(fallback.disc),
+        chapter_count: primary.chapter_count,
+        truncated_frames: primary.truncated_frames,
+        tagged_length_ms: primary.tagged_length_ms.or(fallback.tagged_length_ms),
+        replaygain_track_peak: primary.replaygain_track_peak.or(fallback.replaygain_track_peak),
+        format: primary.format,
+        ..Default::default()
+    }
+}
+
+/// Reads the ID3v2 tag of a file. WAV and AIFF files, recognized by their header, are only read if enabled, from their
+/// ID3 chunk.
+fn read_v2_tag(path: &Path, options: &ListOptions) -> id3::Result<Entry> {
+    let mut file = fs::File::open(path)?;
+    let format = listed_chunked_audio_format(&mut file, options)?;
+    read_v2_tag_from(file, format, options)
+}
+
+/// Returns the format of a WAV or AIFF file, or `None` for other files. Fails if the file is a WAV or AIFF file but
+/// those aren't listed.
+fn listed_chunked_audio_format(file: &mut fs::File, options: &ListOptions) -> id3::Result<Option<AudioFormat>> {
+    let format = chunked_audio_format(file)?;
+    if !*options.wav_aiff && format.is_some() {
+        return Err(id3::Error::new(
+            id3::ErrorKind::NoTag,
+            "WAV and AIFF files aren't listed",
+        ));
+    }
+    Ok(format)
+}
+
+/// Reads the ID3v2 tag of an opened file of the format. Text that isn't valid in the encoding declared by its frame,
+/// e.g. mislabeled UTF-8, can't be decoded and stops the tag from being read. In that case the frames read up to that
+/// point are kept and the entry is flagged. Fails with an `InvalidData` IO error if the tag declares a length above
+/// `MAX_TAG_BYTES`.
+fn read_v2_tag_from(mut file: fs::File, format: Option<AudioFormat>, options: &ListOptions) -> id3::Result<Entry> {
+    if format.is_none() {
+        let len = read_id3v2_len(&mut file)?.unwrap_or(0);
+        if len > MAX_TAG_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the ID3v2 tag declares {} bytes, more than the limit of {}",
+                    len, MAX_TAG_BYTES
+                ),
+            )
+            .into());
+        }
+        file.rewind()?;
+    }
+    let entry = match id3::Tag::read_from2(file) {
+        Ok(tag) => entry_from_tag(&tag, *options.max_frame_bytes),
+        Err(id3::Error {
+            kind: id3::ErrorKind::StringDecoding(_),
+            partial_tag: Some(tag),
+            ..
+        }) => Entry {
+            invalid_text_encoding: true,
+            ..entry_from_tag(&tag, *options.max_frame_bytes)
+        },
+        Err(err) => return Err(err),
+    };
+    Ok(Entry {
+        format: format.unwrap_or_default(),
+        ..entry
+    })
+}
+
+/// Reads the tag of a file found while walking a directory. Without tags, files are recognized by their extension
+/// instead and an empty entry is returned.
+fn read_tag(path: &Path, options: &ListOptions) -> id3::Result<Entry> {
+    if !*options.no_tags {
+        read_v2_tag(path, options)
+    } else if has_listed_extension(path, options) {
+        Ok(Entry {
+            format: AudioFormat::from_extension(path).unwrap_or_default(),
+            ..Default::default()
+        })
+    } else {
+        Err(id3::Error::new(id3::ErrorKind::NoTag, "not an .mp3 file"))
+    }
+}
+
+/// Returns whether a file has an extension of the files that are listed, i.e. .mp3 or, if enabled, the extension of a
+/// WAV or AIFF file, in any case.
+fn has_listed_extension(path: &Path, options: &ListOptions) -> bool {
+    has_mp3_extension(path)
+        || *options.wav_aiff
+            && path.extension().is_some_and(|e| {
+                ["wav", "wave", "aif", "aiff", "aifc"]
+                    .iter()
+                    .any(|c| e.eq_ignore_ascii_case(c))
+            })
+}
+
+/// Returns whether a file has an .mp3 extension, in any case.
+#[inline]
+fn has_mp3_extension(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("mp3"))
+}
+
+/// Resolves the final target of a symlink, following any intermediate links. Broken links resolve to `None`.
+#[inline]
+fn resolve_link(path: &Path) -> Option<PathBuf> {
+    fs::canonicalize(path).ok()
+}
+
+/// Returns the years of all the values of the year (TYER) and recording date (TDRC) frames of a tag, in that order.
+fn tag_years(tag: &id3::Tag) -> Vec<i32> {
+    ["TYER", "TDRC"]
+        .into_iter()
+        .flat_map(|id| tag.text_values_for_frame_id(id).unwrap_or_default())
+        .filter_map(|value| value.trim().parse::<id3::Timestamp>().ok())
+        .map(|timestamp| timestamp.year)
+        .collect()
 }
 
 #[inline]
-fn tag_string_values(tag: &id3::Tag, frame_id: &str) -> Vec<String> {
-    tag_option_string_values(tag, frame_id).unwrap_or_default()
+fn tag_string_values(tag: &id3::Tag, frame_id: &str, max_bytes: usize) -> Vec<String> {
+    tag_option_string_values(tag, frame_id, max_bytes).unwrap_or_default()
 }
 
+/// Returns the values of a text frame. If the text of the frame is longer than `max_bytes`, the values after the limit
+/// are dropped and the value it falls in is cut short and marked with an ellipsis. Values of ID3v2.2 and ID3v2.3 tags
+/// that hold several values are split with `split_legacy_value`. Stray byte order marks and other invisible characters at the start of each
+/// value are removed.
 #[inline]
-fn tag_option_string_values(tag: &id3::Tag, frame_id: &str) -> Option<Vec<String>> {
-    tag.text_values_for_frame_id(frame_id)
-        .map(|v| v.into_iter().filter(|s| !s.is_empty()).map(|s| s.to_string()).collect())
+fn tag_option_string_values(tag: &id3::Tag, frame_id: &str, max_bytes: usize) -> Option<Vec<String>> {
+    tag.text_values_for_frame_id(frame_id).map(|v| {
+        let mut remaining = max_bytes;
+        let mut truncated = Vec::with_capacity(v.len());
+        for s in v {
+            if s.len() <= remaining {
+                remaining -= s.len();
+                truncated.push(Cow::Borrowed(s));
+            } else {
+                let end = (0..=remaining).rev().find(|i| s.is_char_boundary(*i)).unwrap_or(0);
+                truncated.push(Cow::Owned(format!("{}…", &s[..end])));
+                break;
+            }
+        }
+        truncated
+            .iter()
+            .flat_map(|s| split_legacy_value(tag.version(), frame_id, s))
+            .map(|s| s.trim_start_matches(is_invisible_prefix))
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect()
+    })
+}
+
+/// Splits a value of a text frame on "/", which ID3v2.2 and ID3v2.3 use to separate multiple values in the frames of
+/// people and genres. Newer versions of the id3 crate only split on it for some of those frames, so this splits all of
+/// them regardless of the crate version. Values of other frames, like titles, may contain a "/" and are kept whole, as
+/// are values of ID3v2.4 tags, which separate them with a null character instead.
+fn split_legacy_value<'a>(version: id3::Version, frame_id: &str, value: &'a str) -> Vec<&'a str> {
+    let multi_value = matches!(
+        frame_id,
+        "TPE1" | "TPE2" | "TPE3" | "TPE4" | "TCOM" | "TEXT" | "TOPE" | "TCON"
+    );
+    match version {
+        id3::Version::Id3v22 | id3::Version::Id3v23 if multi_value => value.split('/').collect(),
+        _ => vec![value],
+    }
+}
+
+/// Returns whether a character is invisible and can be left at the start of a value by a tagger, like a byte order mark
+/// that was decoded as text, a zero-width character or a control character.
+fn is_invisible_prefix(c: char) -> bool {
+    matches!(c, '\u{feff}' | '\u{fffe}' | '\u{200b}'..='\u{200d}' | '\u{2060}') || c.is_control()
 }
@@ -5,164 +5,2941 @@
 //! Works similar to `ls`, but ignores all files that are not MP3s with valid ID3 tags. Various options are provided for
 //! sorting. In addition to a human readable table format, JSON output is also supported.
 
-use clap::{clap_derive::ArgEnum, CommandFactory, Parser, ValueHint};
+use clap::{clap_derive::ArgEnum, ArgGroup, CommandFactory, Parser, Subcommand, ValueEnum, ValueHint};
+use encoding_rs::Encoding;
 use serde_json::{json, Value};
-use std::{error::Error, io::Write};
-use tabled::Table;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    ffi::OsString,
+    fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tabled::{builder::Builder, Tabled};
 
 #[inline]
 fn capitalize_first_letter(s: &str) -> String {
     s[0..1].to_uppercase() + &s[1..]
 }
 
-fn error(err: impl Error) -> ! {
-    _ = Args::command()
-        .error(clap::ErrorKind::Io, capitalize_first_letter(&err.to_string()))
-        .print();
+fn error(err: impl Error + 'static) -> ! {
+    if ERRORS_JSON.load(Ordering::Relaxed) {
+        print_error_json(&err);
+    } else {
+        _ = Cli::command()
+            .error(clap::ErrorKind::Io, capitalize_first_letter(&err.to_string()))
+            .print();
+    }
     _ = std::io::stdout().lock().flush();
     _ = std::io::stderr().lock().flush();
     std::process::exit(1)
 }
 
+/// Prints an error that doesn't stop the run to stderr, as JSON if `--errors-json` is set.
+fn print_error(err: &(dyn Error + 'static)) {
+    if ERRORS_JSON.load(Ordering::Relaxed) {
+        print_error_json(err);
+    } else {
+        eprintln!("error: {}", capitalize_first_letter(&err.to_string()));
+    }
+}
+
+/// Whether errors are printed to stderr as JSON objects instead of text, as set by `--errors-json`.
+static ERRORS_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Prints an error to stderr as a JSON object on one line, with the message, the kind of error and the path it's about.
+/// Errors that don't come from listing have the kind "other" and no path.
+fn print_error_json(err: &(dyn Error + 'static)) {
+    let (kind, path) = match err.downcast_ref::<lsmp3::LsError>() {
+        Some(err) => (err.kind(), err.path().map(|p| p.to_string_lossy())),
+        None => ("other", None),
+    };
+    eprintln!(
+        "{}",
+        json!({
+            "error": capitalize_first_letter(&err.to_string()),
+            "path": path,
+            "kind": kind,
+        })
+    );
+}
+
+/// The exit status used by `--fail-if-empty` when no files were listed. Distinct from the status of errors (1) and of
+/// invalid arguments (2).
+const EXIT_EMPTY: i32 = 3;
+
+fn warning(msg: impl AsRef<str>) {
+    eprintln!("warning: {}", msg.as_ref());
+}
+
+/// Prints a warning for each validation issue in the result that was requested to be reported, and for each entry whose
+/// frames were truncated.
+fn warn_issues(info: &lsmp3::Info, args: &Args) {
+    let truncated = info
+        .entry_paths()
+        .zip(&info.entries)
+        .filter(|(_, e)| !e.truncated_frames.is_empty());
+    for (path, entry) in truncated {
+        warning(format!(
+            "{:?}: truncated the text of {} to {} bytes",
+            path,
+            entry.truncated_frames.join(", "),
+            args.max_frame_bytes
+        ));
+    }
+    if args.strict_year && !args.validate {
+        for (path, entry) in info.entry_paths().zip(&info.entries) {
+            for issue in lsmp3::validate_entry(entry) {
+                if let lsmp3::Issue::InvalidYear(_) = issue {
+                    warning(format!("{:?}: {}", path, issue));
+                }
+            }
+        }
+    }
+}
+
+/// Validates all entries of the results, returning the path of each entry along with its issues.
+fn validate_results(results: &[lsmp3::Info]) -> Vec<(PathBuf, lsmp3::Issue)> {
+    results
+        .iter()
+        .flat_map(|info| {
+            info.entry_paths().zip(&info.entries).flat_map(|(path, entry)| {
+                lsmp3::validate_entry(entry)
+                    .into_iter()
+                    .map(move |issue| (path.clone(), issue))
+            })
+        })
+        .collect()
+}
+
+/// Formats the frame encodings of each file as one line per file, listing each frame id along with its encoding, e.g.
+/// "a.mp3: TIT2=utf-8 TPE1=latin1". Frames that can't be read are shown with a "?".
+fn encodings_to_text(files: &[(PathBuf, Vec<lsmp3::FrameEncoding>)]) -> String {
+    files
+        .iter()
+        .map(|(path, frames)| {
+            let frames: String = frames
+                .iter()
+                .map(|frame| match frame.encoding {
+                    Some(encoding) => format!(" {}={}", frame.id, encoding),
+                    None => format!(" {}=?", frame.id),
+                })
+                .collect();
+            format!("{}:{}\n", path.to_string_lossy(), frames)
+        })
+        .collect()
+}
+
+/// Parses a sort key with an optional direction suffix, e.g. "year" or "track:desc". The direction is left unset if
+/// there's no suffix, so that it can be defaulted by `sort_keys`.
+fn parse_sort_key(s: &str) -> Result<(lsmp3::SortBy, Option<lsmp3::Direction>), String> {
+    let (key, direction) = match s.rsplit_once(':') {
+        Some((key, direction)) => (
+            key,
+            match direction.to_ascii_lowercase().as_str() {
+                "asc" => Some(lsmp3::Direction::Ascending),
+                "desc" => Some(lsmp3::Direction::Descending),
+                _ => return Err(format!("invalid direction {:?}, expected asc or desc", direction)),
+            },
+        ),
+        None => (s, None),
+    };
+    let sort_by = lsmp3::SortBy::from_str(key, true).map_err(|_| {
+        format!(
+            "invalid sort key {:?}, expected one of: {}",
+            key,
+            value_names::<lsmp3::SortBy>().join(", ")
+        )
+    })?;
+    Ok((sort_by, direction))
+}
+
+/// Returns the names of the values of an argument enum, as accepted on the command line.
+fn value_names<T: ValueEnum>() -> Vec<&'static str> {
+    T::value_variants()
+        .iter()
+        .filter_map(|v| v.to_possible_value())
+        .map(|v| v.get_name())
+        .collect()
+}
+
+/// The keys that "all" expands to, in order of priority.
+const ALL_SORT_KEYS: [lsmp3::SortBy; 6] = [
+    lsmp3::SortBy::Artist,
+    lsmp3::SortBy::Year,
+    lsmp3::SortBy::Album,
+    lsmp3::SortBy::Track,
+    lsmp3::SortBy::Title,
+    lsmp3::SortBy::Name,
+];
+
+/// Parses a comma separated list of sort keys like `parse_sort_key`, e.g. "album,track:desc", additionally accepting
+/// "all" as a shorthand for a full chain of keys, e.g. "all" or "all:desc".
+fn parse_sort_keys(s: &str) -> Result<Vec<(lsmp3::SortBy, Option<lsmp3::Direction>)>, String> {
+    let mut keys = Vec::new();
+    for s in s.split(',').map(str::trim) {
+        let key = s.rsplit_once(':').map_or(s, |(key, _)| key);
+        if s.is_empty() {
+            return Err("empty sort key in the list".to_owned());
+        } else if key.eq_ignore_ascii_case("all") {
+            // Parse the direction suffix, if any, along with a placeholder key.
+            let (_, direction) = parse_sort_key(&format!("name{}", &s[key.len()..]))?;
+            keys.extend(ALL_SORT_KEYS.iter().map(|k| (*k, direction)));
+        } else {
+            keys.push(parse_sort_key(s)?);
+        }
+    }
+    Ok(keys)
+}
+
+/// Removes repeated sort keys, keeping the first occurrence of each. Later occurrences could never affect the order.
+fn dedup_sort_keys<T>(keys: &mut Vec<(lsmp3::SortBy, T)>) {
+    let mut seen = BTreeSet::new();
+    keys.retain(|(key, _)| seen.insert(*key));
+}
+
+/// A named sequence of sort keys.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+enum Preset {
+    /// Year, album, track
+    Chronological,
+    /// Artist, album, title
+    Alphabetical,
+    /// Artist, year, album, track
+    Discography,
+}
+
+impl Preset {
+    fn sort_keys(&self) -> Vec<(lsmp3::SortBy, Option<lsmp3::Direction>)> {
+        use lsmp3::SortBy::*;
+        let keys: &[lsmp3::SortBy] = match self {
+            Preset::Chronological => &[Year, Album, Track],
+            Preset::Alphabetical => &[Artist, Album, Title],
+            Preset::Discography => &[Artist, Year, Album, Track],
+        };
+        keys.iter().map(|k| (*k, Some(lsmp3::Direction::Ascending))).collect()
+    }
+}
+
+/// Returns the sort keys to use for the arguments. The keys given with `--sort` come first, followed by the keys of the
+/// preset that weren't given explicitly. Without either, the smart sort keys are used if requested, or else the default
+/// sort keys. Repeated keys are removed. Keys without a direction are sorted ascending, or in their default direction
+/// with `--smart-direction`.
+fn sort_keys(args: &Args) -> Vec<(lsmp3::SortBy, lsmp3::Direction)> {
+    let mut keys: Vec<_> = args.sort.iter().flatten().copied().collect();
+    match args.preset {
+        Some(preset) => keys.extend(preset.sort_keys()),
+        // Sorting by track also sorts by disc, if both files have one.
+        None if keys.is_empty() && args.smart_sort => {
+            keys = vec![
+                (lsmp3::SortBy::Track, Some(lsmp3::Direction::Ascending)),
+                (lsmp3::SortBy::Name, Some(lsmp3::Direction::Ascending)),
+            ]
+        }
+        None if keys.is_empty() => keys = default_sort_keys(),
+        None => {}
+    }
+    dedup_sort_keys(&mut keys);
+    keys.into_iter()
+        .map(|(key, direction)| {
+            let default = if args.smart_direction {
+                key.default_direction()
+            } else {
+                lsmp3::Direction::Ascending
+            };
+            (key, direction.unwrap_or(default))
+        })
+        .collect()
+}
+
+/// Returns the format to use when none is given as an argument, which is read from the `LSMP3_FORMAT` environment
+/// variable if set.
+fn default_format() -> Format {
+    match std::env::var("LSMP3_FORMAT") {
+        Ok(format) if !format.trim().is_empty() => Format::from_str(format.trim(), true).unwrap_or_else(|err| {
+            Cli::command()
+                .error(clap::ErrorKind::InvalidValue, format!("Invalid LSMP3_FORMAT: {}", err))
+                .exit()
+        }),
+        _ => Format::Table,
+    }
+}
+
+/// Returns the sort keys to use when none are given as arguments, which are read from the `LSMP3_SORT` environment
+/// variable if set.
+fn default_sort_keys() -> Vec<(lsmp3::SortBy, Option<lsmp3::Direction>)> {
+    match std::env::var("LSMP3_SORT") {
+        Ok(keys) if !keys.trim().is_empty() => keys
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|k| !k.is_empty())
+            .map(parse_sort_keys)
+            .collect::<Result<Vec<_>, _>>()
+            .map(|keys| keys.concat())
+            .unwrap_or_else(|err| {
+                Cli::command()
+                    .error(clap::ErrorKind::InvalidValue, format!("Invalid LSMP3_SORT: {}", err))
+                    .exit()
+            }),
+        _ => vec![(lsmp3::SortBy::Name, Some(lsmp3::Direction::Ascending))],
+    }
+}
+
+/// Removes the entries of the result that don't match all filters.
+fn apply_filters(info: &mut lsmp3::Info, filters: &[lsmp3::Predicate]) {
+    info.entries = lsmp3::filter_entries(std::mem::take(&mut info.entries), filters);
+}
+
+/// Removes the entries that don't fuzzily match the search query, if any.
+fn apply_search(info: &mut lsmp3::Info, search: &Option<String>) {
+    if let Some(query) = search {
+        info.entries.retain(|e| lsmp3::fuzzy_score(query, e).is_some());
+    }
+}
+
+/// Orders entries by how well they match the search query, from the best to the worst match. Entries that match equally
+/// well keep their sorted order.
+fn rank_entries(entries: &mut [lsmp3::Entry], query: &str) {
+    entries.sort_by_cached_key(|e| std::cmp::Reverse(lsmp3::fuzzy_score(query, e)));
+}
+
+/// A field used to group entries.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+enum GroupBy {
+    Album,
+}
+
+/// A group of entries that share the value of a field.
+#[derive(Debug, PartialEq, Eq)]
+struct Group {
+    /// The shared value, or `None` for the entries where the field is missing.
+    value: Option<String>,
+
+    /// The artist of the whole group if it was resolved, which is "Various Artists" when the tracks have different
+    /// artists.
+    album_artist: Option<String>,
+
+    entries: Vec<lsmp3::Entry>,
+}
+
+impl Group {
+    /// Returns the number of distinct artists of the entries in the group.
+    fn artist_count(&self) -> usize {
+        lsmp3::count_values(self.entries.iter().flat_map(|e| e.artist.iter().cloned())).len()
+    }
+
+    fn header(&self) -> String {
+        let value = self.value.as_deref().unwrap_or("(no album)");
+        let header = match &self.album_artist {
+            Some(album_artist) => format!("[{} - {}]", value, album_artist),
+            None => format!("[{}]", value),
+        };
+        match self.artist_count() {
+            0 => header,
+            1 => format!("{} (1 artist)", header),
+            count => format!("{} ({} artists)", header, count),
+        }
+    }
+}
+
+/// Groups the entries by the field, keeping the order of the entries within each group. Groups are ordered by their
+/// first entry, so the groups follow the sort order of the entries. If `various_artists` is set, the album artist of
+/// each group is resolved.
+fn group_entries(entries: &[lsmp3::Entry], group_by: GroupBy, various_artists: bool) -> Vec<Group> {
+    let mut groups: Vec<Group> = Vec::new();
+    // The index of the group of each value, so that finding the group of an entry doesn't scan all groups.
+    let mut indices: BTreeMap<Option<String>, usize> = BTreeMap::new();
+    for entry in entries {
+        let value = match group_by {
+            GroupBy::Album => Some(entry.album.join("/")).filter(|a| !a.is_empty()),
+        };
+        match indices.get(&value) {
+            Some(&i) => groups[i].entries.push(entry.clone()),
+            None => {
+                indices.insert(value.clone(), groups.len());
+                groups.push(Group {
+                    value,
+                    album_artist: None,
+                    entries: vec![entry.clone()],
+                });
+            }
+        }
+    }
+    if various_artists {
+        for group in &mut groups {
+            group.album_artist = album_artist(&group.entries);
+        }
+    }
+    groups
+}
+
+/// Returns the artist shared by all entries that have one, or "Various Artists" if they differ.
+fn album_artist(entries: &[lsmp3::Entry]) -> Option<String> {
+    let artists: BTreeSet<_> = entries
+        .iter()
+        .map(|e| e.artist.join("/"))
+        .filter(|a| !a.is_empty())
+        .collect();
+    match artists.len() {
+        0 => None,
+        1 => artists.into_iter().next(),
+        _ => Some("Various Artists".to_owned()),
+    }
+}
+
+/// Converts the entries of a section to text, grouped or indexed if requested.
+fn entries_to_table(entries: &[lsmp3::Entry], args: &Args, columns: &[Column]) -> String {
+    let table_options = args.table_options();
+    if let Some(group_by) = args.group_by {
+        group_entries(entries, group_by, args.various_artists)
+            .iter()
+            .map(|g| format!("{}\n{}", g.header(), to_table(&g.entries, columns, &table_options)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else if let Some(index_by) = args.index_by {
+        index_entries(entries, index_by)
+            .into_iter()
+            .map(|(bucket, entries)| format!("[{}]\n{}", bucket, to_table(&entries, columns, &table_options)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        to_table(entries, columns, &table_options)
+    }
+}
+
+/// Converts the entries of a section to HTML tables, with a heading for each group or index bucket if grouped or
+/// indexed.
+fn entries_to_html(entries: &[lsmp3::Entry], args: &Args, columns: &[Column]) -> String {
+    let table_options = args.table_options();
+    if let Some(group_by) = args.group_by {
+        group_entries(entries, group_by, args.various_artists)
+            .iter()
+            .map(|g| {
+                format!(
+                    "<h3>{}</h3>\n{}",
+                    escape_html(&g.header()),
+                    to_html(&g.entries, columns, &table_options)
+                )
+            })
+            .collect()
+    } else if let Some(index_by) = args.index_by {
+        index_entries(entries, index_by)
+            .into_iter()
+            .map(|(bucket, entries)| {
+                format!(
+                    "<h3>{}</h3>\n{}",
+                    escape_html(&bucket),
+                    to_html(&entries, columns, &table_options)
+                )
+            })
+            .collect()
+    } else {
+        to_html(entries, columns, &table_options)
+    }
+}
+
+/// Returns a file name for a group value that is safe on all common filesystems, replacing path separators, reserved
+/// characters and control characters.
+fn sanitize_file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // Windows doesn't allow names ending in a dot or space, and a name of only dots refers to a directory.
+    let name = name.trim_end_matches(['.', ' ']);
+    if name.is_empty() {
+        "_".to_owned()
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Writes each group of the entries to its own file in `dir`, named after the group. Groups whose names collide after
+/// sanitizing are numbered.
+fn write_split_output(
+    dir: &Path,
+    entries: &[lsmp3::Entry],
+    args: &Args,
+    columns: &[Column],
+    format: Format,
+) -> Result<(), lsmp3::LsError> {
+    let group_by = args.group_by.unwrap_or(GroupBy::Album);
+    let write_err = |path: &Path, err| lsmp3::LsError::IoWriteError(path.as_os_str().to_owned(), err);
+    fs::create_dir_all(dir).map_err(|err| write_err(dir, err))?;
+    let extension = match format {
+        Format::Table => "txt",
+        Format::Json => "json",
+        Format::Html => "html",
+        // SQLite output is written to the single file of --output, which can't be used with --split-output.
+        Format::Sqlite => unreachable!("SQLite output can't be split"),
+    };
+    let mut names = BTreeSet::new();
+    for group in group_entries(entries, group_by, args.various_artists) {
+        let base = sanitize_file_name(group.value.as_deref().unwrap_or("(no album)"));
+        let name = (1..)
+            .map(|i| match i {
+                1 => base.clone(),
+                i => format!("{} ({})", base, i),
+            })
+            .find(|name| !names.contains(name))
+            .unwrap_or(base);
+        let path = dir.join(format!("{}.{}", name, extension));
+        names.insert(name);
+        let contents = match format {
+            Format::Table => to_table(&group.entries, columns, &args.table_options()),
+            Format::Json => to_json(&group.entries, &args.json_options()).to_string(),
+            Format::Html => HTML_PAGE
+                .replace("{title}", &escape_html(group.value.as_deref().unwrap_or("(no album)")))
+                .replace("{body}", &to_html(&group.entries, columns, &args.table_options())),
+            Format::Sqlite => unreachable!("SQLite output can't be split"),
+        };
+        fs::write(&path, contents).map_err(|err| write_err(&path, err))?;
+    }
+    Ok(())
+}
+
+/// Converts the entries of a section to JSON. If grouped or indexed, they are output as a list of objects with the
+/// group or index bucket and its entries.
+fn entries_to_json(entries: &[lsmp3::Entry], args: &Args) -> Value {
+    if let Some(group_by) = args.group_by {
+        Value::Array(
+            group_entries(entries, group_by, args.various_artists)
+                .into_iter()
+                .map(|g| {
+                    let mut value = json!({
+                        "group": g.value,
+                        "artist_count": g.artist_count(),
+                        "values": to_json(&g.entries, &args.json_options()),
+                    });
+                    if let Some(album_artist) = g.album_artist {
+                        value["album_artist"] = json!(album_artist);
+                    }
+                    value
+                })
+                .collect(),
+        )
+    } else if let Some(index_by) = args.index_by {
+        Value::Array(
+            index_entries(entries, index_by)
+                .into_iter()
+                .map(|(bucket, entries)| {
+                    json!({
+                        "index": bucket,
+                        "values": to_json(&entries, &args.json_options()),
+                    })
+                })
+                .collect(),
+        )
+    } else {
+        to_json(entries, &args.json_options())
+    }
+}
+
+/// A field whose distinct values are counted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+enum CountBy {
+    Artist,
+    Album,
+    Year,
+    Genre,
+}
+
+impl CountBy {
+    fn header(&self) -> &'static str {
+        match self {
+            CountBy::Artist => "ARTIST",
+            CountBy::Album => "ALBUM",
+            CountBy::Year => "YEAR",
+            CountBy::Genre => "GENRE",
+        }
+    }
+
+    /// Returns the values of the field for an entry. Each of multiple values is counted separately.
+    fn values(&self, entry: &lsmp3::Entry) -> Vec<String> {
+        match self {
+            CountBy::Artist => entry.artist.clone(),
+            CountBy::Album => entry.album.clone(),
+            CountBy::Year => entry.year.iter().map(|y| y.to_string()).collect(),
+            CountBy::Genre => entry.genre.clone(),
+        }
+    }
+}
+
+fn counts_to_table(header: &str, counts: &[lsmp3::Count]) -> String {
+    let mut builder = Builder::default();
+    builder.set_columns([header, "COUNT"]);
+    for c in counts {
+        builder.add_record([c.value.clone(), c.count.to_string()]);
+    }
+    build_table(builder)
+}
+
+/// The kind of listed path whose results are output.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+enum PathKind {
+    File,
+    Dir,
+}
+
+impl PathKind {
+    fn matches(&self, path_type: &lsmp3::PathType) -> bool {
+        match self {
+            PathKind::File => *path_type == lsmp3::PathType::File,
+            PathKind::Dir => *path_type == lsmp3::PathType::Directory,
+        }
+    }
+}
+
+/// A property to order the results of the listed directories by.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+enum SortDirs {
+    Name,
+    Size,
+    Count,
+}
+
+/// Sorts the results by a property of each directory. Sizes and counts are ordered from the largest, and ties keep the
+/// order in which the directories were listed.
+fn sort_dirs(results: &mut [lsmp3::Info], by: SortDirs) {
+    match by {
+        SortDirs::Name => results.sort_by(|a, b| a.path.cmp(&b.path)),
+        SortDirs::Size => {
+            results.sort_by_cached_key(|info| std::cmp::Reverse(info.entries.iter().map(|e| e.size).sum::<u64>()))
+        }
+        SortDirs::Count => results.sort_by_key(|info| std::cmp::Reverse(info.entries.len())),
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
 enum Format {
     Table,
     Json,
+    Html,
+    Sqlite,
+}
+
+/// A field whose first letter is used to group entries into an index.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+enum IndexBy {
+    Name,
+    Title,
+    Artist,
+    Album,
+}
+
+impl IndexBy {
+    /// Returns the bucket of an entry, which is the uppercased first character of the field, or "#" if it isn't a
+    /// letter or the field is missing.
+    fn bucket(&self, entry: &lsmp3::Entry) -> String {
+        let first = |values: &[String]| values.first().and_then(|v| v.chars().next());
+        let c = match self {
+            IndexBy::Name => entry.name.to_string_lossy().chars().next(),
+            IndexBy::Title => first(&entry.title),
+            IndexBy::Artist => first(&entry.artist),
+            IndexBy::Album => first(&entry.album),
+        };
+        match c {
+            Some(c) if c.is_alphabetic() => c.to_uppercase().collect(),
+            _ => "#".to_owned(),
+        }
+    }
+}
+
+/// Groups the entries into buckets by `IndexBy::bucket`, keeping their order within each bucket. The "#" bucket comes
+/// first, followed by the letters in alphabetical order.
+fn index_entries(entries: &[lsmp3::Entry], index_by: IndexBy) -> Vec<(String, Vec<lsmp3::Entry>)> {
+    let mut buckets = BTreeMap::<String, Vec<lsmp3::Entry>>::new();
+    for entry in entries {
+        buckets.entry(index_by.bucket(entry)).or_default().push(entry.clone());
+    }
+    buckets.into_iter().collect()
 }
 
 #[derive(Debug, Parser)]
 #[clap(version, about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    #[clap(flatten)]
+    args: Args,
+}
+
+// The command is parsed once, so the size of its largest variant doesn't matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List MP3s (the default when no command is given)
+    List(Args),
+
+    /// Set fields of the tags of MP3s, or remove them by setting them to an empty value
+    Set(SetArgs),
+}
+
+#[derive(Debug, clap::Args)]
+#[clap(group(ArgGroup::new("fields").required(true).multiple(true)))]
+struct SetArgs {
+    /// The FILEs to write the tags of
+    #[clap(required = true, value_hint = ValueHint::FilePath)]
+    file: Vec<PathBuf>,
+
+    /// Show what would change without writing anything
+    #[clap(long = "dry-run", short = 'n')]
+    dry_run: bool,
+
+    /// Set the title
+    #[clap(long = "title", value_name = "TEXT", group = "fields")]
+    title: Option<String>,
+
+    /// Set the artist
+    #[clap(long = "artist", value_name = "TEXT", group = "fields")]
+    artist: Option<String>,
+
+    /// Set the album
+    #[clap(long = "album", value_name = "TEXT", group = "fields")]
+    album: Option<String>,
+
+    /// Set the year
+    #[clap(long = "year", value_name = "YEAR", group = "fields")]
+    year: Option<String>,
+
+    /// Set the track number, optionally followed by "/" and the total number of tracks, e.g. "3/12"
+    #[clap(long = "track", value_name = "NUM[/TOTAL]", group = "fields")]
+    track: Option<String>,
+
+    /// Set the genre
+    #[clap(long = "genre", value_name = "TEXT", group = "fields")]
+    genre: Option<String>,
+
+    /// Set the original artist
+    #[clap(long = "original-artist", value_name = "TEXT", group = "fields")]
+    original_artist: Option<String>,
+
+    /// Set the original album
+    #[clap(long = "original-album", value_name = "TEXT", group = "fields")]
+    original_album: Option<String>,
+
+    /// Set the conductor
+    #[clap(long = "conductor", value_name = "TEXT", group = "fields")]
+    conductor: Option<String>,
+
+    /// Set the publisher
+    #[clap(long = "publisher", value_name = "TEXT", group = "fields")]
+    publisher: Option<String>,
+}
+
+impl SetArgs {
+    /// Returns the fields to set along with their values, named like the sort keys.
+    fn values(&self) -> Vec<(lsmp3::SortBy, String)> {
+        use lsmp3::SortBy::*;
+        [
+            (Title, &self.title),
+            (Artist, &self.artist),
+            (Album, &self.album),
+            (Year, &self.year),
+            (Track, &self.track),
+            (Genre, &self.genre),
+            (OriginalArtist, &self.original_artist),
+            (OriginalAlbum, &self.original_album),
+            (Conductor, &self.conductor),
+            (Publisher, &self.publisher),
+        ]
+        .into_iter()
+        .filter_map(|(field, value)| value.clone().map(|v| (field, v)))
+        .collect()
+    }
+}
+
+#[derive(Debug, clap::Args)]
+#[clap(group(ArgGroup::new("mode")))]
 struct Args {
     /// The FILEs to list information about (the current directory by default)
     #[clap(value_hint = ValueHint::AnyPath)]
     file: Vec<String>,
 
-    /// The output format to use
+    /// The output format to use [default: table, or $LSMP3_FORMAT]
     #[clap(long = "format", short = 'f')]
     #[clap(value_name = "WORD")]
     #[clap(arg_enum)]
-    #[clap(default_value = "table")]
-    format: Format,
+    format: Option<Format>,
 
     /// Reverse order while sorting
     #[clap(long = "reverse", short = 'r')]
     reverse: bool,
 
+    /// Ignore a leading article when sorting by title, artist or album, e.g. to sort "The Beatles" as "Beatles". Takes
+    /// an optional "/" or comma separated list of articles, which must follow an equals sign, e.g.
+    /// --ignore-articles=the/le/la, since a word after a space is a path to list [default: the/a/an]. Values with a
+    /// sort order tag are sorted as is
+    #[clap(long = "ignore-articles", value_name = "ARTICLES")]
+    #[clap(min_values = 0, require_equals = true, default_missing_value = "the/a/an")]
+    ignore_articles: Option<String>,
+
+    /// The articles parsed from `ignore_articles`.
+    #[clap(skip)]
+    articles: Vec<String>,
+
+    /// Sort the genres in the comma separated LIST first when sorting by genre, in the order given, followed by all
+    /// other genres in alphabetical order
+    #[clap(long = "genre-priority", value_name = "LIST")]
+    #[clap(use_value_delimiter = true)]
+    genre_priority: Vec<String>,
+
+    /// Always sort files without a value for a sort key after those with one, regardless of the direction
+    #[clap(long = "untagged-last")]
+    untagged_last: bool,
+
+    /// Show at most N values of each multi-value field in the table, noting how many more there are. JSON output always
+    /// contains all values
+    #[clap(long = "max-values", value_name = "N")]
+    #[clap(parse(try_from_str = parse_max_values))]
+    max_values: Option<usize>,
+
+    /// Output the path of each listed file and directory as an absolute path, instead of as given
+    #[clap(long = "absolute-paths")]
+    absolute_paths: bool,
+
+    /// Stop listing once N files have been found across all paths. These are the first N files encountered while
+    /// walking, which are then sorted, not the first N of the sorted listing. Filters are applied afterwards
+    #[clap(long = "max-results", value_name = "N")]
+    max_results: Option<usize>,
+
+    /// Only list a random sample of the files found in directories, either a percentage like "10%" or a number of files
+    /// like "500". Files that aren't sampled are skipped without being read, which makes e.g. --stats on a large
+    /// library much faster
+    #[clap(long = "sample", value_name = "PERCENT%|N")]
+    #[clap(parse(try_from_str = parse_sample))]
+    sample: Option<lsmp3::Sample>,
+
+    /// The seed that decides which files --sample picks. The same seed picks the same files
+    #[clap(long = "sample-seed", value_name = "NUM", default_value = "0")]
+    #[clap(requires = "sample")]
+    sample_seed: u64,
+
+    /// Give up on reading the tags of a file after SECS seconds, e.g. on a flaky network mount, failing with an error
+    /// that --no-fail-fast skips [default: no timeout]
+    #[clap(long = "file-timeout", value_name = "SECS")]
+    #[clap(parse(try_from_str = parse_timeout))]
+    file_timeout: Option<Duration>,
+
+    /// Trim tag values and collapse runs of whitespace within them before sorting and grouping
+    #[clap(long = "trim")]
+    #[clap(overrides_with = "raw-tags")]
+    trim: bool,
+
+    /// Split each tag value into several values on any of CHARS, e.g. ";" for taggers that store "A; B" in a single
+    /// value, trimming the whitespace around each part
+    #[clap(long = "split-on", value_name = "CHARS")]
+    split_on: Option<String>,
+
+    /// Show tag values exactly as they are stored, including any stray whitespace (default)
+    #[clap(long = "raw-tags")]
+    #[clap(overrides_with = "trim")]
+    raw_tags: bool,
+
+    /// List only the first file, by name, of the files in a directory that resolve to the same file, e.g. symlinks into
+    /// a shared pool
+    #[clap(long = "dedup-targets")]
+    dedup_targets: bool,
+
+    /// Follow only the symlinks given as paths, skipping the symlinks found inside the listed directories
+    #[clap(long = "follow-first")]
+    follow_first: bool,
+
+    /// List only the first path of the files that are hard links to the same file, across all the listed paths
+    #[cfg(unix)]
+    #[clap(long = "dedup-inodes")]
+    dedup_inodes: bool,
+
+    /// Print the "path:" header before the table of a directory even when it's the only path listed
+    #[clap(long = "always-header")]
+    always_header: bool,
+
+    /// Only output the results of the given paths that are files, or of those that are directories (including their
+    /// subdirectories when listing recursively)
+    #[clap(long = "type", value_name = "WORD")]
+    #[clap(arg_enum)]
+    path_type: Option<PathKind>,
+
+    /// Order the sections of the listed directories by their path, or by the total size or number of their listed
+    /// files, largest first, instead of in the order they were listed
+    #[clap(long = "sort-dirs", value_name = "WORD")]
+    #[clap(arg_enum)]
+    sort_dirs: Option<SortDirs>,
+
+    /// Show additional metadata columns
+    #[clap(long = "long", short = 'l')]
+    long: bool,
+
     /// List subdirectories recursively
     #[clap(long = "recursive", short = 'R')]
     recursive: bool,
 
-    /// Sort by WORD (can be set multiple times)
+    /// Merge the results of each listed directory and its subdirectories into a single table, naming each file by its
+    /// path relative to the listed directory
+    #[clap(long = "flatten")]
+    #[clap(conflicts_with = "json-stream")]
+    flatten: bool,
+
+    /// Sort by WORD, optionally followed by ":asc" or ":desc" to set the direction of that key (can be set multiple
+    /// times, or to a comma separated list like "album,track"). "all" sorts by artist, year, album, track, title and
+    /// name [default: name, or the space or comma separated keys in $LSMP3_SORT]
     #[clap(long = "sort", short = 's')]
-    #[clap(value_name = "WORD")]
-    #[clap(arg_enum)]
+    #[clap(value_name = "WORD[:asc|:desc]")]
+    #[clap(parse(try_from_str = parse_sort_keys))]
     #[clap(multiple = true)]
     #[clap(number_of_values = 1)]
-    #[clap(default_value = "name")]
-    sort_by: Vec<lsmp3::SortBy>,
-}
+    sort: Vec<Vec<(lsmp3::SortBy, Option<lsmp3::Direction>)>>,
 
-#[inline]
-fn to_table(res: &[lsmp3::Entry]) -> String {
-    if res.is_empty() {
-        Default::default()
-    } else {
-        Table::new(res)
-            .with(tabled::Style::blank())
-            .with(tabled::Modify::new(tabled::object::Segment::all()).with(tabled::Alignment::left()))
-            .to_string()
-            + "\n"
-    }
-}
+    /// The sort keys resolved from `sort`, the preset and the environment.
+    #[clap(skip)]
+    sort_by: Vec<(lsmp3::SortBy, lsmp3::Direction)>,
 
-#[inline]
-fn to_json(res: &[lsmp3::Entry]) -> Value {
-    serde_json::to_value(res).unwrap_or_else(|err| error(err))
-}
+    /// Sort by a predefined sequence of keys, after any keys given with --sort
+    #[clap(long = "preset")]
+    #[clap(value_name = "WORD")]
+    #[clap(arg_enum)]
+    preset: Option<Preset>,
 
-fn main() {
-    let args = Args::parse();
-
-    let results = lsmp3::list(
-        &args.file,
-        &lsmp3::ListOptions {
-            sort_by: &args.sort_by,
-            reverse: &args.reverse,
-            recursive: &args.recursive,
-        },
-    )
-    .unwrap_or_else(|err| error(err));
-    match args.format {
-        Format::Table => {
-            let mut tables = Vec::with_capacity(results.len());
-            if results.len() == 1 {
-                tables.push(to_table(&results[0].entries));
-            } else {
-                let (files, dirs): (Vec<_>, Vec<_>) =
-                    results.into_iter().partition(|f| f.path_type == lsmp3::PathType::File);
-                if !files.is_empty() {
-                    let mut f = files.into_iter().flat_map(|f| f.entries).collect::<Vec<_>>();
-                    f.sort_unstable_by(|a, b| {
-                        let ord = lsmp3::cmp_entry(a, b, &args.sort_by);
-                        if args.reverse {
-                            ord.reverse()
-                        } else {
-                            ord
-                        }
-                    });
-                    tables.push(to_table(&f));
-                }
-                if !dirs.is_empty() {
-                    tables.extend(dirs.iter().map(|f| format!("{}:\n{}", f.path, to_table(&f.entries))));
-                }
+    /// Without --sort or --preset, sort by track number within each disc, and then by name, instead of the default sort
+    /// keys. Orders the tracks of well tagged albums regardless of how their files are named
+    #[clap(long = "smart-sort")]
+    smart_sort: bool,
 
-                for (i, table) in tables.iter().enumerate() {
-                    print!("{}", table);
-                    if i < tables.len() - 1 {
-                        println!();
-                    }
-                }
-            }
+    /// Sort keys given without ":asc" or ":desc" in the direction that suits their field, i.e. size and year from the
+    /// largest, and the other keys ascending
+    #[clap(long = "smart-direction")]
+    smart_direction: bool,
+
+    /// Show a checksum of each whole file (tags and audio, not just the audio) computed with WORD
+    #[clap(long = "checksum")]
+    #[clap(value_name = "WORD")]
+    #[clap(arg_enum)]
+    checksum: Option<lsmp3::ChecksumAlgorithm>,
+
+    /// Show a checksum of only the ID3v2 tag of each file computed with WORD, to tell when a file was retagged without
+    /// changing its audio
+    #[clap(long = "tag-hash")]
+    #[clap(value_name = "WORD")]
+    #[clap(arg_enum)]
+    tag_hash: Option<lsmp3::ChecksumAlgorithm>,
+
+    /// Show the number of audio frames of each file and of decoded samples per channel, without the encoder delay and
+    /// padding recorded by LAME. Reads each file in full
+    #[clap(long = "frame-count")]
+    frame_count: bool,
+
+    /// Show the container format of each file, i.e. MP3, WAV or AIFF, from its header, or from its extension with
+    /// --no-tags. JSON always includes it as "format"
+    #[clap(long = "show-type")]
+    show_type: bool,
+
+    /// Show the resolved target of symlinked files
+    #[clap(long = "dereference-verbose", short = 'L')]
+    dereference_verbose: bool,
+
+    /// Show statistics about the listed files instead of the files themselves
+    #[clap(long = "stats")]
+    #[clap(group = "mode")]
+    stats: bool,
+
+    /// Only count the files with an .mp3 extension and their total size, without reading them, to estimate how much
+    /// work a full listing is
+    #[clap(long = "estimate")]
+    #[clap(group = "mode")]
+    estimate: bool,
+
+    /// Instead of listing, show the chapters marked in the tag of a single file, e.g. of an audiobook, with their start
+    /// and end times
+    #[clap(long = "chapters")]
+    #[clap(group = "mode")]
+    chapters: bool,
+
+    /// Instead of listing, show the text encoding declared by each frame of the ID3v2 tag of each file, e.g. to find
+    /// the frames that aren't UTF-8 yet. Exits with an error if a file can't be read
+    #[clap(long = "show-encoding")]
+    #[clap(group = "mode")]
+    show_encoding: bool,
+
+    /// Instead of listing, output JSON with the files of each album and the embedded cover art of each file, if any,
+    /// with its MIME type and dimensions, e.g. for building a gallery
+    #[clap(long = "art-manifest")]
+    #[clap(conflicts_with = "format")]
+    #[clap(group = "mode")]
+    art_manifest: bool,
+
+    /// Instead of listing, print the keys accepted by --sort, one per line or as a JSON array
+    #[clap(long = "list-sorts")]
+    #[clap(group = "mode")]
+    list_sorts: bool,
+
+    /// Instead of listing, print the formats accepted by --format, one per line or as a JSON array
+    #[clap(long = "list-formats")]
+    #[clap(group = "mode")]
+    list_formats: bool,
+
+    /// Instead of listing, show how many of the files have each tag field, e.g. to find the fields that are missing most
+    /// often
+    #[clap(long = "tag-coverage")]
+    #[clap(group = "mode")]
+    tag_coverage: bool,
+
+    /// Limit the genre and artist breakdowns of the statistics to the NUM most common values
+    #[clap(long = "stats-top")]
+    #[clap(value_name = "NUM")]
+    #[clap(default_value = "10")]
+    stats_top: usize,
+
+    /// Compare the listing against a JSON listing previously saved to FILE and show what was added, removed or changed
+    #[clap(long = "compare")]
+    #[clap(value_name = "FILE")]
+    #[clap(value_hint = ValueHint::FilePath)]
+    #[clap(group = "mode")]
+    compare: Option<String>,
+
+    /// Keep implausible years (below 1 or above 9999) and warn about them instead of hiding them
+    #[clap(long = "strict-year")]
+    strict_year: bool,
+
+    /// Which year to show for files whose tag has several, e.g. reissues with the dates of each release. All the years
+    /// are listed under "years" in JSON
+    #[clap(long = "year-policy")]
+    #[clap(value_name = "POLICY")]
+    #[clap(arg_enum)]
+    #[clap(default_value = "first")]
+    year_policy: lsmp3::YearPolicy,
+
+    /// Add a column rendered from TEMPLATE, where fields are referenced by name in braces, e.g. "{artist} - {title}"
+    #[clap(long = "template")]
+    #[clap(value_name = "TEMPLATE")]
+    template: Option<lsmp3::Template>,
+
+    /// Output only the rendered template for each file
+    #[clap(long = "template-only")]
+    #[clap(requires = "template")]
+    #[clap(conflicts_with = "format")]
+    #[clap(group = "mode")]
+    template_only: bool,
+
+    /// Instead of listing, copy each file to the path rendered from TEMPLATE within the --dest directory, e.g.
+    /// "{artist}/{album}/{track} {title}.mp3". Each "/" of the template starts a directory, and characters that aren't
+    /// allowed in file names are replaced with "_" in field values
+    #[clap(long = "rename", value_name = "TEMPLATE")]
+    #[clap(requires = "dest")]
+    #[clap(group = "mode")]
+    rename: Option<lsmp3::Template>,
+
+    /// The directory to copy or move files into with --rename
+    #[clap(long = "dest", value_name = "DIR")]
+    #[clap(value_hint = ValueHint::DirPath)]
+    #[clap(requires = "rename")]
+    dest: Option<PathBuf>,
+
+    /// Move files with --rename instead of copying them
+    #[clap(long = "move")]
+    #[clap(requires = "rename")]
+    move_files: bool,
+
+    /// Only print the files that --rename would copy or move, without touching them
+    #[clap(long = "dry-run", short = 'n')]
+    #[clap(requires = "rename")]
+    dry_run: bool,
+
+    /// Order the output by full path and break sorting ties by file name, so that it is identical regardless of the
+    /// order in which the filesystem returns entries
+    #[clap(long = "deterministic-walk")]
+    deterministic_walk: bool,
+
+    /// Output the size, year and track number and total as strings in JSON instead of numbers, for consumers that
+    /// expect every value to be a string
+    #[clap(long = "json-strings")]
+    json_strings: bool,
+
+    /// Wrap the JSON output in an object with the version of lsmp3 and of the JSON schema, with the listing under
+    /// "results". Saved listings with this wrapper can still be compared against
+    #[clap(long = "envelope")]
+    #[clap(conflicts_with = "json-stream")]
+    envelope: bool,
+
+    /// Output newline delimited JSON, writing each listed path as soon as it has been listed
+    #[clap(long = "json-stream")]
+    #[clap(group = "mode")]
+    json_stream: bool,
+
+    /// Only list files matching FIELD=VALUE, where FIELD is sample_rate, channel_mode (stereo, joint-stereo,
+    /// dual-channel or mono), isrc or compilation (true or false). Can be given multiple times to require all filters
+    /// to match
+    #[clap(long = "filter", value_name = "FIELD=VALUE")]
+    #[clap(multiple_occurrences = true)]
+    filter: Vec<lsmp3::Predicate>,
+
+    /// Only list files modified at or after TIME, either a date like "2024-01-01" (UTC) or a time ago like "7d", in
+    /// seconds (s), minutes (m), hours (h), days (d) or weeks (w)
+    #[clap(long = "since", value_name = "TIME")]
+    #[clap(parse(try_from_str = parse_time))]
+    since: Option<u64>,
+
+    /// Only list files modified before TIME, given like --since
+    #[clap(long = "until", value_name = "TIME")]
+    #[clap(parse(try_from_str = parse_time))]
+    until: Option<u64>,
+
+    /// Only list files whose title, artist or album fuzzily match QUERY, e.g. "darkside" matches "Dark Side", ordered
+    /// from the best to the worst match instead of by the sort keys
+    #[clap(long = "search", value_name = "QUERY")]
+    search: Option<String>,
+
+    /// Hide the columns of the table that have no value for any of its files
+    #[clap(long = "auto-columns")]
+    auto_columns: bool,
+
+    /// Show the names of the files without their extension in the table, e.g. for clean track listings. JSON output
+    /// keeps the full names
+    #[clap(long = "strip-extension")]
+    strip_extension: bool,
+
+    /// Number the entries of each table from 1 in the order they are shown, in a RANK column, or in a "rank" key in
+    /// JSON
+    #[clap(long = "rank")]
+    rank: bool,
+
+    /// The casing of the table headers, e.g. "Album Artist" for title
+    #[clap(long = "header-case")]
+    #[clap(value_name = "CASE")]
+    #[clap(arg_enum)]
+    #[clap(default_value = "upper")]
+    header_case: HeaderCase,
+
+    /// Show TEXT in the table for fields without a value, e.g. "-", instead of leaving them blank
+    #[clap(long = "empty-placeholder", value_name = "TEXT")]
+    empty_placeholder: Option<String>,
+
+    /// Show sizes in human readable units, e.g. 22 kiB (default)
+    #[clap(long = "human-readable")]
+    #[clap(overrides_with = "bytes")]
+    human_readable: bool,
+
+    /// Show sizes as the exact number of bytes instead of in human readable units
+    #[clap(long = "bytes")]
+    #[clap(overrides_with = "human-readable")]
+    bytes: bool,
+
+    /// Show the size of each file both in human readable units and as the exact number of bytes, in two columns
+    #[clap(long = "size-both")]
+    #[clap(conflicts_with = "bytes")]
+    size_both: bool,
+
+    /// Group the entries of each directory by the first letter of WORD, for building an index. Entries that don't start
+    /// with a letter are grouped under "#"
+    #[clap(long = "index-by", value_name = "WORD")]
+    #[clap(arg_enum)]
+    #[clap(group = "mode")]
+    index_by: Option<IndexBy>,
+
+    /// Instead of listing, check the metadata for issues such as implausible years, track numbers above the total
+    /// number of tracks or a tagged length (TLEN) that doesn't match the duration of the audio, and exit with an error
+    /// if any are found
+    #[clap(long = "validate")]
+    #[clap(group = "mode")]
+    validate: bool,
+
+    /// Instead of listing, walk and decode the MPEG frames of each file to find corrupt audio, such as truncated files,
+    /// data between frames or frames that can't be decoded, even when the tags are valid, and exit with an error if any
+    /// is found. Reads each whole file
+    #[clap(long = "verify-audio")]
+    #[clap(group = "mode")]
+    verify_audio: bool,
+
+    /// Instead of listing, show the files that were skipped because they couldn't be parsed as MP3s, along with the
+    /// reason
+    #[clap(long = "list-skipped")]
+    #[clap(group = "mode")]
+    list_skipped: bool,
+
+    /// Group the entries of each directory by WORD, in the order of their first entry
+    #[clap(long = "group-by", value_name = "WORD")]
+    #[clap(arg_enum)]
+    #[clap(group = "mode")]
+    group_by: Option<GroupBy>,
+
+    /// Write the tables of the files, albums and artists to the SQLite database FILE, replacing those of an earlier run.
+    /// Required with "--format sqlite", and only used with it
+    #[clap(long = "output", value_name = "FILE")]
+    #[clap(value_hint = ValueHint::FilePath)]
+    #[clap(conflicts_with = "split-output")]
+    output: Option<PathBuf>,
+
+    /// Write each group to its own file in DIR instead of printing it, named after the group and in the chosen format
+    #[clap(long = "split-output", value_name = "DIR")]
+    #[clap(value_hint = ValueHint::DirPath)]
+    #[clap(requires = "group-by")]
+    split_output: Option<PathBuf>,
+
+    /// Show the album artist of each group, or "Various Artists" if its tracks have different artists
+    #[clap(long = "various-artists")]
+    #[clap(requires = "group-by")]
+    various_artists: bool,
+
+    /// Instead of listing, count the files with each distinct value of WORD, from the most to the least common
+    #[clap(long = "count-by", value_name = "WORD")]
+    #[clap(arg_enum)]
+    #[clap(group = "mode")]
+    count_by: Option<CountBy>,
+
+    /// Stop at the first file or directory that can't be read (default)
+    #[clap(long = "fail-fast")]
+    #[clap(overrides_with = "no-fail-fast")]
+    fail_fast: bool,
+
+    /// Report files and directories that can't be read and keep listing the rest, exiting with an error at the end.
+    /// Paths that don't exist are still fatal
+    #[clap(long = "no-fail-fast")]
+    #[clap(overrides_with = "fail-fast")]
+    #[clap(conflicts_with = "json-stream")]
+    no_fail_fast: bool,
+
+    /// Print errors to stderr as JSON objects, one per line, with the "error" message, the "path" it's about and its
+    /// "kind", e.g. "io", "id3" or "invalid_path"
+    #[clap(long = "errors-json")]
+    errors_json: bool,
+
+    /// Don't read any tags and only list the name and size of files with an .mp3 extension, which is much faster for
+    /// large directories
+    #[clap(long = "no-tags")]
+    no_tags: bool,
+
+    /// Cache the tags read from files in FILE and reuse them for files whose size and modification time are unchanged
+    #[clap(long = "cache")]
+    #[clap(value_name = "FILE")]
+    #[clap(value_hint = ValueHint::FilePath)]
+    cache: Option<PathBuf>,
+
+    /// Also read the ID3v1 tag at the end of each file and use its fields where the ID3v2 tag is missing them
+    #[clap(long = "merge-tags")]
+    #[clap(conflicts_with = "no-tags")]
+    merge_tags: bool,
+
+    /// Truncate the text of frames longer than N bytes in the output, with a warning, to keep malformed files with huge
+    /// frames from flooding it. The whole tag is still read, and files whose tag declares more than 32 MiB fail to be
+    /// read
+    #[clap(long = "max-frame-bytes", value_name = "N")]
+    #[clap(default_value_t = lsmp3::DEFAULT_MAX_FRAME_BYTES)]
+    max_frame_bytes: usize,
+
+    /// Also list WAV and AIFF files that have an ID3 chunk, which are skipped otherwise
+    #[clap(long = "wav-aiff")]
+    wav_aiff: bool,
+
+    /// Exit with status 3 if no files were listed across all paths after filtering, to tell an empty listing apart
+    /// from a successful one
+    #[clap(long = "fail-if-empty")]
+    fail_if_empty: bool,
+
+    /// Print how long the scan took and how many files were listed per second to stderr
+    #[clap(long = "timing")]
+    timing: bool,
+
+    /// Only walk and parse the files without sorting or output, and print how many were parsed and how long it took,
+    /// to measure the throughput of reading tags on its own
+    #[clap(long = "bench", hide = true)]
+    #[clap(group = "mode")]
+    bench: bool,
+
+    /// Encode the text output in ENCODING instead of UTF-8, e.g. "shift_jis" or "latin1". Characters that can't be
+    /// encoded are written as HTML character references. Can't be used with JSON output, which is always UTF-8
+    #[clap(long = "encoding")]
+    #[clap(value_name = "ENCODING")]
+    #[clap(parse(try_from_str = parse_encoding))]
+    encoding: Option<&'static Encoding>,
+
+    /// Read the tags of paths that are HTTP or HTTPS URLs by fetching only the ID3v2 tag with range requests
+    #[cfg(feature = "remote")]
+    #[clap(long = "remote")]
+    remote: bool,
+
+    /// Replace control characters in displayed names and tags with "\xNN" escapes, so that they can't mess up the
+    /// terminal. The default when writing to a terminal. JSON output is never escaped
+    #[clap(long = "escape-control")]
+    #[clap(overrides_with = "no-escape-control")]
+    escape_control: bool,
+
+    /// Output names and tags verbatim, even when writing to a terminal
+    #[clap(long = "no-escape-control")]
+    #[clap(overrides_with = "escape-control")]
+    no_escape_control: bool,
+}
+
+impl Args {
+    /// Returns how sizes are shown in the table of entries.
+    fn size_format(&self) -> SizeFormat {
+        if self.size_both {
+            SizeFormat::Both
+        } else if self.bytes {
+            SizeFormat::Bytes
+        } else {
+            SizeFormat::HumanReadable
+        }
+    }
+
+    /// Returns the options for rendering the table of entries.
+    fn table_options(&self) -> TableOptions<'_> {
+        TableOptions {
+            size_format: self.size_format(),
+            max_values: self.max_values,
+            empty_placeholder: self.empty_placeholder.as_deref().unwrap_or_default(),
+            auto_columns: self.auto_columns,
+            strip_extension: self.strip_extension,
+            rank: self.rank,
+            header_case: self.header_case,
+        }
+    }
+
+    /// Returns the options for converting entries to JSON.
+    fn json_options(&self) -> JsonOptions {
+        JsonOptions {
+            strings: self.json_strings,
+            rank: self.rank,
+        }
+    }
+
+    /// Returns the flag of the report selected by the arguments, if any. Reports are output as text or JSON, unlike the
+    /// listing of entries.
+    fn report_flag(&self) -> Option<&'static str> {
+        [
+            (self.stats, "--stats"),
+            (self.estimate, "--estimate"),
+            (self.chapters, "--chapters"),
+            (self.show_encoding, "--show-encoding"),
+            (self.list_sorts, "--list-sorts"),
+            (self.list_formats, "--list-formats"),
+            (self.tag_coverage, "--tag-coverage"),
+            (self.compare.is_some(), "--compare"),
+            (self.validate, "--validate"),
+            (self.verify_audio, "--verify-audio"),
+            (self.list_skipped, "--list-skipped"),
+            (self.count_by.is_some(), "--count-by"),
+            (self.bench, "--bench"),
+        ]
+        .into_iter()
+        .find(|(selected, _)| *selected)
+        .map(|(_, flag)| flag)
+    }
+
+    /// Returns whether control characters should be escaped in text output.
+    fn escape_control(&self) -> bool {
+        self.escape_control || (!self.no_escape_control && io::stdout().is_terminal())
+    }
+}
+
+/// An optional column that is appended to the table after the default columns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Column {
+    Disc,
+    OriginalArtist,
+    OriginalAlbum,
+    Conductor,
+    Remixer,
+    Isrc,
+    Publisher,
+    Copyright,
+    Compilation,
+    SampleRate,
+    ChannelMode,
+    Checksum,
+    TagHash,
+    FrameCount,
+    SampleCount,
+    LinkTarget,
+    Source,
+    Type,
+    Template(lsmp3::Template),
+}
+
+impl Column {
+    fn header(&self) -> &'static str {
+        match self {
+            Column::OriginalArtist => "ORIGINAL ARTIST",
+            Column::OriginalAlbum => "ORIGINAL ALBUM",
+            Column::Conductor => "CONDUCTOR",
+            Column::Remixer => "REMIXER",
+            Column::Disc => "DISC",
+            Column::Isrc => "ISRC",
+            Column::Publisher => "PUBLISHER",
+            Column::Copyright => "COPYRIGHT",
+            Column::Compilation => "COMP",
+            Column::SampleRate => "SAMPLE RATE",
+            Column::ChannelMode => "CHANNELS",
+            Column::Checksum => "CHECKSUM",
+            Column::TagHash => "TAG HASH",
+            Column::FrameCount => "FRAMES",
+            Column::SampleCount => "SAMPLES",
+            Column::LinkTarget => "TARGET",
+            Column::Source => "SOURCE",
+            Column::Type => "TYPE",
+            Column::Template(_) => "TEMPLATE",
+        }
+    }
+
+    fn display(&self, entry: &lsmp3::Entry) -> String {
+        match self {
+            Column::OriginalArtist => entry.original_artist.join("/"),
+            Column::OriginalAlbum => entry.original_album.join("/"),
+            Column::Conductor => entry.conductor.join("/"),
+            Column::Remixer => entry.remixer.join("/"),
+            Column::Disc => entry.disc.map(|d| d.to_string()).unwrap_or_default(),
+            Column::Isrc => entry.isrc.join("/"),
+            Column::Publisher => entry.publisher.join("/"),
+            Column::Copyright => entry.copyright.join("/"),
+            Column::Compilation => if entry.compilation { "✓" } else { "✗" }.to_owned(),
+            Column::SampleRate => entry.sample_rate.map(|r| r.to_string()).unwrap_or_default(),
+            Column::ChannelMode => entry.channel_mode.map(|m| m.to_string()).unwrap_or_default(),
+            Column::Checksum => entry.checksum.clone().unwrap_or_default(),
+            Column::TagHash => entry.tag_hash.clone().unwrap_or_default(),
+            Column::FrameCount => entry.frame_count.map(|n| n.to_string()).unwrap_or_default(),
+            Column::SampleCount => entry.sample_count.map(|n| n.to_string()).unwrap_or_default(),
+            Column::LinkTarget => entry.link_target.clone().unwrap_or_default(),
+            Column::Source => entry.source_dir.clone().unwrap_or_default(),
+            Column::Type => entry.format.to_string(),
+            Column::Template(template) => template.render(entry),
+        }
+    }
+}
+
+impl Args {
+    /// Returns the optional columns enabled by the arguments.
+    fn columns(&self) -> Vec<Column> {
+        let mut columns = Vec::new();
+        if self.long {
+            columns.extend([
+                Column::Disc,
+                Column::OriginalArtist,
+                Column::OriginalAlbum,
+                Column::Conductor,
+                Column::Remixer,
+                Column::Isrc,
+                Column::Publisher,
+                Column::Copyright,
+                Column::Compilation,
+                Column::SampleRate,
+                Column::ChannelMode,
+            ]);
+        }
+        if self.checksum.is_some() {
+            columns.push(Column::Checksum);
+        }
+        if self.tag_hash.is_some() {
+            columns.push(Column::TagHash);
+        }
+        if self.frame_count {
+            columns.extend([Column::FrameCount, Column::SampleCount]);
+        }
+        if self.dereference_verbose {
+            columns.push(Column::LinkTarget);
+        }
+        if self.show_type {
+            columns.push(Column::Type);
+        }
+        if let Some(template) = &self.template {
+            columns.push(Column::Template(template.clone()));
+        }
+        columns
+    }
+}
+
+/// Truncates each multi-value field of an entry to its first `max` values, noting how many were left out on the last
+/// value that is kept, e.g. "A/B (+3 more)". `max` must be at least 1.
+fn truncate_values(entry: &lsmp3::Entry, max: usize) -> lsmp3::Entry {
+    let mut entry = entry.clone();
+    for values in entry.text_values_mut() {
+        if values.len() > max {
+            let more = values.len() - max;
+            values.truncate(max);
+            if let Some(last) = values.last_mut() {
+                *last = format!("{} (+{} more)", last, more);
+            }
+        }
+    }
+    entry
+}
+
+/// How the size of each file is shown in the table of entries.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+enum SizeFormat {
+    /// In human readable units, e.g. 22 kiB.
+    #[default]
+    HumanReadable,
+    /// As the exact number of bytes.
+    Bytes,
+    /// In human readable units, followed by a column with the exact number of bytes.
+    Both,
+}
+
+/// The casing of the headers of the table of entries.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+enum HeaderCase {
+    /// E.g. "ALBUM ARTIST".
+    #[default]
+    Upper,
+    /// E.g. "album artist".
+    Lower,
+    /// E.g. "Album Artist".
+    Title,
+}
+
+impl HeaderCase {
+    /// Returns the header in this case. Headers are uppercase to begin with.
+    fn apply(&self, header: &str) -> String {
+        match self {
+            HeaderCase::Upper => header.to_uppercase(),
+            HeaderCase::Lower => header.to_lowercase(),
+            HeaderCase::Title => header
+                .split(' ')
+                .map(|word| {
+                    let mut chars = word.chars();
+                    chars
+                        .next()
+                        .map(|first| first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect())
+                        .unwrap_or_default()
+                })
+                .collect::<Vec<String>>()
+                .join(" "),
+        }
+    }
+}
+
+/// The options for rendering the table of entries.
+#[derive(Debug, Default)]
+struct TableOptions<'a> {
+    size_format: SizeFormat,
+    /// The number of values of each multi-value field to show, if limited.
+    max_values: Option<usize>,
+    /// The text shown in cells of fields without a value.
+    empty_placeholder: &'a str,
+    /// Whether to hide the columns without a value for any entry.
+    auto_columns: bool,
+    /// Whether to show the names of the files without their extension.
+    strip_extension: bool,
+    /// Whether to number the entries in a first column.
+    rank: bool,
+    /// The casing of the headers.
+    header_case: HeaderCase,
+}
+
+/// Returns the headers and the rows of the table of entries, one row per entry. Fields without a value are left empty,
+/// and the columns hidden by the options are removed.
+fn table_cells(res: &[lsmp3::Entry], columns: &[Column], options: &TableOptions) -> (Vec<String>, Vec<Vec<String>>) {
+    let TableOptions {
+        size_format,
+        max_values,
+        auto_columns,
+        strip_extension,
+        rank,
+        header_case,
+        ..
+    } = *options;
+    let mut headers = lsmp3::Entry::headers();
+    let size_index = headers.iter().position(|h| h == "SIZE");
+    if let Some(i) = size_index.filter(|_| size_format == SizeFormat::Both) {
+        headers.insert(i + 1, "BYTES".into());
+    }
+    headers.extend(columns.iter().map(|c| c.header().into()));
+    if rank {
+        headers.insert(0, "RANK".into());
+    }
+    let rows: Vec<Vec<String>> = res
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let truncated = max_values.map(|max| truncate_values(entry, max));
+            let entry = truncated.as_ref().unwrap_or(entry);
+            let mut fields: Vec<_> = entry.fields().into_iter().map(Cow::into_owned).collect();
+            if strip_extension {
+                // The name is the first field.
+                fields[0] = Path::new(&entry.name).with_extension("").to_string_lossy().to_string();
+            }
+            match (size_index, size_format) {
+                (Some(i), SizeFormat::Bytes) => fields[i] = entry.size.to_string(),
+                (Some(i), SizeFormat::Both) => fields.insert(i + 1, entry.size.to_string()),
+                _ => {}
+            }
+            fields.extend(columns.iter().map(|c| c.display(entry)));
+            if rank {
+                fields.insert(0, (i + 1).to_string());
+            }
+            fields
+        })
+        .collect();
+    let shown: Vec<bool> = (0..headers.len())
+        .map(|i| !auto_columns || rows.iter().any(|row| !row[i].is_empty()))
+        .collect();
+    let keep = |values: Vec<String>| {
+        values
+            .into_iter()
+            .zip(&shown)
+            .filter(|(_, s)| **s)
+            .map(|(v, _)| v)
+            .collect::<Vec<_>>()
+    };
+    (
+        keep(headers.iter().map(|h| header_case.apply(h)).collect()),
+        rows.into_iter().map(keep).collect(),
+    )
+}
+
+#[inline]
+fn to_table(res: &[lsmp3::Entry], columns: &[Column], options: &TableOptions) -> String {
+    if res.is_empty() {
+        Default::default()
+    } else {
+        let (headers, rows) = table_cells(res, columns, options);
+        let mut builder = Builder::default();
+        builder.set_columns(headers);
+        for row in rows {
+            builder.add_record(row.into_iter().map(|field| {
+                if field.is_empty() {
+                    options.empty_placeholder.to_owned()
+                } else {
+                    field
+                }
+            }));
+        }
+        build_table(builder)
+    }
+}
+
+#[inline]
+fn build_table(builder: Builder) -> String {
+    builder
+        .build()
+        .with(tabled::Style::blank())
+        .with(tabled::Modify::new(tabled::object::Segment::all()).with(tabled::Alignment::left()))
+        .to_string()
+        + "\n"
+}
+
+/// Escapes the characters of text that have a special meaning in HTML, so it can be used in elements and attributes.
+fn escape_html(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Converts the entries to an HTML table with the same columns as `to_table`. Each cell has the value to sort by in its
+/// `data-sort` attribute, which is the number of bytes for sizes and the text of the cell otherwise, so that the table
+/// can be sorted by the script of `HTML_PAGE`. Cells of fields without a value are sorted first.
+fn to_html(res: &[lsmp3::Entry], columns: &[Column], options: &TableOptions) -> String {
+    if res.is_empty() {
+        return Default::default();
+    }
+    let (headers, rows) = table_cells(res, columns, options);
+    let size_index = headers.iter().position(|h| h.eq_ignore_ascii_case("SIZE"));
+    let mut html = String::from("<table>\n<thead>\n<tr>");
+    for header in &headers {
+        html += &format!("<th scope=\"col\">{}</th>", escape_html(header));
+    }
+    html += "</tr>\n</thead>\n<tbody>\n";
+    for (entry, row) in res.iter().zip(rows) {
+        html += "<tr>";
+        for (i, field) in row.iter().enumerate() {
+            let sort = if Some(i) == size_index {
+                entry.size.to_string()
+            } else {
+                field.clone()
+            };
+            // Sizes are padded to align in tables, which HTML doesn't need.
+            let text = if field.is_empty() {
+                options.empty_placeholder
+            } else {
+                field.trim()
+            };
+            html += &format!("<td data-sort=\"{}\">{}</td>", escape_html(&sort), escape_html(text));
+        }
+        html += "</tr>\n";
+    }
+    html + "</tbody>\n</table>\n"
+}
+
+/// The standalone page that HTML output is embedded in, with `{title}` and `{body}` to fill in. Clicking the header of a
+/// column sorts the rows of its table by the column, in ascending and then descending order.
+const HTML_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body { font-family: sans-serif; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { padding: 0.2em 0.8em; text-align: left; }
+th { cursor: pointer; user-select: none; }
+th[data-order="asc"]::after { content: " \25b2"; }
+th[data-order="desc"]::after { content: " \25bc"; }
+tbody tr:nth-child(even) { background: #f2f2f2; }
+</style>
+</head>
+<body>
+{body}<script>
+document.querySelectorAll("th").forEach((th) => th.addEventListener("click", () => {
+  const table = th.closest("table");
+  const ascending = th.dataset.order !== "asc";
+  table.querySelectorAll("th").forEach((h) => delete h.dataset.order);
+  th.dataset.order = ascending ? "asc" : "desc";
+  const key = (row) => row.cells[th.cellIndex].dataset.sort;
+  const rows = Array.from(table.tBodies[0].rows).sort((a, b) =>
+    key(a).localeCompare(key(b), undefined, { numeric: true }) * (ascending ? 1 : -1));
+  table.tBodies[0].append(...rows);
+}));
+</script>
+</body>
+</html>
+"#;
+
+/// Converts the sections to a standalone HTML page, with a heading for the path of each section that has one.
+fn sections_to_html(sections: &[Section], to_html: impl Fn(&[lsmp3::Entry]) -> String) -> String {
+    let body: String = sections
+        .iter()
+        .map(|section| match &section.path {
+            Some(path) => format!("<h2>{}</h2>\n{}", escape_html(path), to_html(&section.entries)),
+            None => to_html(&section.entries),
+        })
+        .collect();
+    HTML_PAGE.replace("{title}", "lsmp3").replace("{body}", &body)
+}
+
+fn estimate_to_table(estimate: &lsmp3::Estimate, human_readable: bool) -> String {
+    let mut builder = Builder::default();
+    builder.add_record(["FILES".to_string(), estimate.files.to_string()]);
+    builder.add_record([
+        "SIZE".to_string(),
+        if human_readable {
+            lsmp3::human_readable_size(&estimate.size)
+        } else {
+            estimate.size.to_string()
+        },
+    ]);
+    build_table(builder)
+}
+
+/// The tables created by `to_sqlite`. Albums and artists are stored once and referenced by the tracks, and the artists of
+/// each track are kept in their original order.
+const SQL_SCHEMA: &str = "\
+CREATE TABLE albums (id INTEGER PRIMARY KEY, title TEXT NOT NULL UNIQUE);
+CREATE TABLE artists (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+CREATE TABLE tracks (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    title TEXT,
+    album_id INTEGER REFERENCES albums (id),
+    year INTEGER,
+    track INTEGER,
+    track_total INTEGER,
+    disc INTEGER,
+    genre TEXT
+);
+CREATE TABLE track_artists (
+    track_id INTEGER NOT NULL REFERENCES tracks (id),
+    artist_id INTEGER NOT NULL REFERENCES artists (id),
+    position INTEGER NOT NULL,
+    PRIMARY KEY (track_id, position)
+);
+";
+
+/// Returns the id of a value in a table of `SQL_SCHEMA` that has an id and a unique text, inserting the value if it's
+/// new.
+fn sqlite_id(
+    ids: &mut BTreeMap<String, usize>,
+    insert: &mut rusqlite::Statement,
+    value: &str,
+) -> rusqlite::Result<usize> {
+    if let Some(id) = ids.get(value) {
+        return Ok(*id);
+    }
+    let id = ids.len() + 1;
+    insert.execute(rusqlite::params![id, value])?;
+    ids.insert(value.to_owned(), id);
+    Ok(id)
+}
+
+/// Writes the results to the SQLite database at `path`, creating it if needed, in the tables of `SQL_SCHEMA`. The
+/// tables of an earlier run are replaced, and the whole database is written in a single transaction. Multiple values of
+/// the album, title and genre are joined with a "/", and empty values are stored as NULL.
+fn to_sqlite(results: &[lsmp3::Info], path: &Path) -> rusqlite::Result<()> {
+    let mut db = rusqlite::Connection::open(path)?;
+    let tx = db.transaction()?;
+    tx.execute_batch(
+        "DROP TABLE IF EXISTS track_artists;
+        DROP TABLE IF EXISTS tracks;
+        DROP TABLE IF EXISTS artists;
+        DROP TABLE IF EXISTS albums;",
+    )?;
+    tx.execute_batch(SQL_SCHEMA)?;
+    {
+        let mut insert_album = tx.prepare("INSERT INTO albums VALUES (?1, ?2)")?;
+        let mut insert_artist = tx.prepare("INSERT INTO artists VALUES (?1, ?2)")?;
+        let mut insert_track = tx.prepare("INSERT INTO tracks VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)")?;
+        let mut insert_track_artist = tx.prepare("INSERT INTO track_artists VALUES (?1, ?2, ?3)")?;
+        let mut albums = BTreeMap::new();
+        let mut artists = BTreeMap::new();
+        let text = |values: &[String]| Some(values.join("/")).filter(|v| !v.is_empty());
+        let entries = results.iter().flat_map(|info| info.entry_paths().zip(&info.entries));
+        for (id, (path, entry)) in (1..).zip(entries) {
+            let album = match text(&entry.album) {
+                Some(album) => Some(sqlite_id(&mut albums, &mut insert_album, &album)?),
+                None => None,
+            };
+            insert_track.execute(rusqlite::params![
+                id,
+                path.to_string_lossy(),
+                entry.size,
+                text(&entry.title),
+                album,
+                entry.year,
+                entry.track.number,
+                entry.track.total,
+                entry.disc,
+                text(&entry.genre),
+            ])?;
+            for (position, artist) in entry.artist.iter().enumerate() {
+                let artist_id = sqlite_id(&mut artists, &mut insert_artist, artist)?;
+                insert_track_artist.execute(rusqlite::params![id, artist_id, position])?;
+            }
+        }
+    }
+    tx.commit()
+}
+
+/// Returns the percentage of `count` out of `total`, or zero if there are none.
+fn percentage(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+fn coverage_to_table(stats: &lsmp3::Stats) -> String {
+    let mut builder = Builder::default();
+    builder.set_columns(["FIELD", "FILES", "PERCENT"]);
+    for c in &stats.coverage {
+        builder.add_record([
+            c.value.clone(),
+            c.count.to_string(),
+            format!("{:.0}%", percentage(c.count, stats.files)),
+        ]);
+    }
+    build_table(builder)
+}
+
+fn coverage_to_json(stats: &lsmp3::Stats) -> Value {
+    Value::Array(
+        stats
+            .coverage
+            .iter()
+            .map(|c| {
+                json!({
+                    "field": c.value,
+                    "count": c.count,
+                    "percent": percentage(c.count, stats.files),
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Formats a time in milliseconds as hours, minutes, seconds and milliseconds, e.g. "1:02:03.450".
+fn format_time_ms(ms: u32) -> String {
+    let secs = ms / 1000;
+    format!(
+        "{}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        ms % 1000
+    )
+}
+
+fn chapters_to_table(chapters: &[lsmp3::Chapter]) -> String {
+    if chapters.is_empty() {
+        return Default::default();
+    }
+    let mut builder = Builder::default();
+    builder.set_columns(["#", "START", "END", "TITLE"]);
+    for (i, chapter) in chapters.iter().enumerate() {
+        builder.add_record([
+            (i + 1).to_string(),
+            format_time_ms(chapter.start_ms),
+            format_time_ms(chapter.end_ms),
+            chapter.title.clone().unwrap_or_default(),
+        ]);
+    }
+    build_table(builder)
+}
+
+fn stats_to_table(stats: &lsmp3::Stats, human_readable: bool) -> String {
+    let mut summary = Builder::default();
+    summary.add_record(["FILES".to_string(), stats.files.to_string()]);
+    summary.add_record([
+        "SIZE".to_string(),
+        if human_readable {
+            lsmp3::human_readable_size(&stats.size)
+        } else {
+            stats.size.to_string()
+        },
+    ]);
+    let mut tables = vec![build_table(summary)];
+    for (header, counts) in [
+        ("GENRE", &stats.genres),
+        ("ARTIST", &stats.artists),
+        ("DECADE", &stats.decades),
+    ] {
+        if !counts.is_empty() {
+            tables.push(counts_to_table(header, counts));
+        }
+    }
+    tables.join("\n")
+}
+
+/// The options for converting entries to JSON.
+#[derive(Debug, Default, Copy, Clone)]
+struct JsonOptions {
+    /// Whether to output the numeric fields as strings, e.g. `"year": "2020"`.
+    strings: bool,
+    /// Whether to add the 1-based position of each entry as its "rank".
+    rank: bool,
+}
+
+/// Converts entries to JSON.
+fn to_json(res: &[lsmp3::Entry], options: &JsonOptions) -> Value {
+    let mut value = serde_json::to_value(res).unwrap_or_else(|err| error(err));
+    for (i, entry) in value.as_array_mut().into_iter().flatten().enumerate() {
+        if options.rank {
+            entry["rank"] = json!(i + 1);
+        }
+        if options.strings {
+            stringify_numbers(entry);
+        }
+    }
+    value
+}
+
+/// Replaces the size, year, track number and total, and rank of an entry in JSON with their string representation.
+fn stringify_numbers(entry: &mut Value) {
+    let stringify = |value: Option<&mut Value>| {
+        if let Some(value) = value.filter(|v| v.is_number()) {
+            *value = Value::String(value.to_string());
+        }
+    };
+    stringify(entry.get_mut("size"));
+    stringify(entry.get_mut("year"));
+    stringify(entry.get_mut("rank"));
+    if let Some(track) = entry.get_mut("track") {
+        stringify(track.get_mut("number"));
+        stringify(track.get_mut("total"));
+    }
+}
+
+/// A group of entries in the output, headed by the path of the listed directory if present.
+struct Section {
+    path: Option<String>,
+    entries: Vec<lsmp3::Entry>,
+}
+
+/// Groups the results into the sections that are output. A single result is output as one section without a path,
+/// unless `always_header` is set. Otherwise the files are merged into one section without a path, followed by a section
+/// for each directory. Merged files from more than one directory have the directory of each file as its source.
+fn results_to_sections(results: Vec<lsmp3::Info>, options: &lsmp3::ListOptions, always_header: bool) -> Vec<Section> {
+    let mut sections = Vec::with_capacity(results.len());
+    if results.len() == 1 && !always_header {
+        sections.extend(results.into_iter().map(|f| Section {
+            path: None,
+            entries: f.entries,
+        }));
+    } else {
+        let (files, dirs): (Vec<_>, Vec<_>) = results.into_iter().partition(|f| f.path_type == lsmp3::PathType::File);
+        if !files.is_empty() {
+            let dir = |f: &lsmp3::Info| match Path::new(&f.path).parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy().to_string(),
+                _ => ".".to_owned(),
+            };
+            let sources = files.iter().map(dir).collect::<BTreeSet<_>>().len();
+            let mut f = files
+                .into_iter()
+                .flat_map(|f| {
+                    let source_dir = Some(dir(&f)).filter(|_| sources > 1);
+                    f.entries.into_iter().map(move |entry| lsmp3::Entry {
+                        source_dir: source_dir.clone(),
+                        ..entry
+                    })
+                })
+                .collect::<Vec<_>>();
+            lsmp3::sort_entries(&mut f, options);
+            sections.push(Section { path: None, entries: f });
+        }
+        sections.extend(dirs.into_iter().map(|f| Section {
+            path: Some(f.path),
+            entries: f.entries,
+        }));
+    }
+    sections
+}
+
+/// Returns the columns to show for the entries of a section, adding the source of each entry if they have one, i.e. if
+/// they come from more than one directory, which would otherwise be ambiguous.
+fn section_columns(entries: &[lsmp3::Entry], columns: &[Column]) -> Vec<Column> {
+    let mut columns = columns.to_vec();
+    if entries.iter().any(|e| e.source_dir.is_some()) {
+        columns.insert(0, Column::Source);
+    }
+    columns
+}
+
+/// Converts the sections to the JSON output. A section with a path is output as an object with the path and its
+/// entries, otherwise as a plain list of entries. The entries of each section are converted with `entries_to_json`.
+fn sections_to_json(sections: &[Section], entries_to_json: impl Fn(&[lsmp3::Entry]) -> Value) -> Value {
+    let mut values: Vec<_> = sections
+        .iter()
+        .map(|s| match &s.path {
+            Some(path) => json!({
+                "path": path,
+                "values": entries_to_json(&s.entries),
+            }),
+            None => entries_to_json(&s.entries),
+        })
+        .collect();
+    if values.len() == 1 {
+        values.swap_remove(0)
+    } else {
+        Value::Array(values)
+    }
+}
+
+/// Prints the text of each section, separated by empty lines. Sections with a path are headed by it, and say so
+/// explicitly if they have no entries, so that empty directories aren't mistaken for missing output.
+fn print_sections(
+    out: &mut dyn Write,
+    sections: &[Section],
+    to_text: impl Fn(&[lsmp3::Entry]) -> String,
+) -> io::Result<()> {
+    for (i, section) in sections.iter().enumerate() {
+        match &section.path {
+            Some(path) if section.entries.is_empty() => writeln!(out, "{}:\n(no MP3s)", path)?,
+            Some(path) => write!(out, "{}:\n{}", path, to_text(&section.entries))?,
+            None => write!(out, "{}", to_text(&section.entries))?,
+        }
+        if i < sections.len() - 1 {
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Wraps the JSON output in an object along with the version of lsmp3 and of the shape of the output.
+fn envelope(results: Value) -> Value {
+    json!({
+        "lsmp3_version": env!("CARGO_PKG_VERSION"),
+        "schema_version": lsmp3::SCHEMA_VERSION,
+        "results": results,
+    })
+}
+
+/// Collects the entries from JSON output, keyed by their path. Entries listed under a directory are keyed by the
+/// directory path joined with the file name, and all other entries by their file name.
+fn json_to_keyed_entries(
+    value: Value,
+    dir: Option<&str>,
+    keyed: &mut Vec<(String, lsmp3::Entry)>,
+) -> Result<(), serde_json::Error> {
+    match value {
+        Value::Array(values) => {
+            for value in values {
+                json_to_keyed_entries(value, dir, keyed)?;
+            }
+        }
+        Value::Object(mut map) if map.contains_key("schema_version") => {
+            json_to_keyed_entries(map.remove("results").unwrap_or_default(), dir, keyed)?;
+        }
+        Value::Object(mut map) if map.contains_key("values") => {
+            let path = map.remove("path").map(serde_json::from_value::<String>).transpose()?;
+            json_to_keyed_entries(map.remove("values").unwrap_or_default(), path.as_deref(), keyed)?;
+        }
+        value => {
+            let entry: lsmp3::Entry = serde_json::from_value(value)?;
+            let path = match dir {
+                Some(dir) => Path::new(dir).join(&entry.name),
+                None => PathBuf::from(&entry.name),
+            };
+            keyed.push((path.to_string_lossy().to_string(), entry));
+        }
+    }
+    Ok(())
+}
+
+/// Reads the keyed entries from a JSON listing saved to a file.
+fn read_snapshot(path: &str) -> Result<Vec<(String, lsmp3::Entry)>, lsmp3::LsError> {
+    let err = |err: io::Error| lsmp3::LsError::IoReadError(path.into(), err);
+    let mut keyed = Vec::new();
+    json_to_keyed_entries(
+        serde_json::from_slice(&fs::read(path).map_err(err)?).map_err(|e| err(e.into()))?,
+        None,
+        &mut keyed,
+    )
+    .map_err(|e| err(e.into()))?;
+    Ok(keyed)
+}
+
+fn comparison_to_text(comparison: &lsmp3::Comparison) -> String {
+    let mut summary = Builder::default();
+    summary.add_record(["ADDED".to_string(), comparison.added.len().to_string()]);
+    summary.add_record(["REMOVED".to_string(), comparison.removed.len().to_string()]);
+    summary.add_record(["CHANGED".to_string(), comparison.changed.len().to_string()]);
+    let mut text = build_table(summary);
+    if !comparison.is_empty() {
+        text.push('\n');
+    }
+    for path in &comparison.added {
+        text.push_str(&format!("+ {}\n", path));
+    }
+    for path in &comparison.removed {
+        text.push_str(&format!("- {}\n", path));
+    }
+    for changed in &comparison.changed {
+        text.push_str(&format!("~ {}\n", changed.path));
+        for change in &changed.changes {
+            text.push_str(&format!("    {}: {} -> {}\n", change.field, change.old, change.new));
+        }
+    }
+    text
+}
+
+/// Saves the cache to the file it was loaded from, if caching is enabled.
+fn save_cache(cache: &Option<lsmp3::Cache>, args: &Args) {
+    if let (Some(cache), Some(path)) = (cache, &args.cache) {
+        cache.save(path).unwrap_or_else(|err| error(err));
+    }
+}
+
+/// Replaces control characters with "\xNN" escapes. All control characters are at most U+009F, so they fit in two
+/// hex digits.
+fn escape_control(s: &str) -> Cow<'_, str> {
+    if s.chars().any(char::is_control) {
+        Cow::Owned(
+            s.chars()
+                .map(|c| match c {
+                    c if c.is_control() => format!("\\x{:02x}", u32::from(c)),
+                    c => c.to_string(),
+                })
+                .collect(),
+        )
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+/// Escapes control characters in each line of a text, keeping the line breaks.
+fn escape_control_lines(text: &str) -> String {
+    text.split('\n').map(escape_control).collect::<Vec<_>>().join("\n")
+}
+
+/// Escapes control characters in an OS string, leaving it untouched if it has none.
+fn escape_control_os(s: &mut OsString) {
+    if let Cow::Owned(escaped) = escape_control(&s.to_string_lossy()) {
+        *s = escaped.into();
+    }
+}
+
+/// Escapes control characters in all displayed names and tags of the results.
+fn escape_results(results: &mut [lsmp3::Info]) {
+    fn escape(s: &mut String) {
+        if let Cow::Owned(escaped) = escape_control(s) {
+            *s = escaped;
+        }
+    }
+    for info in results {
+        escape(&mut info.path);
+        for skipped in &mut info.skipped {
+            escape_control_os(&mut skipped.name);
+            escape(&mut skipped.reason);
+        }
+        for entry in &mut info.entries {
+            escape_control_os(&mut entry.name);
+            for values in entry.text_values_mut() {
+                values.iter_mut().for_each(escape);
+            }
+            entry.link_target.iter_mut().for_each(escape);
+        }
+    }
+}
+
+/// Makes the path of a result absolute, resolving "." and ".." and symlinked directories. A listed file keeps its own
+/// name, even if it's a symlink, so that it still matches its entry. Paths that can't be resolved are kept as is.
+fn absolutize_path(info: &mut lsmp3::Info) {
+    let path = Path::new(&info.path);
+    let absolute = match (info.path_type == lsmp3::PathType::File, path.file_name()) {
+        (true, Some(name)) => {
+            let parent = path
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            fs::canonicalize(parent).map(|p| p.join(name))
+        }
+        _ => fs::canonicalize(path),
+    };
+    if let Ok(absolute) = absolute {
+        info.path = absolute.to_string_lossy().to_string();
+    }
+}
+
+/// Merges the results of the subdirectories of each listed directory into the result of the directory itself, renaming
+/// their entries to their path relative to it and sorting the merged entries. Subdirectories are listed right after
+/// their parent, so each directory result starts a new merged result unless it is within the previous one.
+fn flatten_results(results: Vec<lsmp3::Info>, options: &lsmp3::ListOptions) -> Vec<lsmp3::Info> {
+    let mut flattened: Vec<lsmp3::Info> = Vec::with_capacity(results.len());
+    for info in results {
+        let root = flattened
+            .last_mut()
+            .filter(|root| root.path_type == lsmp3::PathType::Directory)
+            .filter(|root| {
+                info.path_type == lsmp3::PathType::Directory && Path::new(&info.path).starts_with(&root.path)
+            });
+        match root {
+            Some(root) => {
+                let prefix = Path::new(&info.path)
+                    .strip_prefix(&root.path)
+                    .unwrap_or(Path::new(""))
+                    .to_owned();
+                root.entries.extend(info.entries.into_iter().map(|entry| lsmp3::Entry {
+                    name: prefix.join(&entry.name).into_os_string(),
+                    ..entry
+                }));
+                root.skipped
+                    .extend(info.skipped.into_iter().map(|skipped| lsmp3::Skipped {
+                        name: prefix.join(&skipped.name).into_os_string(),
+                        ..skipped
+                    }));
+            }
+            None => flattened.push(info),
+        }
+    }
+    for info in &mut flattened {
+        lsmp3::sort_entries(&mut info.entries, options);
+    }
+    flattened
+}
+
+/// Copies or moves each listed file to the path rendered from the template within `dest`, printing each operation.
+/// All paths are planned first, so that nothing is done if two files would end up at the same path.
+fn rename_files(
+    results: &[lsmp3::Info],
+    template: &lsmp3::Template,
+    dest: &Path,
+    move_files: bool,
+    dry_run: bool,
+) -> Result<(), lsmp3::LsError> {
+    let mut targets = BTreeSet::new();
+    let mut renames = Vec::new();
+    for info in results {
+        for (from, entry) in info.entry_paths().zip(&info.entries) {
+            let to = dest.join(template.render_path(entry));
+            if !targets.insert(to.clone()) {
+                return Err(lsmp3::LsError::IoWriteError(
+                    to.into_os_string(),
+                    io::Error::new(io::ErrorKind::AlreadyExists, "more than one file would be written here"),
+                ));
+            }
+            renames.push((from, to));
+        }
+    }
+    let mut stdout = io::stdout().lock();
+    for (from, to) in renames {
+        _ = writeln!(stdout, "{} -> {}", from.to_string_lossy(), to.to_string_lossy());
+        if !dry_run {
+            lsmp3::organize_file(&from, &to, move_files)?;
+        }
+    }
+    Ok(())
+}
+
+/// Formats the time a scan of `files` files took, along with the throughput.
+fn format_timing(files: usize, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs_f64();
+    let per_sec = if secs > 0.0 { files as f64 / secs } else { 0.0 };
+    format!(
+        "listed {} file{} in {:.3}s ({:.1} files/s)",
+        files,
+        if files == 1 { "" } else { "s" },
+        secs,
+        per_sec
+    )
+}
+
+/// Builds the art manifest of the results, which lists the files of each album, in the order the albums are first found,
+/// along with the cover art embedded in each file. Files without an album are listed under a `null` album.
+fn art_manifest(results: &[lsmp3::Info]) -> Value {
+    let mut albums: Vec<(Option<String>, Vec<Value>)> = Vec::new();
+    for info in results {
+        for (path, entry) in info.entry_paths().zip(&info.entries) {
+            let artwork = lsmp3::read_artwork(&path).unwrap_or_else(|err| error(err));
+            let track = json!({
+                "file": path.to_string_lossy(),
+                "has_art": artwork.is_some(),
+                "width": artwork.as_ref().and_then(|a| a.width),
+                "height": artwork.as_ref().and_then(|a| a.height),
+                "mime": artwork.map(|a| a.mime),
+            });
+            let album = Some(entry.album.join("/")).filter(|a| !a.is_empty());
+            match albums.iter_mut().find(|(a, _)| *a == album) {
+                Some((_, tracks)) => tracks.push(track),
+                None => albums.push((album, vec![track])),
+            }
+        }
+    }
+    Value::Array(
+        albums
+            .into_iter()
+            .map(|(album, tracks)| json!({ "album": album, "tracks": tracks }))
+            .collect(),
+    )
+}
+
+/// Writes text to stdout, transcoded to the encoding.
+fn write_encoded(text: &str, encoding: &'static Encoding) {
+    let (encoded, _, _) = encoding.encode(text);
+    io::stdout().lock().write_all(&encoded).unwrap_or_else(|err| error(err));
+}
+
+/// Writes a report other than a listing, e.g. an estimate or chapters, to stdout. Like listings, text reports have
+/// their control characters escaped and all reports are transcoded with `--encoding`.
+fn write_report(text: &str, args: &Args, format: Format) {
+    let text = match format {
+        Format::Table | Format::Html | Format::Sqlite if args.escape_control() => {
+            Cow::Owned(escape_control_lines(text))
+        }
+        _ => Cow::Borrowed(text),
+    };
+    match args.encoding {
+        Some(encoding) => write_encoded(&text, encoding),
+        None => io::stdout()
+            .lock()
+            .write_all(text.as_bytes())
+            .unwrap_or_else(|err| error(err)),
+    }
+}
+
+/// Writes the results to `out` in the output mode and format selected by the arguments. Returns whether validation
+/// found any issues, in which case the run should fail.
+fn print_results(
+    out: &mut dyn Write,
+    mut results: Vec<lsmp3::Info>,
+    args: &Args,
+    options: &lsmp3::ListOptions,
+    format: Format,
+) -> io::Result<bool> {
+    // Reports that read the files again open them by their paths as listed, which are resolved before escaping, while
+    // the escaped paths are the ones printed.
+    let paths: Vec<PathBuf> = results.iter().flat_map(|info| info.entry_paths()).collect();
+    // Compared entries are matched against a snapshot by name, so they must stay verbatim, and the art manifest is JSON.
+    if format == Format::Table && args.compare.is_none() && !args.art_manifest && args.escape_control() {
+        escape_results(&mut results);
+    }
+    if args.list_skipped {
+        let skipped = results.iter().flat_map(|info| {
+            info.skipped
+                .iter()
+                .map(move |s| (Path::new(&info.path).join(&s.name), &s.reason))
+        });
+        match format {
+            Format::Table | Format::Html | Format::Sqlite => {
+                for (path, reason) in skipped {
+                    writeln!(out, "{}: {}", path.to_string_lossy(), reason)?;
+                }
+            }
+            Format::Json => write!(
+                out,
+                "{}",
+                Value::Array(
+                    skipped
+                        .map(|(path, reason)| json!({
+                            "path": path.to_string_lossy(),
+                            "reason": reason,
+                        }))
+                        .collect()
+                )
+            )?,
+        }
+        return Ok(false);
+    }
+    if args.validate {
+        let issues = validate_results(&results);
+        match format {
+            Format::Table | Format::Html | Format::Sqlite => {
+                for (path, issue) in &issues {
+                    writeln!(out, "{}: {}", path.to_string_lossy(), issue)?;
+                }
+            }
+            Format::Json => write!(
+                out,
+                "{}",
+                Value::Array(
+                    issues
+                        .iter()
+                        .map(|(path, issue)| json!({
+                            "path": path.to_string_lossy(),
+                            "issue": issue.to_string(),
+                        }))
+                        .collect()
+                )
+            )?,
+        }
+        return Ok(!issues.is_empty());
+    }
+    if args.verify_audio {
+        let mut defects = Vec::new();
+        let mut unreadable = false;
+        for (path, shown) in paths.iter().zip(results.iter().flat_map(|info| info.entry_paths())) {
+            match lsmp3::verify_audio(path) {
+                Ok(found) => defects.extend(found.into_iter().map(|defect| (shown.clone(), defect))),
+                // A file that can't be read fails the run, but the other files are still verified.
+                Err(err) => {
+                    print_error(&err);
+                    unreadable = true;
+                }
+            }
+        }
+        match format {
+            Format::Table | Format::Html | Format::Sqlite => {
+                for (path, defect) in &defects {
+                    writeln!(out, "{}: {}", path.to_string_lossy(), defect)?;
+                }
+            }
+            Format::Json => write!(
+                out,
+                "{}",
+                Value::Array(
+                    defects
+                        .iter()
+                        .map(|(path, defect)| json!({
+                            "path": path.to_string_lossy(),
+                            "issue": defect.to_string(),
+                        }))
+                        .collect()
+                )
+            )?,
+        }
+        return Ok(unreadable || !defects.is_empty());
+    }
+    if args.art_manifest {
+        write!(out, "{}", art_manifest(&results))?;
+        return Ok(false);
+    }
+    if args.show_encoding {
+        let mut files = Vec::new();
+        let mut unreadable = false;
+        for (path, shown) in paths.iter().zip(results.iter().flat_map(|info| info.entry_paths())) {
+            match lsmp3::read_frame_encodings(path) {
+                Ok(frames) => files.push((shown, frames)),
+                // As with --verify-audio, a file that can't be read fails the run without stopping the others.
+                Err(err) => {
+                    print_error(&err);
+                    unreadable = true;
+                }
+            }
+        }
+        match format {
+            Format::Table | Format::Html | Format::Sqlite => write!(out, "{}", encodings_to_text(&files))?,
+            Format::Json => write!(
+                out,
+                "{}",
+                Value::Array(
+                    files
+                        .iter()
+                        .map(|(path, frames)| json!({
+                            "path": path.to_string_lossy(),
+                            "frames": frames,
+                        }))
+                        .collect()
+                )
+            )?,
+        }
+        return Ok(unreadable);
+    }
+    if let Some(count_by) = args.count_by {
+        let counts = lsmp3::count_values(results.iter().flat_map(|f| &f.entries).flat_map(|e| count_by.values(e)));
+        match format {
+            Format::Table | Format::Html | Format::Sqlite if counts.is_empty() => {}
+            Format::Table | Format::Html | Format::Sqlite => {
+                write!(out, "{}", counts_to_table(count_by.header(), &counts))?
+            }
+            Format::Json => write!(out, "{}", serde_json::to_string(&counts)?)?,
+        }
+        return Ok(false);
+    }
+    if args.stats {
+        let stats = lsmp3::aggregate(results.iter().flat_map(|f| &f.entries), Some(args.stats_top));
+        match format {
+            Format::Table | Format::Html | Format::Sqlite => write!(out, "{}", stats_to_table(&stats, !args.bytes))?,
+            Format::Json => write!(out, "{}", serde_json::to_string(&stats)?)?,
+        }
+        return Ok(false);
+    }
+    if let (Format::Sqlite, Some(path)) = (format, &args.output) {
+        to_sqlite(&results, path).unwrap_or_else(|err| error(err));
+        return Ok(false);
+    }
+    if args.tag_coverage {
+        let stats = lsmp3::aggregate(results.iter().flat_map(|f| &f.entries), None);
+        match format {
+            Format::Table | Format::Html | Format::Sqlite => write!(out, "{}", coverage_to_table(&stats))?,
+            Format::Json => write!(out, "{}", coverage_to_json(&stats))?,
+        }
+        return Ok(false);
+    }
+    if let Some(snapshot) = &args.compare {
+        let old_entries = read_snapshot(snapshot).unwrap_or_else(|err| error(err));
+        let mut new_entries = Vec::new();
+        json_to_keyed_entries(
+            sections_to_json(&results_to_sections(results, options, false), |entries| {
+                to_json(entries, &Default::default())
+            }),
+            None,
+            &mut new_entries,
+        )?;
+        let comparison = lsmp3::compare(old_entries, new_entries);
+        match format {
+            Format::Table | Format::Html | Format::Sqlite => write!(out, "{}", comparison_to_text(&comparison))?,
+            Format::Json => write!(out, "{}", serde_json::to_string(&comparison)?)?,
+        }
+        return Ok(false);
+    }
+    let mut sections = results_to_sections(results, options, args.always_header && format == Format::Table);
+    if let Some(query) = &args.search {
+        for section in &mut sections {
+            rank_entries(&mut section.entries, query);
+        }
+    }
+    if let Some(template) = args.template.as_ref().filter(|_| args.template_only) {
+        print_sections(out, &sections, |entries| {
+            entries.iter().map(|e| template.render(e) + "\n").collect()
+        })?;
+        return Ok(false);
+    }
+    let columns = args.columns();
+    if let Some(dir) = &args.split_output {
+        let entries: Vec<_> = sections.into_iter().flat_map(|s| s.entries).collect();
+        write_split_output(dir, &entries, args, &columns, format).unwrap_or_else(|err| error(err));
+        return Ok(false);
+    }
+    match format {
+        Format::Table => print_sections(out, &sections, |entries| {
+            entries_to_table(entries, args, &section_columns(entries, &columns))
+        })?,
+        Format::Json => {
+            let value = sections_to_json(&sections, |entries| entries_to_json(entries, args));
+            let value = if args.envelope { envelope(value) } else { value };
+            write!(out, "{}", serde_json::to_string(&value)?)?
+        }
+        Format::Html => write!(
+            out,
+            "{}",
+            sections_to_html(&sections, |entries| {
+                entries_to_html(entries, args, &section_columns(entries, &columns))
+            })
+        )?,
+        Format::Sqlite => unreachable!("SQLite output is written before the sections"),
+    }
+    Ok(false)
+}
+
+/// Parses the amount of files to sample, either a percentage between 0 and 100 like "10%" or a number of files.
+fn parse_sample(s: &str) -> Result<lsmp3::Sample, String> {
+    match s.trim().strip_suffix('%') {
+        Some(percent) => match percent.trim().parse::<f64>() {
+            Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(lsmp3::Sample::Percent(percent)),
+            Ok(_) => Err("the percentage must be between 0 and 100".to_owned()),
+            Err(err) => Err(format!("{}", err)),
+        },
+        None => s
+            .trim()
+            .parse()
+            .map(lsmp3::Sample::Count)
+            .map_err(|err| format!("{}", err)),
+    }
+}
+
+/// Parses a point in time as seconds since the Unix epoch, either a date like "2024-01-01", which is midnight UTC, or a
+/// time ago like "7d" relative to now.
+fn parse_time(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let date: Vec<_> = s.split('-').collect();
+    if let [year, month, day] = date[..] {
+        let parse = |part: &str| part.parse::<i64>().map_err(|_| format!("invalid date {:?}", s));
+        let (year, month, day) = (parse(year)?, parse(month)?, parse(day)?);
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return Err(format!("invalid date {:?}", s));
+        }
+        return u64::try_from(days_from_civil(year, month, day) * 86400).map_err(|_| format!("{:?} is before 1970", s));
+    }
+    let unit = s.len() - s.chars().last().map_or(0, char::len_utf8);
+    let secs = match &s[unit..] {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "expected a date like 2024-01-01 or a time ago like 7d, got {:?}",
+                s
+            ))
+        }
+    };
+    let amount: u64 = s[..unit].trim().parse().map_err(|err| format!("{}", err))?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|err| format!("{}", err))?
+        .as_secs();
+    Ok(now.saturating_sub(amount.saturating_mul(secs)))
+}
+
+/// Returns the number of days from 1970-01-01 to a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Count years from March, so that the leap day is the last day of the year.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parses a timeout in seconds, which may be fractional but must be positive.
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    match s.trim().parse::<f64>() {
+        Ok(secs) if secs > 0.0 => Duration::try_from_secs_f64(secs).map_err(|err| format!("{}", err)),
+        Ok(_) => Err("must be greater than 0".to_owned()),
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
+/// Parses the number of values to show of a multi-value field, which must be at least one.
+fn parse_max_values(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(0) => Err("must be at least 1".to_owned()),
+        Ok(n) => Ok(n),
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
+/// Parses the label of a character encoding, as understood by web browsers, e.g. "shift_jis" or "latin1".
+fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| format!("unknown encoding {:?}", label))
+}
+
+/// Sets the fields of the tags of the files, printing the changes of each file that changes.
+fn run_set(args: &SetArgs) {
+    let values = args.values();
+    let mut stdout = io::stdout().lock();
+    for file in &args.file {
+        let changes = lsmp3::set_tags(file, &values, args.dry_run).unwrap_or_else(|err| error(err));
+        if !changes.is_empty() {
+            _ = writeln!(stdout, "~ {}", file.to_string_lossy());
+            for change in &changes {
+                _ = writeln!(stdout, "    {}: {} -> {}", change.field, change.old, change.new);
+            }
+        }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Set(args)) => run_set(&args),
+        Some(Command::List(args)) => run_list(args),
+        None => run_list(cli.args),
+    }
+}
+
+/// Lists the files selected by the arguments.
+fn run_list(mut args: Args) {
+    let start = Instant::now();
+    ERRORS_JSON.store(args.errors_json, Ordering::Relaxed);
+    args.sort_by = sort_keys(&args);
+    args.articles = args
+        .ignore_articles
+        .iter()
+        .flat_map(|a| a.split(['/', ',']))
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+        .map(str::to_owned)
+        .collect();
+    let format = args.format.unwrap_or_else(default_format);
+    args.encoding = args.encoding.filter(|e| *e != encoding_rs::UTF_8);
+    if args.encoding.is_some() && (format == Format::Json || format == Format::Sqlite || args.json_stream) {
+        Cli::command()
+            .error(
+                clap::ErrorKind::ArgumentConflict,
+                "JSON and SQLite output are always UTF-8, so --encoding can't be used with them",
+            )
+            .exit()
+    }
+    if let Some(report) = args.report_flag() {
+        let name = match format {
+            Format::Html => Some("HTML"),
+            Format::Sqlite => Some("SQLite"),
+            Format::Table | Format::Json => None,
+        };
+        if let Some(name) = name {
+            Cli::command()
+                .error(
+                    clap::ErrorKind::ArgumentConflict,
+                    format!(
+                        "{} output is only available for listings, so it can't be used with {}",
+                        name, report
+                    ),
+                )
+                .exit()
         }
-        Format::Json => {
-            let mut values = Vec::with_capacity(results.len());
-            if results.len() == 1 {
-                values.push(to_json(&results[0].entries));
-            } else {
-                let (files, dirs): (Vec<_>, Vec<_>) =
-                    results.into_iter().partition(|f| f.path_type == lsmp3::PathType::File);
-                if !files.is_empty() {
-                    let mut f = files.into_iter().flat_map(|f| f.entries).collect::<Vec<_>>();
-                    f.sort_unstable_by(|a, b| {
-                        let ord = lsmp3::cmp_entry(a, b, &args.sort_by);
-                        if args.reverse {
-                            ord.reverse()
-                        } else {
-                            ord
-                        }
-                    });
-                    values.push(to_json(&f));
-                }
-                if !dirs.is_empty() {
-                    values.extend(dirs.iter().map(|f| {
-                        json!({
-                            "path": f.path,
-                            "values": to_json(&f.entries),
-                        })
-                    }));
-                }
+    }
+    match (format, &args.output) {
+        (Format::Sqlite, None) => Cli::command()
+            .error(
+                clap::ErrorKind::MissingRequiredArgument,
+                "SQLite output is written to a database file, so --output is required with it",
+            )
+            .exit(),
+        (Format::Table | Format::Json | Format::Html, Some(_)) => Cli::command()
+            .error(
+                clap::ErrorKind::ArgumentConflict,
+                "--output is only used with \"--format sqlite\"",
+            )
+            .exit(),
+        _ => {}
+    }
+    if args.list_sorts || args.list_formats {
+        let values = if args.list_sorts {
+            let mut values = value_names::<lsmp3::SortBy>();
+            values.push("all");
+            values
+        } else {
+            value_names::<Format>()
+        };
+        let text = match format {
+            Format::Table | Format::Html | Format::Sqlite => {
+                values.iter().map(|value| format!("{}\n", value)).collect()
+            }
+            Format::Json => json!(values).to_string(),
+        };
+        write_report(&text, &args, format);
+        return;
+    }
+    let cache = args
+        .cache
+        .as_deref()
+        .map(lsmp3::Cache::load)
+        .transpose()
+        .unwrap_or_else(|err| error(err));
 
-                print!(
-                    "{}",
-                    if values.len() == 1 {
-                        serde_json::to_string(&values[0])
-                    } else {
-                        serde_json::to_string(&values)
-                    }
-                    .unwrap_or_else(|err| error(err))
+    let split_on: Vec<char> = args.split_on.iter().flat_map(|s| s.chars()).collect();
+    args.filter.extend(args.since.map(lsmp3::Predicate::ModifiedSince));
+    args.filter.extend(args.until.map(lsmp3::Predicate::ModifiedUntil));
+    let modified = args.filter.iter().any(lsmp3::Predicate::needs_modified);
+    let audio_properties = args.long || args.filter.iter().any(lsmp3::Predicate::needs_audio);
+    // Validation reports implausible years, so they must be kept instead of treated as missing.
+    let strict_year = args.strict_year || args.validate;
+    let options = lsmp3::ListOptions {
+        sort_by: &args.sort_by,
+        reverse: &args.reverse,
+        recursive: &args.recursive,
+        checksum: &args.checksum,
+        tag_hash: &args.tag_hash,
+        resolve_links: &args.dereference_verbose,
+        strict_year: &strict_year,
+        year_policy: &args.year_policy,
+        deterministic: &args.deterministic_walk,
+        audio_properties: &audio_properties,
+        // Comparing the tagged length requires the duration of the audio.
+        duration: &args.validate,
+        frame_count: &args.frame_count,
+        modified: &modified,
+        skipped: &args.list_skipped,
+        no_tags: &args.no_tags,
+        cache: &cache,
+        merge_tags: &args.merge_tags,
+        wav_aiff: &args.wav_aiff,
+        max_frame_bytes: &args.max_frame_bytes,
+        untagged_last: &args.untagged_last,
+        trim: &args.trim,
+        split_on: &split_on,
+        max_results: &args.max_results,
+        dedup_targets: &args.dedup_targets,
+        follow_first: &args.follow_first,
+        #[cfg(unix)]
+        dedup_inodes: &args.dedup_inodes,
+        ignore_articles: &args.articles,
+        genre_priority: &args.genre_priority,
+        sample: &args.sample,
+        sample_seed: &args.sample_seed,
+        file_timeout: &args.file_timeout,
+        #[cfg(feature = "remote")]
+        remote: &args.remote,
+    };
+    if args.estimate {
+        let estimate = lsmp3::estimate(&args.file, &options).unwrap_or_else(|err| error(err));
+        let text = match format {
+            Format::Table | Format::Html | Format::Sqlite => estimate_to_table(&estimate, !args.bytes),
+            Format::Json => serde_json::to_string(&estimate).unwrap_or_else(|err| error(err)),
+        };
+        write_report(&text, &args, format);
+        return;
+    }
+    if args.bench {
+        let start = Instant::now();
+        let mut files = 0;
+        let options = lsmp3::ListOptions {
+            sort_by: &[],
+            ..options
+        };
+        lsmp3::list_each(&args.file, &options, |info| files += info.entries.len()).unwrap_or_else(|err| error(err));
+        write_report(&format!("{}\n", format_timing(files, start.elapsed())), &args, format);
+        return;
+    }
+    if args.chapters {
+        let [file] = args.file.as_slice() else {
+            Cli::command()
+                .error(
+                    clap::ErrorKind::WrongNumberOfValues,
+                    "--chapters lists the chapters of a single file",
                 )
+                .exit()
+        };
+        let chapters = lsmp3::read_chapters(Path::new(file)).unwrap_or_else(|err| error(err));
+        let text = match format {
+            Format::Table | Format::Html | Format::Sqlite => chapters_to_table(&chapters),
+            Format::Json => serde_json::to_string(&chapters).unwrap_or_else(|err| error(err)),
+        };
+        write_report(&text, &args, format);
+        return;
+    }
+    if args.json_stream {
+        let mut stdout = std::io::stdout().lock();
+        let mut files = 0;
+        let mut listed = 0;
+        lsmp3::list_each(&args.file, &options, |mut info| {
+            if args.path_type.is_some_and(|kind| !kind.matches(&info.path_type)) {
+                return;
+            }
+            files += info.entries.len();
+            if args.absolute_paths {
+                absolutize_path(&mut info);
+            }
+            apply_filters(&mut info, &args.filter);
+            apply_search(&mut info, &args.search);
+            if let Some(query) = &args.search {
+                rank_entries(&mut info.entries, query);
             }
+            listed += info.entries.len();
+            warn_issues(&info, &args);
+            _ = serde_json::to_writer(
+                &mut stdout,
+                &json!({
+                    "path": info.path,
+                    "values": to_json(&info.entries, &args.json_options()),
+                }),
+            );
+            _ = writeln!(stdout);
+            _ = stdout.flush();
+        })
+        .unwrap_or_else(|err| error(err));
+        save_cache(&cache, &args);
+        if args.timing {
+            eprintln!("{}", format_timing(files, start.elapsed()));
+        }
+        if args.fail_if_empty && listed == 0 {
+            std::process::exit(EXIT_EMPTY);
+        }
+        return;
+    }
+
+    let (mut results, errors) = if args.no_fail_fast {
+        lsmp3::list_lenient(&args.file, &options).unwrap_or_else(|err| error(err))
+    } else {
+        (
+            lsmp3::list(&args.file, &options).unwrap_or_else(|err| error(err)),
+            Vec::new(),
+        )
+    };
+    for err in &errors {
+        print_error(err);
+    }
+    if let Some(kind) = args.path_type {
+        results.retain(|info| kind.matches(&info.path_type));
+    }
+    save_cache(&cache, &args);
+    let files = results.iter().map(|info| info.entries.len()).sum();
+    let elapsed = start.elapsed();
+    for info in &mut results {
+        if args.absolute_paths {
+            absolutize_path(info);
+        }
+        apply_filters(info, &args.filter);
+        apply_search(info, &args.search);
+        warn_issues(info, &args);
+    }
+    if args.flatten {
+        results = flatten_results(results, &options);
+    }
+    if let Some(by) = args.sort_dirs {
+        sort_dirs(&mut results, by);
+    }
+    let empty = results.iter().all(|info| info.entries.is_empty());
+    let failed = match (&args.rename, args.encoding) {
+        (Some(template), _) => {
+            // The destination is required along with the template.
+            let dest = args.dest.as_deref().unwrap_or(Path::new("."));
+            rename_files(&results, template, dest, args.move_files, args.dry_run).unwrap_or_else(|err| error(err));
+            false
         }
+        (None, Some(encoding)) => {
+            // Encoding needs the whole output, so it's rendered to a buffer first.
+            let mut buf = Vec::new();
+            let failed = print_results(&mut buf, results, &args, &options, format).unwrap_or_else(|err| error(err));
+            write_encoded(&String::from_utf8_lossy(&buf), encoding);
+            failed
+        }
+        (None, None) => {
+            print_results(&mut io::stdout().lock(), results, &args, &options, format).unwrap_or_else(|err| error(err))
+        }
+    };
+    if args.timing {
+        _ = io::stdout().lock().flush();
+        eprintln!("{}", format_timing(files, elapsed));
+    }
+    if failed || !errors.is_empty() {
+        _ = io::stdout().lock().flush();
+        std::process::exit(1);
+    }
+    if args.fail_if_empty && empty {
+        _ = io::stdout().lock().flush();
+        std::process::exit(EXIT_EMPTY);
     }
 }
 
@@ -194,6 +2971,7 @@ mod tests {
                     total: Some(3),
                 },
                 genre: vec![s!("Trip-Hop"), s!("Hip-Hop")],
+                ..Default::default()
             },
             lsmp3::Entry {
                 name: s!("None.mp3"),
@@ -210,6 +2988,7 @@ mod tests {
                     total: None,
                 },
                 genre: vec![],
+                ..Default::default()
             },
         ]
     }
@@ -217,7 +2996,7 @@ mod tests {
     #[test]
     fn test_to_table() {
         assert_eq!(
-            to_table(&get_test_entries()),
+            to_table(&get_test_entries(), &[], &Default::default()),
             format!(
                 "{}\n{}\n{}\n",
                 " NAME       SIZE      TITLE        ARTIST               ALBUM        YEAR   TRACK   GENRE            ",
@@ -227,10 +3006,260 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_to_table_bytes() {
+        assert_eq!(
+            to_table(
+                &get_test_entries(),
+                &[],
+                &TableOptions {
+                    size_format: SizeFormat::Bytes,
+                    ..Default::default()
+                }
+            ),
+            format!(
+                "{}\n{}\n{}\n",
+                " NAME       SIZE   TITLE        ARTIST               ALBUM        YEAR   TRACK   GENRE            ",
+                " Some.mp3   8080   Two/titles   Three/cool/artists   Dual/Album   2020   2/3     Trip-Hop/Hip-Hop ",
+                " None.mp3   4                                                                                     "
+            )
+        )
+    }
+
+    #[test]
+    fn test_to_table_size_both() {
+        assert_eq!(
+            to_table(
+                &get_test_entries(),
+                &[],
+                &TableOptions {
+                    size_format: SizeFormat::Both,
+                    ..Default::default()
+                }
+            ),
+            format!(
+                "{}\n{}\n{}\n",
+                " NAME       SIZE      BYTES   TITLE        ARTIST               ALBUM        YEAR   TRACK   GENRE            ",
+                " Some.mp3   7.9 kiB   8080    Two/titles   Three/cool/artists   Dual/Album   2020   2/3     Trip-Hop/Hip-Hop ",
+                " None.mp3     4 B     4                                                                                      "
+            )
+        )
+    }
+
+    #[test]
+    fn test_to_table_empty_placeholder() {
+        assert_eq!(
+            to_table(
+                &get_test_entries(),
+                &[Column::Disc],
+                &TableOptions {
+                    empty_placeholder: "-",
+                    ..Default::default()
+                }
+            ),
+            format!(
+                "{}\n{}\n{}\n",
+                " NAME       SIZE      TITLE        ARTIST               ALBUM        YEAR   TRACK   GENRE              DISC ",
+                " Some.mp3   7.9 kiB   Two/titles   Three/cool/artists   Dual/Album   2020   2/3     Trip-Hop/Hip-Hop   -    ",
+                " None.mp3     4 B     -            -                    -            -      -       -                  -    "
+            )
+        )
+    }
+
+    #[test]
+    fn test_to_table_header_case() {
+        let headers = |header_case| {
+            let table = to_table(
+                &get_test_entries(),
+                &[Column::OriginalArtist],
+                &TableOptions {
+                    header_case,
+                    ..Default::default()
+                },
+            );
+            table
+                .lines()
+                .next()
+                .unwrap()
+                .split("   ")
+                .map(str::trim)
+                .filter(|h| !h.is_empty())
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(headers(HeaderCase::Upper)[..2], ["NAME", "SIZE"]);
+        assert_eq!(headers(HeaderCase::Lower)[..2], ["name", "size"]);
+        assert_eq!(headers(HeaderCase::Title).last().unwrap(), "Original Artist");
+        assert_eq!(HeaderCase::Title.apply("TAGGED  LENGTH"), "Tagged  Length");
+    }
+
+    #[test]
+    fn test_to_table_strip_extension() {
+        let mut entries = get_test_entries();
+        entries[1].name = "some/dir/Track.01.mp3".into();
+        let table = to_table(
+            &entries,
+            &[],
+            &TableOptions {
+                strip_extension: true,
+                ..Default::default()
+            },
+        );
+        let names: Vec<_> = table
+            .lines()
+            .map(|line| line.split("   ").next().unwrap().trim())
+            .collect();
+        assert_eq!(names, ["NAME", "Some", "some/dir/Track.01"]);
+    }
+
+    #[test]
+    fn test_to_table_rank() {
+        let table = to_table(
+            &get_test_entries(),
+            &[],
+            &TableOptions {
+                rank: true,
+                ..Default::default()
+            },
+        );
+        let ranks: Vec<_> = table
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(ranks, ["RANK", "1", "2"]);
+
+        let value = to_json(
+            &get_test_entries(),
+            &JsonOptions {
+                strings: true,
+                rank: true,
+            },
+        );
+        assert_eq!((&value[0]["rank"], &value[1]["rank"]), (&json!("1"), &json!("2")));
+    }
+
+    #[test]
+    fn test_to_table_auto_columns() {
+        let mut entries = get_test_entries();
+        entries[0].genre.clear();
+        entries[0].track = lsmp3::Track::default();
+        assert_eq!(
+            to_table(
+                &entries,
+                &[Column::Disc],
+                &TableOptions {
+                    auto_columns: true,
+                    ..Default::default()
+                }
+            ),
+            format!(
+                "{}\n{}\n{}\n",
+                " NAME       SIZE      TITLE        ARTIST               ALBUM        YEAR ",
+                " Some.mp3   7.9 kiB   Two/titles   Three/cool/artists   Dual/Album   2020 ",
+                " None.mp3     4 B                                                         "
+            )
+        )
+    }
+
+    #[test]
+    fn test_to_html() {
+        let mut entries = get_test_entries();
+        entries[0].title = vec!["<b>Rock & \"Roll\"</b>".to_owned()];
+        let html = to_html(
+            &entries,
+            &[],
+            &TableOptions {
+                empty_placeholder: "-",
+                auto_columns: true,
+                ..Default::default()
+            },
+        );
+        assert!(html.starts_with("<table>\n<thead>\n<tr><th scope=\"col\">NAME</th><th scope=\"col\">SIZE</th>"));
+        assert!(html.contains(
+            "<td data-sort=\"Some.mp3\">Some.mp3</td><td data-sort=\"8080\">7.9 kiB</td>\
+             <td data-sort=\"&lt;b&gt;Rock &amp; &quot;Roll&quot;&lt;/b&gt;\">&lt;b&gt;Rock &amp; &quot;Roll&quot;&lt;/b&gt;</td>"
+        ));
+        // Cells without a value show the placeholder but sort first.
+
+        assert!(html.contains("<td data-sort=\"4\">4 B</td><td data-sort=\"\">-</td>"));
+        assert!(html.ends_with("</tbody>\n</table>\n"));
+        assert_eq!(to_html(&[], &[], &Default::default()), "");
+    }
+
+    #[test]
+    fn test_index_entries() {
+        let entry = |artist: &[&str]| lsmp3::Entry {
+            artist: artist.iter().map(|a| a.to_string()).collect(),
+            ..Default::default()
+        };
+        let entries = vec![
+            entry(&["beta"]),
+            entry(&["Alpha"]),
+            entry(&["99 Luftballons"]),
+            entry(&[]),
+            entry(&["Bravo", "Zulu"]),
+            entry(&["Ölfass"]),
+        ];
+        assert_eq!(
+            index_entries(&entries, IndexBy::Artist)
+                .into_iter()
+                .map(|(bucket, entries)| (bucket, entries.len()))
+                .collect::<Vec<_>>(),
+            vec![(s!("#"), 2), (s!("A"), 1), (s!("B"), 2), (s!("Ö"), 1)]
+        );
+        assert_eq!(index_entries(&entries, IndexBy::Artist)[2].1[0], entries[0]);
+    }
+
+    #[test]
+    fn test_group_entries() {
+        let entry = |album: &str, artist: &str| lsmp3::Entry {
+            album: vec![album.to_owned()],
+            artist: vec![artist.to_owned()],
+            ..Default::default()
+        };
+        let entries = vec![
+            entry("Hits", "One"),
+            entry("Solo", "Two"),
+            entry("Hits", "Three"),
+            entry("Solo", "Two"),
+            lsmp3::Entry::default(),
+        ];
+        let groups = group_entries(&entries, GroupBy::Album, true);
+        assert_eq!(
+            groups.iter().map(|g| g.header()).collect::<Vec<_>>(),
+            vec![
+                "[Hits - Various Artists] (2 artists)",
+                "[Solo - Two] (1 artist)",
+                "[(no album)]"
+            ]
+        );
+        assert_eq!(groups[0].entries, vec![entries[0].clone(), entries[2].clone()]);
+        assert_eq!(
+            group_entries(&entries, GroupBy::Album, false)[0].header(),
+            "[Hits] (2 artists)"
+        );
+    }
+
+    #[test]
+    fn test_to_json_strings() {
+        let value = to_json(
+            &get_test_entries(),
+            &JsonOptions {
+                strings: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            (&value[0]["size"], &value[0]["year"], &value[0]["track"]),
+            (&json!("8080"), &json!("2020"), &json!({ "number": "2", "total": "3" }))
+        );
+        assert_eq!((&value[1]["size"], value[1].get("year")), (&json!("4"), None));
+    }
+
     #[test]
     fn test_to_json() {
         assert_eq!(
-            to_json(&get_test_entries()),
+            to_json(&get_test_entries(), &Default::default()),
             json!([
                 {
                     "album": [
@@ -244,6 +3273,7 @@ mod tests {
                     ],
                     "name": "Some.mp3",
                     "size": 8080,
+                    "format": "mp3",
                     "genre": [
                         "Trip-Hop",
                         "Hip-Hop"
@@ -260,14 +3290,582 @@ mod tests {
                 },
                 {
                     "name": "None.mp3",
-                    "size": 4
+                    "size": 4,
+                    "format": "mp3"
                 }
             ])
         )
     }
 
+    #[test]
+    fn test_stats_to_table() {
+        assert_eq!(
+            stats_to_table(&lsmp3::aggregate(&get_test_entries(), Some(1)), true),
+            format!(
+                "{}\n{}\n\n{}\n{}\n\n{}\n{}\n\n{}\n{}\n",
+                " FILES   2       ",
+                " SIZE    7.9 kiB ",
+                " GENRE     COUNT ",
+                " Hip-Hop   1     ",
+                " ARTIST   COUNT ",
+                " Three    1     ",
+                " DECADE   COUNT ",
+                " 2020s    1     "
+            )
+        )
+    }
+
+    #[test]
+    fn test_parse_sort_key() {
+        assert_eq!(parse_sort_key("year"), Ok((lsmp3::SortBy::Year, None)));
+        assert_eq!(
+            parse_sort_key("original-artist:asc"),
+            Ok((lsmp3::SortBy::OriginalArtist, Some(lsmp3::Direction::Ascending)))
+        );
+        assert_eq!(
+            parse_sort_key("Track:DESC"),
+            Ok((lsmp3::SortBy::Track, Some(lsmp3::Direction::Descending)))
+        );
+        assert!(parse_sort_key("track:down").is_err());
+        assert!(parse_sort_key("bitrate").is_err());
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        use lsmp3::{Direction::*, SortBy::*};
+        let args = Cli::parse_from(["lsmp3", "--preset", "chronological"]).args;
+        assert_eq!(
+            sort_keys(&args),
+            vec![(Year, Ascending), (Album, Ascending), (Track, Ascending)]
+        );
+        let args = Cli::parse_from(["lsmp3", "--preset", "chronological", "-s", "track:desc", "-s", "name"]).args;
+        assert_eq!(
+            sort_keys(&args),
+            vec![
+                (Track, Descending),
+                (Name, Ascending),
+                (Year, Ascending),
+                (Album, Ascending)
+            ]
+        );
+        let args = Cli::parse_from(["lsmp3", "-s", "size"]).args;
+        assert_eq!(sort_keys(&args), vec![(Size, Ascending)]);
+        let args = Cli::parse_from(["lsmp3", "-s", "album,track", "-s", "title"]).args;
+        assert_eq!(
+            sort_keys(&args),
+            vec![(Album, Ascending), (Track, Ascending), (Title, Ascending)]
+        );
+        let args = Cli::parse_from(["lsmp3", "--smart-sort"]).args;
+        assert_eq!(sort_keys(&args), vec![(Track, Ascending), (Name, Ascending)]);
+        let args = Cli::parse_from(["lsmp3", "--smart-sort", "-s", "size"]).args;
+        assert_eq!(sort_keys(&args), vec![(Size, Ascending)]);
+        let args = Cli::parse_from(["lsmp3", "-s", "name", "-s", "size", "-s", "name:desc"]).args;
+        assert_eq!(sort_keys(&args), vec![(Name, Ascending), (Size, Ascending)]);
+        let args = Cli::parse_from(["lsmp3", "--smart-direction", "-s", "size,year:asc,title"]).args;
+        assert_eq!(
+            sort_keys(&args),
+            vec![(Size, Descending), (Year, Ascending), (Title, Ascending)]
+        );
+        let args = Cli::parse_from(["lsmp3", "--smart-direction", "--preset", "chronological"]).args;
+        assert_eq!(sort_keys(&args)[0], (Year, Ascending));
+        let args = Cli::parse_from(["lsmp3", "-s", "year:desc", "-s", "all"]).args;
+        assert_eq!(
+            sort_keys(&args),
+            vec![
+                (Year, Descending),
+                (Artist, Ascending),
+                (Album, Ascending),
+                (Track, Ascending),
+                (Title, Ascending),
+                (Name, Ascending)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ignore_articles_args() {
+        let args = Cli::parse_from(["lsmp3", "--ignore-articles", "music"]).args;
+        assert_eq!(
+            (args.ignore_articles.as_deref(), &args.file[..]),
+            (Some("the/a/an"), &[s!("music")][..])
+        );
+        let args = Cli::parse_from(["lsmp3", "--ignore-articles=le/la", "music"]).args;
+        assert_eq!(args.ignore_articles.as_deref(), Some("le/la"));
+    }
+
+    #[test]
+    fn test_parse_sort_keys() {
+        use lsmp3::{Direction::*, SortBy::*};
+        assert_eq!(parse_sort_keys("track:desc"), Ok(vec![(Track, Some(Descending))]));
+        assert_eq!(
+            parse_sort_keys("ALL:desc").unwrap(),
+            ALL_SORT_KEYS.map(|k| (k, Some(Descending))).to_vec()
+        );
+        assert_eq!(
+            parse_sort_keys("all").unwrap(),
+            ALL_SORT_KEYS.map(|k| (k, None)).to_vec()
+        );
+        assert!(parse_sort_keys("all:down").is_err());
+        assert_eq!(
+            parse_sort_keys("album, track:desc,title"),
+            Ok(vec![(Album, None), (Track, Some(Descending)), (Title, None)])
+        );
+        assert!(parse_sort_keys("album,,title").is_err());
+        assert!(parse_sort_keys("album,tracks")
+            .unwrap_err()
+            .starts_with("invalid sort key \"tracks\""));
+    }
+
+    #[test]
+    fn test_counts_to_table() {
+        let entries = get_test_entries();
+        let counts = lsmp3::count_values(entries.iter().flat_map(|e| CountBy::Year.values(e)));
+        assert_eq!(
+            counts_to_table(CountBy::Year.header(), &counts),
+            format!("{}\n{}\n", " YEAR   COUNT ", " 2020   1     ")
+        );
+    }
+
+    #[test]
+    fn test_escape_control() {
+        assert_eq!(escape_control("Plain title"), Cow::Borrowed("Plain title"));
+        assert_eq!(escape_control("\u{1b}[31mRed\u{1b}[0m"), "\\x1b[31mRed\\x1b[0m");
+        assert_eq!(escape_control("Tab\there\u{7f}\u{9b}"), "Tab\\x09here\\x7f\\x9b");
+        assert_eq!(
+            escape_control_lines("Chapter\u{1b}[2J\nEnd\n"),
+            "Chapter\\x1b[2J\nEnd\n"
+        );
+
+        let mut results = vec![lsmp3::Info {
+            path: s!("dir\n"),
+            path_type: lsmp3::PathType::Directory,
+            entries: vec![lsmp3::Entry {
+                name: s!("\u{1b}]0;pwned\u{7}.mp3"),
+                title: vec![s!("a\rb")],
+                artist_sort_order: Some(vec![s!("c\u{8}")]),
+                ..Default::default()
+            }],
+            skipped: vec![],
+        }];
+        escape_results(&mut results);
+        assert_eq!(results[0].path, "dir\\x0a");
+        let entry = &results[0].entries[0];
+        assert_eq!(entry.name, "\\x1b]0;pwned\\x07.mp3");
+        assert_eq!(entry.title, vec![s!("a\\x0db")] as Vec<String>);
+        assert_eq!(entry.artist_sort_order, Some(vec![s!("c\\x08")]));
+    }
+
+    #[test]
+    fn test_print_results_control_name() {
+        let dir = std::env::temp_dir().join("lsmp3-control-name");
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let data = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        fs::copy(data.join("id3v24_some_tags.mp3"), dir.join("a\u{1}b.mp3")).unwrap();
+        let print = |mode: &str| {
+            let dir = dir.to_string_lossy().to_string();
+            let args = Cli::parse_from(["lsmp3", "--escape-control", mode, &dir]).args;
+            let results = lsmp3::list(&args.file, &Default::default()).unwrap();
+            let mut out = Vec::new();
+            let failed = print_results(&mut out, results, &args, &Default::default(), Format::Table).unwrap();
+            (failed, String::from_utf8(out).unwrap())
+        };
+
+        // The file is read by its actual name, while the name is printed escaped.
+        assert_eq!(print("--verify-audio"), (false, String::new()));
+        let (failed, encodings) = print("--show-encoding");
+        assert!(!failed);
+        assert!(encodings.contains("a\\x01b.mp3:"), "{}", encodings);
+    }
+
+    #[test]
+    fn test_print_results_verify_audio_unreadable() {
+        let dir = std::env::temp_dir().join("lsmp3-verify-unreadable");
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let data = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        for name in ["a.mp3", "b.mp3"] {
+            fs::copy(data.join("id3v24_some_tags.mp3"), dir.join(name)).unwrap();
+        }
+        let args = Cli::parse_from(["lsmp3", "--verify-audio", &dir.to_string_lossy()]).args;
+        let results = lsmp3::list(&args.file, &Default::default()).unwrap();
+        fs::remove_file(dir.join("a.mp3")).unwrap();
+
+        // The missing file fails the run without stopping the other file from being verified.
+        let mut out = Vec::new();
+        assert!(print_results(&mut out, results, &args, &Default::default(), Format::Table).unwrap());
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_print_results_show_encoding_unreadable() {
+        let dir = std::env::temp_dir().join("lsmp3-encoding-unreadable");
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let data = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        for name in ["a.mp3", "b.mp3"] {
+            fs::copy(data.join("id3v24_some_tags.mp3"), dir.join(name)).unwrap();
+        }
+        let args = Cli::parse_from(["lsmp3", "--show-encoding", &dir.to_string_lossy()]).args;
+        let results = lsmp3::list(&args.file, &Default::default()).unwrap();
+        fs::remove_file(dir.join("a.mp3")).unwrap();
+
+        let mut out = Vec::new();
+        assert!(print_results(&mut out, results, &args, &Default::default(), Format::Table).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains("a.mp3"));
+        assert!(out.contains("b.mp3:"));
+    }
+
+    #[test]
+    fn test_results_to_sections_always_header() {
+        let results = || {
+            vec![lsmp3::Info {
+                path: s!("music"),
+                path_type: lsmp3::PathType::Directory,
+                entries: get_test_entries(),
+                skipped: vec![],
+            }]
+        };
+        let options = Default::default();
+        let sections = results_to_sections(results(), &options, false);
+        assert_eq!((sections.len(), sections[0].path.as_deref()), (1, None));
+        let sections = results_to_sections(results(), &options, true);
+        assert_eq!((sections.len(), sections[0].path.as_deref()), (1, Some("music")));
+        assert_eq!(sections[0].entries, get_test_entries());
+    }
+
+    #[test]
+    fn test_print_sections_empty() {
+        let sections = [
+            Section {
+                path: Some(s!("empty")),
+                entries: vec![],
+            },
+            Section {
+                path: Some(s!("music")),
+                entries: get_test_entries(),
+            },
+        ];
+        let mut out = Vec::new();
+        print_sections(&mut out, &sections, |entries| format!("{} entries\n", entries.len())).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "empty:\n(no MP3s)\n\nmusic:\n2 entries\n"
+        );
+    }
+
+    #[test]
+    fn test_print_single_path() {
+        let results = vec![lsmp3::Info {
+            path: s!("music"),
+            path_type: lsmp3::PathType::Directory,
+            entries: get_test_entries(),
+            skipped: vec![],
+        }];
+        let sections = results_to_sections(results, &Default::default(), false);
+        let mut out = Vec::new();
+        print_sections(&mut out, &sections, |entries| format!("{} entries\n", entries.len())).unwrap();
+        // The listing of a single path is printed on its own, without a header.
+        assert_eq!(String::from_utf8(out).unwrap(), "2 entries\n");
+    }
+
+    #[test]
+    fn test_results_to_sections_source() {
+        let file = |path: &str| lsmp3::Info {
+            path: path.to_owned(),
+            path_type: lsmp3::PathType::File,
+            entries: vec![lsmp3::Entry::named("a.mp3")],
+            skipped: vec![],
+        };
+        let sections = results_to_sections(vec![file("a.mp3"), file("rock/a.mp3")], &Default::default(), false);
+        let sources: Vec<_> = sections[0].entries.iter().map(|e| e.source_dir.as_deref()).collect();
+        assert_eq!(sources, [Some("."), Some("rock")]);
+        assert_eq!(section_columns(&sections[0].entries, &[]), [Column::Source]);
+        assert!(to_table(&sections[0].entries, &[Column::Source], &Default::default()).contains(" rock "));
+
+        // Files from the same directory are unambiguous without their source.
+        let sections = results_to_sections(vec![file("rock/a.mp3"), file("rock/b.mp3")], &Default::default(), false);
+        assert!(sections[0].entries.iter().all(|e| e.source_dir.is_none()));
+        assert_eq!(section_columns(&sections[0].entries, &[]), []);
+        assert!(to_json(&sections[0].entries, &Default::default())[0]
+            .get("source_dir")
+            .is_none());
+    }
+
+    #[test]
+    fn test_parse_encoding() {
+        assert_eq!(parse_encoding("Shift_JIS"), Ok(encoding_rs::SHIFT_JIS));
+        assert_eq!(parse_encoding("latin1"), Ok(encoding_rs::WINDOWS_1252));
+        assert!(parse_encoding("klingon").is_err());
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("Top 100 Hits of 2002"), "Top 100 Hits of 2002");
+        assert_eq!(sanitize_file_name("AC/DC: Live?"), "AC_DC_ Live_");
+        assert_eq!(sanitize_file_name("Vol. 1..."), "Vol. 1");
+        assert_eq!(sanitize_file_name(".."), "_");
+    }
+
+    #[test]
+    fn test_write_split_output() {
+        let dir = std::env::temp_dir().join("lsmp3-split-output");
+        _ = fs::remove_dir_all(&dir);
+        let mut entries = get_test_entries();
+        entries.push(lsmp3::Entry {
+            name: s!("Other.mp3"),
+            album: vec![s!("Dual_Album")],
+            ..Default::default()
+        });
+        let args = Cli::parse_from(["lsmp3", "--group-by", "album", "--split-output", "unused"]).args;
+        write_split_output(&dir, &entries, &args, &[], Format::Json).unwrap();
+
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["(no album).json", "Dual_Album (2).json", "Dual_Album.json"]);
+        let written: Value = serde_json::from_str(&fs::read_to_string(dir.join("Dual_Album.json")).unwrap()).unwrap();
+        assert_eq!(written, to_json(&entries[..1], &Default::default()));
+    }
+
+    #[test]
+    fn test_absolutize_path() {
+        let cwd = std::env::current_dir().unwrap();
+        let mut info = lsmp3::Info {
+            path: s!("."),
+            path_type: lsmp3::PathType::Directory,
+            entries: vec![],
+            skipped: vec![],
+        };
+        absolutize_path(&mut info);
+        assert_eq!(Path::new(&info.path), fs::canonicalize(&cwd).unwrap());
+
+        info.path = s!("testdata/../Cargo.toml");
+        info.path_type = lsmp3::PathType::File;
+        absolutize_path(&mut info);
+        assert_eq!(
+            Path::new(&info.path),
+            fs::canonicalize(&cwd).unwrap().join("Cargo.toml")
+        );
+
+        info.path = s!("does/not/exist");
+        absolutize_path(&mut info);
+        assert_eq!(info.path, "does/not/exist");
+    }
+
+    #[test]
+    fn test_truncate_values() {
+        let entry = &get_test_entries()[0];
+        let truncated = truncate_values(entry, 1);
+        assert_eq!(truncated.artist, vec![s!("Three (+2 more)")] as Vec<String>);
+        assert_eq!(truncated.title, vec![s!("Two (+1 more)")] as Vec<String>);
+        assert_eq!(truncate_values(entry, 3), *entry);
+        assert_eq!(parse_max_values("2"), Ok(2));
+        assert!(parse_max_values("0").is_err());
+    }
+
+    #[test]
+    fn test_format_timing() {
+        assert_eq!(
+            format_timing(3, Duration::from_millis(1500)),
+            "listed 3 files in 1.500s (2.0 files/s)"
+        );
+        assert_eq!(
+            format_timing(1, Duration::from_secs(2)),
+            "listed 1 file in 2.000s (0.5 files/s)"
+        );
+        assert_eq!(
+            format_timing(0, Duration::ZERO),
+            "listed 0 files in 0.000s (0.0 files/s)"
+        );
+    }
+
+    #[test]
+    fn test_to_sqlite() {
+        let mut entries = get_test_entries();
+        entries[1].title = vec![s!("It's")];
+        entries[1].album = entries[0].album.clone();
+        entries[1].artist = vec![s!("cool"), s!("Other")];
+        let results = [lsmp3::Info {
+            path: s!("dir"),
+            path_type: lsmp3::PathType::Directory,
+            entries,
+            skipped: vec![],
+        }];
+        let path = std::env::temp_dir().join("lsmp3-to-sqlite.db");
+        _ = fs::remove_file(&path);
+        to_sqlite(&results, &path).unwrap();
+        // Writing again replaces the tables rather than adding to them.
+        to_sqlite(&results, &path).unwrap();
+
+        let db = rusqlite::Connection::open(&path).unwrap();
+        let rows = |sql: &str| -> Vec<String> {
+            let mut statement = db.prepare(sql).unwrap();
+            let rows = statement.query_map([], |row| row.get(0)).unwrap();
+            rows.map(Result::unwrap).collect()
+        };
+        assert_eq!(rows("SELECT title FROM albums"), ["Dual/Album"]);
+        assert_eq!(
+            rows("SELECT name FROM artists ORDER BY id"),
+            ["Three", "cool", "artists", "Other"]
+        );
+        assert_eq!(
+            rows(
+                "SELECT path || ' ' || size || ' ' || ifnull(title, '-') || ' ' || ifnull(album_id, '-') || ' ' || \
+                 ifnull(year, '-') || ' ' || ifnull(track, '-') || ' ' || ifnull(track_total, '-') || ' ' || \
+                 ifnull(disc, '-') || ' ' || ifnull(genre, '-') FROM tracks ORDER BY id"
+            ),
+            [
+                "dir/Some.mp3 8080 Two/titles 1 2020 2 3 - Trip-Hop/Hip-Hop",
+                "dir/None.mp3 4 It's 1 - - - - -",
+            ]
+        );
+        assert_eq!(
+            rows(
+                "SELECT t.title || ': ' || group_concat(a.name, ', ') FROM tracks t \
+                 JOIN track_artists ta ON ta.track_id = t.id JOIN artists a ON a.id = ta.artist_id \
+                 GROUP BY t.id ORDER BY t.id"
+            ),
+            ["Two/titles: Three, cool, artists", "It's: cool, Other"]
+        );
+    }
+
+    #[test]
+    fn test_envelope() {
+        let results = to_json(&get_test_entries(), &Default::default());
+        let value = envelope(results.clone());
+        assert_eq!(value["schema_version"], json!(lsmp3::SCHEMA_VERSION));
+        assert_eq!(value["lsmp3_version"], json!(env!("CARGO_PKG_VERSION")));
+        assert_eq!(value["results"], results);
+
+        // Listings with the envelope can be compared against like those without.
+        let (mut plain, mut wrapped) = (Vec::new(), Vec::new());
+        json_to_keyed_entries(results, None, &mut plain).unwrap();
+        json_to_keyed_entries(value, None, &mut wrapped).unwrap();
+        assert_eq!(plain.len(), 2);
+        assert_eq!(plain, wrapped);
+    }
+
+    #[test]
+    fn test_flatten_results() {
+        let info = |path: &str, path_type, names: &[&str]| lsmp3::Info {
+            path: path.to_owned(),
+            path_type,
+            entries: names.iter().map(|name| lsmp3::Entry::named(*name)).collect(),
+            skipped: Vec::new(),
+        };
+        let results = vec![
+            info("a.mp3", lsmp3::PathType::File, &["a.mp3"]),
+            info("music", lsmp3::PathType::Directory, &["z.mp3"]),
+            info("music/rock", lsmp3::PathType::Directory, &["b.mp3"]),
+            info("music/rock/live", lsmp3::PathType::Directory, &["c.mp3"]),
+            info("other", lsmp3::PathType::Directory, &["d.mp3"]),
+        ];
+
+        let flattened = flatten_results(results, &Default::default());
+        let names = |info: &lsmp3::Info| info.entries.iter().map(|e| e.name.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            flattened.iter().map(|i| i.path.as_str()).collect::<Vec<_>>(),
+            ["a.mp3", "music", "other"]
+        );
+        assert_eq!(
+            names(&flattened[1]),
+            [
+                Path::new("rock").join("b.mp3"),
+                Path::new("rock").join("live").join("c.mp3"),
+                PathBuf::from("z.mp3"),
+            ]
+            .map(PathBuf::into_os_string)
+        );
+        assert_eq!(names(&flattened[2]), [OsString::from("d.mp3")]);
+    }
+
+    #[test]
+    fn test_value_names() {
+        assert_eq!(value_names::<Format>(), ["table", "json", "html", "sqlite"]);
+        let sorts = value_names::<lsmp3::SortBy>();
+        assert_eq!(sorts.len(), lsmp3::SortBy::value_variants().len());
+        assert!(sorts.contains(&"original-artist"));
+    }
+
+    #[test]
+    fn test_sort_dirs() {
+        let info = |path: &str, sizes: &[u64]| lsmp3::Info {
+            path: path.to_owned(),
+            path_type: lsmp3::PathType::Directory,
+            entries: sizes
+                .iter()
+                .map(|size| lsmp3::Entry {
+                    size: *size,
+                    ..Default::default()
+                })
+                .collect(),
+            skipped: Vec::new(),
+        };
+        let mut results = vec![info("b", &[5, 5]), info("c", &[20]), info("a", &[1, 2, 3])];
+        let paths = |results: &[lsmp3::Info]| results.iter().map(|i| i.path.clone()).collect::<Vec<_>>();
+
+        sort_dirs(&mut results, SortDirs::Name);
+        assert_eq!(paths(&results), ["a", "b", "c"]);
+        sort_dirs(&mut results, SortDirs::Size);
+        assert_eq!(paths(&results), ["c", "b", "a"]);
+        sort_dirs(&mut results, SortDirs::Count);
+        assert_eq!(paths(&results), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_path_kind() {
+        let args = Cli::parse_from(["lsmp3", "--type", "dir"]).args;
+        let kind = args.path_type.unwrap();
+        assert!(kind.matches(&lsmp3::PathType::Directory));
+        assert!(!kind.matches(&lsmp3::PathType::File));
+    }
+
+    #[test]
+    fn test_parse_sample() {
+        assert_eq!(parse_sample("10%"), Ok(lsmp3::Sample::Percent(10.0)));
+        assert_eq!(parse_sample("0.5 %"), Ok(lsmp3::Sample::Percent(0.5)));
+        assert_eq!(parse_sample("500"), Ok(lsmp3::Sample::Count(500)));
+        assert!(parse_sample("101%").is_err());
+        assert!(parse_sample("-1").is_err());
+        assert!(parse_sample("ten").is_err());
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("1970-01-01"), Ok(0));
+        assert_eq!(parse_time("2000-03-01"), Ok(951_868_800));
+        assert_eq!(parse_time("2024-01-01"), Ok(1_704_067_200));
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let week_ago = parse_time("7d").unwrap();
+        assert!((now - 7 * 86400 - 1..=now - 7 * 86400 + 1).contains(&week_ago));
+        assert!(parse_time("1969-12-31").is_err());
+        assert!(parse_time("2024-13-01").is_err());
+        assert!(parse_time("7y").is_err());
+        assert!(parse_time("d").is_err());
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(parse_timeout("2"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_timeout("0.5"), Ok(Duration::from_millis(500)));
+        assert!(parse_timeout("0").is_err());
+        assert!(parse_timeout("-1").is_err());
+        assert!(parse_timeout("soon").is_err());
+    }
+
+    #[test]
+    fn test_format_time_ms() {
+        assert_eq!(format_time_ms(0), "0:00:00.000");
+        assert_eq!(format_time_ms(125_500), "0:02:05.500");
+        assert_eq!(format_time_ms(3_723_045), "1:02:03.045");
+    }
+
     #[test]
     fn verify_args() {
-        Args::command().debug_assert()
+        Cli::command().debug_assert()
     }
 }
@@ -0,0 +1,29 @@
+use super::*;
+use std::{fs, io, path::Path};
+
+/// Copies or moves a file to a new path, creating the missing parent directories. An existing file is never
+/// overwritten. Moving falls back to copying and removing the original when the file can't be renamed, e.g. because the
+/// new path is on another filesystem. Nothing is done if both paths are the same.
+pub fn organize_file(from: &Path, to: &Path, move_file: bool) -> Result<(), LsError> {
+    let write_err = |err| LsError::IoWriteError(to.as_os_str().to_owned(), err);
+    if from == to {
+        return Ok(());
+    }
+    if to.symlink_metadata().is_ok() {
+        return Err(write_err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "the file already exists",
+        )));
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(write_err)?;
+    }
+    if move_file && fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to).map_err(write_err)?;
+    if move_file {
+        fs::remove_file(from).map_err(|err| LsError::IoWriteError(from.as_os_str().to_owned(), err))?;
+    }
+    Ok(())
+}
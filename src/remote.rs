@@ -0,0 +1,53 @@
+use super::*;
+use std::io::{Cursor, Read};
+
+/// Returns whether a path to list looks like an HTTP or HTTPS URL.
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Reads the tags of a remote file without downloading all of it. The ID3v2 header is fetched first to determine the
+/// size of the tag, then only the tag itself is fetched and parsed. Text frames longer than `max_frame_bytes` are
+/// truncated.
+pub(crate) fn read_remote_entry(url: &str, max_frame_bytes: usize) -> Result<Entry, LsError> {
+    let (header, size) = fetch_prefix(url, 10)?;
+    // Reading from the fetched bytes can't fail.
+    let tag_len = match read_id3v2_len(&mut &header[..]) {
+        Ok(Some(len)) => len,
+        _ => {
+            return Err(LsError::Id3Error(
+                url.into(),
+                id3::Error::new(id3::ErrorKind::NoTag, "no ID3v2 header at the start of the file"),
+            ))
+        }
+    };
+    let (tag, _) = fetch_prefix(url, tag_len)?;
+    let tag = id3::Tag::read_from2(Cursor::new(tag)).map_err(|err| LsError::Id3Error(url.into(), err))?;
+    Ok(Entry {
+        name: url.rsplit('/').next().unwrap_or(url).into(),
+        size: size.unwrap_or_default(),
+        ..entry_from_tag(&tag, max_frame_bytes)
+    })
+}
+
+/// Fetches the first `len` bytes of a remote file with a range request, along with the total size of the file if the
+/// server reports it. Servers that ignore the range and send the whole file are only read up to `len` bytes.
+fn fetch_prefix(url: &str, len: u64) -> Result<(Vec<u8>, Option<u64>), LsError> {
+    let err = |reason: String| LsError::RemoteError(url.to_owned(), reason);
+    let response = ureq::get(url)
+        .set("Range", &format!("bytes=0-{}", len.saturating_sub(1)))
+        .call()
+        .map_err(|e| err(e.to_string()))?;
+    let size = match response.status() {
+        206 => response.header("Content-Range").and_then(|r| r.rsplit('/').next()),
+        _ => response.header("Content-Length"),
+    }
+    .and_then(|s| s.parse().ok());
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .take(len)
+        .read_to_end(&mut bytes)
+        .map_err(|e| err(e.to_string()))?;
+    Ok((bytes, size))
+}
@@ -0,0 +1,57 @@
+use super::*;
+
+/// The score of each character of the query that is matched.
+const MATCH_SCORE: u32 = 1;
+/// The bonus for a character matched right after the previous one.
+const CONSECUTIVE_BONUS: u32 = 4;
+/// The bonus for a character matched at the start of a word.
+const WORD_START_BONUS: u32 = 2;
+
+/// Scores how well a query fuzzily matches the title, artist and album of an entry, or returns `None` if it doesn't
+/// match at all. A query matches if its letters and digits appear in the text in order, ignoring case, so "darkside"
+/// matches "The Dark Side of the Moon". Matches of consecutive characters and at the start of words score higher. An
+/// empty query matches everything with a score of zero.
+pub fn fuzzy_score(query: &str, entry: &Entry) -> Option<u32> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    let text: Vec<char> = [&entry.title, &entry.artist, &entry.album]
+        .into_iter()
+        .flatten()
+        .flat_map(|value| value.chars().chain([' ']))
+        .flat_map(char::to_lowercase)
+        .collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+    // Try every occurrence of the first character as the start of the match and keep the best score, since matching
+    // greedily from the first occurrence can miss a better match later on.
+    text.iter()
+        .enumerate()
+        .filter(|(_, c)| **c == query[0])
+        .filter_map(|(start, _)| score_from(&query, &text, start))
+        .max()
+}
+
+/// Scores a match of the query that starts at `start` in the text, matching each following character at its first
+/// occurrence.
+fn score_from(query: &[char], text: &[char], start: usize) -> Option<u32> {
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let mut pos = start;
+    for q in query {
+        let i = pos + text[pos..].iter().position(|c| c == q)?;
+        score += MATCH_SCORE;
+        if previous.is_some_and(|p| p + 1 == i) {
+            score += CONSECUTIVE_BONUS;
+        }
+        if i == 0 || !text[i - 1].is_alphanumeric() {
+            score += WORD_START_BONUS;
+        }
+        previous = Some(i);
+        pos = i + 1;
+    }
+    Some(score)
+}
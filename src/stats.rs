@@ -0,0 +1,116 @@
+use super::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// The number of entries that share a value.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Count {
+    /// The shared value.
+    pub value: String,
+
+    /// The number of entries with the value.
+    pub count: usize,
+}
+
+/// Aggregate statistics about a collection of entries.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    /// The total number of files.
+    pub files: usize,
+
+    /// The total size of all files.
+    pub size: u64,
+
+    /// The most common genres, by number of files.
+    pub genres: Vec<Count>,
+
+    /// The most common artists, by number of tracks.
+    pub artists: Vec<Count>,
+
+    /// The number of files per decade, in chronological order.
+    pub decades: Vec<Count>,
+
+    /// The number of files that have each tag field, in a fixed order of fields starting with the most common ones.
+    pub coverage: Vec<Count>,
+}
+
+/// Counts the occurrences of each value, returning the counts ordered from the most to the least common. Values with
+/// the same count are ordered alphabetically.
+pub fn count_values<I, S>(values: I) -> Vec<Count>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut counts = HashMap::<String, usize>::new();
+    for value in values {
+        *counts.entry(value.into()).or_default() += 1;
+    }
+    let mut counts: Vec<_> = counts
+        .into_iter()
+        .map(|(value, count)| Count { value, count })
+        .collect();
+    counts.sort_unstable_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    counts
+}
+
+/// Returns whether each tag field is present in an entry, along with the name of the field as used in the JSON output.
+fn tag_fields(entry: &Entry) -> [(&'static str, bool); 14] {
+    [
+        ("title", !entry.title.is_empty()),
+        ("artist", !entry.artist.is_empty()),
+        ("album", !entry.album.is_empty()),
+        ("year", entry.year.is_some()),
+        ("track", entry.track.number.is_some()),
+        ("disc", entry.disc.is_some()),
+        ("genre", !entry.genre.is_empty()),
+        ("original_artist", !entry.original_artist.is_empty()),
+        ("original_album", !entry.original_album.is_empty()),
+        ("conductor", !entry.conductor.is_empty()),
+        ("remixer", !entry.remixer.is_empty()),
+        ("isrc", !entry.isrc.is_empty()),
+        ("publisher", !entry.publisher.is_empty()),
+        ("copyright", !entry.copyright.is_empty()),
+    ]
+}
+
+/// Aggregates statistics over the given entries. The genre and artist breakdowns are limited to the `top` most common
+/// values, if provided.
+pub fn aggregate<'a, I>(entries: I, top: Option<usize>) -> Stats
+where
+    I: IntoIterator<Item = &'a Entry>,
+{
+    let entries: Vec<_> = entries.into_iter().collect();
+    let truncate = |mut counts: Vec<Count>| {
+        if let Some(top) = top {
+            counts.truncate(top);
+        }
+        counts
+    };
+
+    let mut decades = BTreeMap::<i32, usize>::new();
+    for year in entries.iter().filter_map(|e| e.year) {
+        *decades.entry(year.div_euclid(10) * 10).or_default() += 1;
+    }
+
+    Stats {
+        files: entries.len(),
+        size: entries.iter().map(|e| e.size).sum(),
+        genres: truncate(count_values(entries.iter().flat_map(|e| e.genre.iter().cloned()))),
+        artists: truncate(count_values(entries.iter().flat_map(|e| e.artist.iter().cloned()))),
+        decades: decades
+            .into_iter()
+            .map(|(decade, count)| Count {
+                value: format!("{}s", decade),
+                count,
+            })
+            .collect(),
+        coverage: tag_fields(&Entry::default())
+            .iter()
+            .enumerate()
+            .map(|(i, (field, _))| Count {
+                value: field.to_string(),
+                count: entries.iter().filter(|e| tag_fields(e)[i].1).count(),
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,185 @@
+use super::*;
+use std::{path::PathBuf, str::FromStr};
+
+/// A field of an entry that can be referenced from a template.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Field {
+    Name,
+    Size,
+    Title,
+    Artist,
+    Album,
+    Year,
+    Track,
+    TrackTotal,
+    Disc,
+    Genre,
+    OriginalArtist,
+    OriginalAlbum,
+    Conductor,
+    Remixer,
+    Isrc,
+    Publisher,
+    Copyright,
+    SampleRate,
+    ChannelMode,
+}
+
+impl Field {
+    const ALL: &'static [(&'static str, Field)] = &[
+        ("name", Field::Name),
+        ("size", Field::Size),
+        ("title", Field::Title),
+        ("artist", Field::Artist),
+        ("album", Field::Album),
+        ("year", Field::Year),
+        ("track", Field::Track),
+        ("track_total", Field::TrackTotal),
+        ("disc", Field::Disc),
+        ("genre", Field::Genre),
+        ("original_artist", Field::OriginalArtist),
+        ("original_album", Field::OriginalAlbum),
+        ("conductor", Field::Conductor),
+        ("remixer", Field::Remixer),
+        ("isrc", Field::Isrc),
+        ("publisher", Field::Publisher),
+        ("copyright", Field::Copyright),
+        ("sample_rate", Field::SampleRate),
+        ("channel_mode", Field::ChannelMode),
+    ];
+
+    fn from_name(name: &str) -> Option<Field> {
+        Field::ALL.iter().find(|(n, _)| *n == name).map(|(_, f)| *f)
+    }
+
+    /// Returns the value of the field for an entry. Multiple values are joined with a "/", same as in the table.
+    fn value(&self, entry: &Entry) -> String {
+        let opt = |o: Option<u32>| o.map(|n| n.to_string()).unwrap_or_default();
+        match self {
+            Field::Name => entry.name.to_string_lossy().to_string(),
+            Field::Size => entry.size.to_string(),
+            Field::Title => entry.title.join("/"),
+            Field::Artist => entry.artist.join("/"),
+            Field::Album => entry.album.join("/"),
+            Field::Year => entry.year.map(|y| y.to_string()).unwrap_or_default(),
+            Field::Track => opt(entry.track.number),
+            Field::TrackTotal => opt(entry.track.total),
+            Field::Disc => opt(entry.disc),
+            Field::Genre => entry.genre.join("/"),
+            Field::OriginalArtist => entry.original_artist.join("/"),
+            Field::OriginalAlbum => entry.original_album.join("/"),
+            Field::Conductor => entry.conductor.join("/"),
+            Field::Remixer => entry.remixer.join("/"),
+            Field::Isrc => entry.isrc.join("/"),
+            Field::Publisher => entry.publisher.join("/"),
+            Field::Copyright => entry.copyright.join("/"),
+            Field::SampleRate => opt(entry.sample_rate),
+            Field::ChannelMode => entry.channel_mode.map(|m| m.to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+/// A template that renders a string from the fields of an entry.
+///
+/// Fields are referenced by name in braces, e.g. `{artist} - {title} ({year})`, and literal braces are written as `{{`
+/// and `}}`. The available fields are `name`, `size`, `title`, `artist`, `album`, `year`, `track`, `track_total`,
+/// `disc`, `genre`, `original_artist`, `original_album`, `conductor`, `remixer`, `isrc`, `publisher`, `copyright`,
+/// `sample_rate` and `channel_mode` (the last two are only available when audio properties are read). Missing values
+/// render as an empty string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Renders the template for an entry.
+    pub fn render(&self, entry: &Entry) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(s) => s.clone(),
+                Part::Field(field) => field.value(entry),
+            })
+            .collect()
+    }
+
+    /// Renders the template for an entry as a relative path, where each "/" of the template starts a new component.
+    /// Separators and other characters that aren't allowed in file names are replaced with "_" in field values, so that
+    /// a value can't add components of its own. Empty components are dropped, and "." and ".." are replaced, so the
+    /// path never leaves the directory it's joined to.
+    pub fn render_path(&self, entry: &Entry) -> PathBuf {
+        let rendered: String = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(s) => s.clone(),
+                Part::Field(field) => field
+                    .value(entry)
+                    .chars()
+                    .map(|c| match c {
+                        '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                        c if c.is_control() => '_',
+                        c => c,
+                    })
+                    .collect(),
+            })
+            .collect();
+        rendered
+            .split('/')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(|c| if c == "." || c == ".." { "_" } else { c })
+            .collect()
+    }
+}
+
+impl FromStr for Template {
+    type Err = LsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = |reason: String| LsError::InvalidTemplate(s.to_owned(), reason);
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(err("unclosed \"{\"".to_owned())),
+                        }
+                    }
+                    let field =
+                        Field::from_name(name.trim()).ok_or_else(|| err(format!("unknown field {:?}", name)))?;
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(field));
+                }
+                '}' => return Err(err("unmatched \"}\"".to_owned())),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Template { parts })
+    }
+}
@@ -0,0 +1,80 @@
+use super::*;
+use std::fmt;
+
+/// How far the tagged length may be off the actual duration of the audio, in milliseconds, before it's reported.
+pub const LENGTH_TOLERANCE_MS: u32 = 2000;
+
+/// A problem found while validating the metadata of an entry.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Issue {
+    /// The year is not between 1 and 9999.
+    InvalidYear(i32),
+
+    /// The track number is greater than the total number of tracks. Contains the number and the total.
+    TrackExceedsTotal(u32, u32),
+
+    /// Some tag text isn't valid in the encoding declared by its frame, so the tag could only be read partially.
+    InvalidTextEncoding,
+
+    /// The length tagged in the TLEN frame differs from the duration of the audio by more than the tolerance. Contains
+    /// the tagged length and the duration, in milliseconds.
+    LengthMismatch(u32, u32),
+
+    /// The ReplayGain track peak is at or above full scale, so the audio clips. Contains the tagged peak.
+    Clipping(String),
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::InvalidYear(year) => write!(f, "invalid year {}", year),
+            Issue::TrackExceedsTotal(number, total) => {
+                write!(f, "track number {} exceeds the total of {} tracks", number, total)
+            }
+            Issue::InvalidTextEncoding => write!(f, "tag text is not valid in its declared encoding, e.g. UTF-8"),
+            Issue::LengthMismatch(tagged, duration) => write!(
+                f,
+                "tagged length of {} ms differs from the audio duration of {} ms",
+                tagged, duration
+            ),
+            Issue::Clipping(peak) => write!(f, "ReplayGain track peak of {} indicates clipping", peak),
+        }
+    }
+}
+
+/// Returns whether a year is plausible, i.e. between 1 and 9999.
+#[inline]
+pub fn is_valid_year(year: i32) -> bool {
+    (1..=9999).contains(&year)
+}
+
+/// Validates the metadata of an entry, returning all the issues found.
+pub fn validate_entry(entry: &Entry) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    if entry.invalid_text_encoding {
+        issues.push(Issue::InvalidTextEncoding);
+    }
+    if let Some(year) = entry.year.filter(|y| !is_valid_year(*y)) {
+        issues.push(Issue::InvalidYear(year));
+    }
+    if let Track {
+        number: Some(number),
+        total: Some(total),
+    } = entry.track
+    {
+        if number > total {
+            issues.push(Issue::TrackExceedsTotal(number, total));
+        }
+    }
+    if let (Some(tagged), Some(duration)) = (entry.tagged_length_ms, entry.duration_ms) {
+        if tagged.abs_diff(duration) > LENGTH_TOLERANCE_MS {
+            issues.push(Issue::LengthMismatch(tagged, duration));
+        }
+    }
+    if let Some(peak) = &entry.replaygain_track_peak {
+        if peak.parse::<f64>().is_ok_and(|p| p >= 1.0) {
+            issues.push(Issue::Clipping(peak.clone()));
+        }
+    }
+    issues
+}
@@ -1,5 +1,6 @@
+use id3::TagLike;
 use lsmp3::*;
-use std::{env, path::PathBuf};
+use std::{env, fs, path::PathBuf, time::Duration};
 
 /// Creates an owned String or OsString from a string literal.
 macro_rules! s {
@@ -13,6 +14,19 @@ fn test_data_dir() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata")
 }
 
+/// Copies an untagged MP3 into a temporary directory unique to the test, writes the tag built by `f` to it and returns
+/// the path of the copy.
+fn tagged_copy(test: &str, file: &str, f: impl FnOnce(&mut id3::Tag)) -> PathBuf {
+    let dir = env::temp_dir().join("lsmp3-tests").join(test);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(file);
+    fs::copy(test_data_dir().join("id3v24_no_tags.mp3"), &path).unwrap();
+    let mut tag = id3::Tag::new();
+    f(&mut tag);
+    tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+    path
+}
+
 #[test]
 fn test_list_single_file() {
     let path = test_data_dir()
@@ -24,15 +38,17 @@ fn test_list_single_file() {
         list(
             &vec![path.clone()],
             &ListOptions {
-                sort_by: &[SortBy::Name],
+                sort_by: &[(SortBy::Name, Direction::Ascending)],
                 reverse: &false,
                 recursive: &false,
+                ..Default::default()
             }
         )
         .unwrap(),
         vec![Info {
             path,
             path_type: PathType::File,
+            skipped: vec![],
             entries: vec![Entry {
                 name: s!("id3v24_most_tags.mp3"),
                 size: 23017,
@@ -47,7 +63,8 @@ fn test_list_single_file() {
                     number: Some(3),
                     total: None
                 },
-                genre: vec![s!("Pop")]
+                genre: vec![s!("Pop")],
+                ..Default::default()
             }]
         }]
     )
@@ -65,15 +82,17 @@ fn test_list_symlink_file() {
         list(
             &vec![path.clone()],
             &ListOptions {
-                sort_by: &[SortBy::Name],
+                sort_by: &[(SortBy::Name, Direction::Ascending)],
                 reverse: &false,
                 recursive: &false,
+                ..Default::default()
             }
         )
         .unwrap(),
         vec![Info {
             path,
             path_type: PathType::File,
+            skipped: vec![],
             entries: vec![Entry {
                 name: s!("id3v24_most_tags.mp3"),
                 size: 23017,
@@ -88,7 +107,8 @@ fn test_list_symlink_file() {
                     number: Some(3),
                     total: None
                 },
-                genre: vec![s!("Pop")]
+                genre: vec![s!("Pop")],
+                ..Default::default()
             }]
         }]
     )
@@ -112,9 +132,10 @@ fn test_list_multiple_files() {
         list(
             &vec![path1.clone(), path2.clone()],
             &ListOptions {
-                sort_by: &[SortBy::Name],
+                sort_by: &[(SortBy::Name, Direction::Ascending)],
                 reverse: &false,
                 recursive: &false,
+                ..Default::default()
             }
         )
         .unwrap(),
@@ -122,6 +143,7 @@ fn test_list_multiple_files() {
             Info {
                 path: path1,
                 path_type: PathType::File,
+                skipped: vec![],
                 entries: vec![Entry {
                     name: s!("id3v23_most_tags.mp3"),
                     size: 22993,
@@ -136,12 +158,14 @@ fn test_list_multiple_files() {
                         number: Some(3),
                         total: None
                     },
-                    genre: vec![s!("Pop")]
+                    genre: vec![s!("Pop")],
+                    ..Default::default()
                 }]
             },
             Info {
                 path: path2,
                 path_type: PathType::File,
+                skipped: vec![],
                 entries: vec![Entry {
                     name: s!("id3v24_most_tags.mp3"),
                     size: 23017,
@@ -156,7 +180,8 @@ fn test_list_multiple_files() {
                         number: Some(3),
                         total: None
                     },
-                    genre: vec![s!("Pop")]
+                    genre: vec![s!("Pop")],
+                    ..Default::default()
                 }]
             }
         ]
@@ -173,14 +198,15 @@ fn test_list_invalid_file() {
                 .into_string()
                 .unwrap()],
             &ListOptions {
-                sort_by: &[SortBy::Name],
+                sort_by: &[(SortBy::Name, Direction::Ascending)],
                 reverse: &false,
                 recursive: &false,
+                ..Default::default()
             },
         )
         .err()
         .unwrap(),
-        LsError::Id3Error { .. }
+        LsError::NotAnMp3(..)
     ));
 }
 
@@ -195,15 +221,17 @@ fn test_list_dir() {
         list(
             &vec![path.clone()],
             &ListOptions {
-                sort_by: &[SortBy::Name],
+                sort_by: &[(SortBy::Name, Direction::Ascending)],
                 reverse: &false,
                 recursive: &false,
+                ..Default::default()
             }
         )
         .unwrap(),
         vec![Info {
             path,
             path_type: PathType::Directory,
+            skipped: vec![],
             entries: vec![
                 Entry {
                     name: s!("id3v23_most_tags.mp3"),
@@ -219,7 +247,8 @@ fn test_list_dir() {
                         number: Some(3),
                         total: None
                     },
-                    genre: vec![s!("Pop")]
+                    genre: vec![s!("Pop")],
+                    ..Default::default()
                 },
                 Entry {
                     name: s!("id3v23_some_tags.mp3"),
@@ -235,7 +264,8 @@ fn test_list_dir() {
                         number: None,
                         total: None
                     },
-                    genre: vec![]
+                    genre: vec![],
+                    ..Default::default()
                 },
                 Entry {
                     name: s!("id3v24_most_tags.mp3"),
@@ -251,7 +281,8 @@ fn test_list_dir() {
                         number: Some(3),
                         total: None
                     },
-                    genre: vec![s!("Pop")]
+                    genre: vec![s!("Pop")],
+                    ..Default::default()
                 },
                 Entry {
                     name: s!("id3v24_some_tags.mp3"),
@@ -267,13 +298,41 @@ fn test_list_dir() {
                         number: None,
                         total: None
                     },
-                    genre: vec![]
+                    genre: vec![],
+                    ..Default::default()
                 }
             ]
         }]
     )
 }
 
+#[test]
+fn test_info_entry_paths() {
+    let dir = test_data_dir().join("most_tags");
+    let file = test_data_dir().join("id3v24_most_tags.mp3");
+    let results = list(
+        &vec![
+            dir.clone().into_os_string().into_string().unwrap(),
+            file.clone().into_os_string().into_string().unwrap(),
+        ],
+        &ListOptions {
+            sort_by: &[(SortBy::Name, Direction::Ascending)],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        results.iter().flat_map(Info::entry_paths).collect::<Vec<_>>(),
+        vec![
+            dir.join("id3v23_most_tags.mp3"),
+            dir.join("id3v23_some_tags.mp3"),
+            dir.join("id3v24_most_tags.mp3"),
+            dir.join("id3v24_some_tags.mp3"),
+            file
+        ]
+    );
+}
+
 #[test]
 fn test_list_symlink_dir() {
     let path = test_data_dir()
@@ -285,15 +344,17 @@ fn test_list_symlink_dir() {
         list(
             &vec![path.clone()],
             &ListOptions {
-                sort_by: &[SortBy::Name],
+                sort_by: &[(SortBy::Name, Direction::Ascending)],
                 reverse: &false,
                 recursive: &false,
+                ..Default::default()
             }
         )
         .unwrap(),
         vec![Info {
             path,
             path_type: PathType::Directory,
+            skipped: vec![],
             entries: vec![
                 Entry {
                     name: s!("id3v23_most_tags.mp3"),
@@ -309,7 +370,8 @@ fn test_list_symlink_dir() {
                         number: Some(3),
                         total: None
                     },
-                    genre: vec![s!("Pop")]
+                    genre: vec![s!("Pop")],
+                    ..Default::default()
                 },
                 Entry {
                     name: s!("id3v23_some_tags.mp3"),
@@ -325,7 +387,8 @@ fn test_list_symlink_dir() {
                         number: None,
                         total: None
                     },
-                    genre: vec![]
+                    genre: vec![],
+                    ..Default::default()
                 },
                 Entry {
                     name: s!("id3v24_most_tags.mp3"),
@@ -341,7 +404,8 @@ fn test_list_symlink_dir() {
                         number: Some(3),
                         total: None
                     },
-                    genre: vec![s!("Pop")]
+                    genre: vec![s!("Pop")],
+                    ..Default::default()
                 },
                 Entry {
                     name: s!("id3v24_some_tags.mp3"),
@@ -357,7 +421,8 @@ fn test_list_symlink_dir() {
                         number: None,
                         total: None
                     },
-                    genre: vec![]
+                    genre: vec![],
+                    ..Default::default()
                 }
             ]
         }]
@@ -381,9 +446,10 @@ fn test_list_multiple_dirs() {
     let results = list(
         &vec![path1.clone(), path2.clone()],
         &ListOptions {
-            sort_by: &[SortBy::Name],
+            sort_by: &[(SortBy::Name, Direction::Ascending)],
             reverse: &false,
             recursive: &false,
+            ..Default::default()
         },
     )
     .unwrap();
@@ -412,34 +478,35 @@ fn test_list_cwd() {
         list(
             &vec![],
             &ListOptions {
-                sort_by: &[SortBy::Name],
+                sort_by: &[(SortBy::Name, Direction::Ascending)],
                 reverse: &false,
                 recursive: &false,
+                ..Default::default()
             },
         )
         .unwrap(),
         vec![Info {
             path: s!("."),
             path_type: PathType::Directory,
+            skipped: vec![],
             entries: vec![
                 Entry {
                     name: s!("id3v23_all_tags.mp3"),
                     size: 22993,
-                    title: vec![s!("Best Song Ever"), s!("Really Cool Song")],
+                    // Only the frames of people and genres hold several values in ID3v2.3.
+                    title: vec![s!("Best Song Ever/Really Cool Song")],
                     title_sort_order: Some(vec![s!("Ever, Best Song")]),
                     artist: vec![s!("Someone"), s!("Noone")],
                     artist_sort_order: Some(vec![s!("One, Some")]),
-                    album: vec![
-                        s!("Billboard Year-End Hot 100 singles of 2002"),
-                        s!("Top 100 Hits of 2002")
-                    ],
+                    album: vec![s!("Billboard Year-End Hot 100 singles of 2002/Top 100 Hits of 2002")],
                     album_sort_order: Some(vec![s!("2002, Hot 100 Singles")]),
                     year: Some(2002),
                     track: Track {
                         number: Some(3),
                         total: Some(100)
                     },
-                    genre: vec![s!("Pop")]
+                    genre: vec![s!("Pop")],
+                    ..Default::default()
                 },
                 Entry {
                     name: s!("id3v23_most_tags.mp3"),
@@ -455,7 +522,8 @@ fn test_list_cwd() {
                         number: Some(3),
                         total: None
                     },
-                    genre: vec![s!("Pop")]
+                    genre: vec![s!("Pop")],
+                    ..Default::default()
                 },
                 Entry {
                     name: s!("id3v23_no_tags.mp3"),
@@ -471,7 +539,8 @@ fn test_list_cwd() {
                         number: None,
                         total: None
                     },
-                    genre: vec![]
+                    genre: vec![],
+                    ..Default::default()
                 },
                 Entry {
                     name: s!("id3v23_some_tags.mp3"),
@@ -487,7 +556,8 @@ fn test_list_cwd() {
                         number: None,
                         total: None
                     },
-                    genre: vec![]
+                    genre: vec![],
+                    ..Default::default()
                 },
                 Entry {
                     name: s!("id3v24_all_tags.mp3"),
@@ -506,7 +576,8 @@ fn test_list_cwd() {
                         number: Some(3),
                         total: Some(100)
                     },
-                    genre: vec![s!("Pop")]
+                    genre: vec![s!("Pop")],
+                    ..Default::default()
                 },
                 Entry {
                     name: s!("id3v24_most_tags.mp3"),
@@ -522,7 +593,8 @@ fn test_list_cwd() {
                         number: Some(3),
                         total: None
                     },
-                    genre: vec![s!("Pop")]
+                    genre: vec![s!("Pop")],
+                    ..Default::default()
                 },
                 Entry {
                     name: s!("id3v24_no_tags.mp3"),
@@ -538,7 +610,8 @@ fn test_list_cwd() {
                         number: None,
                         total: None
                     },
-                    genre: vec![]
+                    genre: vec![],
+                    ..Default::default()
                 },
                 Entry {
                     name: s!("id3v24_some_tags.mp3"),
@@ -554,7 +627,8 @@ fn test_list_cwd() {
                         number: None,
                         total: None
                     },
-                    genre: vec![]
+                    genre: vec![],
+                    ..Default::default()
                 }
             ]
         }]
@@ -568,9 +642,10 @@ fn test_list_dir_recursive() {
     let results = list(
         &vec![path.clone()],
         &ListOptions {
-            sort_by: &[SortBy::Name],
+            sort_by: &[(SortBy::Name, Direction::Ascending)],
             reverse: &false,
             recursive: &true,
+            ..Default::default()
         },
     )
     .unwrap();
@@ -619,9 +694,10 @@ fn test_order_reverse() {
     let results = list(
         &vec![path.clone()],
         &ListOptions {
-            sort_by: &[SortBy::Name],
+            sort_by: &[(SortBy::Name, Direction::Ascending)],
             reverse: &true,
             recursive: &false,
+            ..Default::default()
         },
     )
     .unwrap();
@@ -646,9 +722,15 @@ fn test_order_by_multiple_fields() {
     let results = list(
         &vec![path.clone()],
         &ListOptions {
-            sort_by: &[SortBy::Album, SortBy::Title, SortBy::Track, SortBy::Name],
+            sort_by: &[
+                (SortBy::Album, Direction::Ascending),
+                (SortBy::Title, Direction::Ascending),
+                (SortBy::Track, Direction::Ascending),
+                (SortBy::Name, Direction::Ascending),
+            ],
             reverse: &false,
             recursive: &false,
+            ..Default::default()
         },
     )
     .unwrap();
@@ -666,3 +748,2089 @@ fn test_order_by_multiple_fields() {
     assert_eq!(results[0].entries[6].name, "id3v23_most_tags.mp3");
     assert_eq!(results[0].entries[7].name, "id3v24_most_tags.mp3");
 }
+
+#[test]
+fn test_list_checksum() {
+    let path = test_data_dir()
+        .join("id3v23_most_tags.mp3")
+        .into_os_string()
+        .into_string()
+        .unwrap();
+    let crc32 = list(
+        &vec![path.clone()],
+        &ListOptions {
+            checksum: &Some(ChecksumAlgorithm::Crc32),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(crc32[0].entries[0].checksum, Some(s!("e985eae1")));
+
+    let xxh3 = list(
+        &vec![path],
+        &ListOptions {
+            checksum: &Some(ChecksumAlgorithm::Xxh3),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(xxh3[0].entries[0].checksum, Some(s!("4de2735006e0122b")));
+}
+
+#[test]
+fn test_list_tag_hash() {
+    let tag_hash = |file: &str| {
+        list(
+            &vec![test_data_dir().join(file).into_os_string().into_string().unwrap()],
+            &ListOptions {
+                tag_hash: &Some(ChecksumAlgorithm::Crc32),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .remove(0)
+        .entries
+        .remove(0)
+        .tag_hash
+    };
+
+    assert_eq!(tag_hash("id3v23_most_tags.mp3"), Some(s!("bfea3082")));
+    assert_ne!(tag_hash("id3v23_all_tags.mp3"), tag_hash("id3v23_most_tags.mp3"));
+}
+
+#[test]
+fn test_list_resolve_links() {
+    let path = test_data_dir()
+        .join("some_tags")
+        .into_os_string()
+        .into_string()
+        .unwrap();
+    let results = list(
+        &vec![path],
+        &ListOptions {
+            resolve_links: &true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(results[0].entries.len(), 4);
+    for entry in &results[0].entries {
+        assert_eq!(
+            entry.link_target,
+            Some(
+                test_data_dir()
+                    .canonicalize()
+                    .unwrap()
+                    .join(&entry.name)
+                    .to_string_lossy()
+                    .to_string()
+            )
+        );
+    }
+
+    let path = test_data_dir()
+        .join("id3v24_most_tags.mp3")
+        .into_os_string()
+        .into_string()
+        .unwrap();
+    let results = list(
+        &vec![path],
+        &ListOptions {
+            resolve_links: &true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(results[0].entries[0].link_target, None);
+}
+
+#[test]
+fn test_aggregate() {
+    let path = test_data_dir().into_os_string().into_string().unwrap();
+    let results = list(&vec![path], &Default::default()).unwrap();
+    let stats = aggregate(&results[0].entries, Some(1));
+
+    assert_eq!(stats.files, 8);
+    assert_eq!(stats.size, 3 * 22993 + 3 * 23017 + 2 * 22950);
+    assert_eq!(
+        stats.genres,
+        vec![Count {
+            value: s!("Pop"),
+            count: 4
+        }]
+    );
+    assert_eq!(
+        stats.artists,
+        vec![Count {
+            value: s!("Someone"),
+            count: 6
+        }]
+    );
+    assert_eq!(
+        stats.decades,
+        vec![Count {
+            value: s!("2000s"),
+            count: 6
+        }]
+    );
+    let coverage = |field: &str| stats.coverage.iter().find(|c| c.value == field).unwrap().count;
+    assert_eq!(coverage("artist"), 6);
+    assert_eq!(coverage("album"), 4);
+    assert_eq!(coverage("genre"), 4);
+    assert_eq!(stats.coverage.len(), 14);
+}
+
+#[test]
+fn test_compare() {
+    let entry = |name: &str, title: &str| Entry {
+        name: name.into(),
+        size: 1,
+        title: vec![title.into()],
+        ..Default::default()
+    };
+    let comparison = compare(
+        vec![
+            (s!("a/kept.mp3"), entry("kept.mp3", "Same")),
+            (s!("a/changed.mp3"), entry("changed.mp3", "Old")),
+            (s!("a/removed.mp3"), entry("removed.mp3", "Gone")),
+        ],
+        vec![
+            (s!("a/added.mp3"), entry("added.mp3", "New")),
+            (s!("a/changed.mp3"), entry("changed.mp3", "New")),
+            (s!("a/kept.mp3"), entry("kept.mp3", "Same")),
+        ],
+    );
+
+    assert_eq!(comparison.added, vec![s!("a/added.mp3")] as Vec<String>);
+    assert_eq!(comparison.removed, vec![s!("a/removed.mp3")] as Vec<String>);
+    assert_eq!(
+        comparison.changed,
+        vec![ChangedEntry {
+            path: s!("a/changed.mp3"),
+            changes: vec![FieldChange {
+                field: s!("title"),
+                old: s!("Old"),
+                new: s!("New"),
+            }]
+        }]
+    );
+}
+
+#[test]
+fn test_list_invalid_year() {
+    let path = tagged_copy("invalid_year", "negative.mp3", |tag| tag.set_year(-5))
+        .into_os_string()
+        .into_string()
+        .unwrap();
+    let results = list(&vec![path.clone()], &Default::default()).unwrap();
+    assert_eq!(results[0].entries[0].year, None);
+
+    let results = list(
+        &vec![path],
+        &ListOptions {
+            strict_year: &true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(results[0].entries[0].year, Some(-5));
+    assert_eq!(validate_entry(&results[0].entries[0]), vec![Issue::InvalidYear(-5)]);
+}
+
+#[test]
+fn test_template() {
+    let entry = Entry {
+        name: s!("Some.mp3"),
+        size: 8080,
+        title: vec![s!("Two"), s!("titles")],
+        artist: vec![s!("Someone")],
+        year: Some(2020),
+        track: Track {
+            number: Some(2),
+            total: Some(3),
+        },
+        ..Default::default()
+    };
+    let template: Template = "{artist} - {title} ({year}) {{{track}/{track_total}}} {album}"
+        .parse()
+        .unwrap();
+    assert_eq!(template.render(&entry), "Someone - Two/titles (2020) {2/3} ");
+
+    assert!(matches!(
+        "{artist".parse::<Template>(),
+        Err(LsError::InvalidTemplate(..))
+    ));
+    assert!(matches!(
+        "{unknown}".parse::<Template>(),
+        Err(LsError::InvalidTemplate(..))
+    ));
+    assert!(matches!(
+        "artist}".parse::<Template>(),
+        Err(LsError::InvalidTemplate(..))
+    ));
+}
+
+#[test]
+fn test_cmp_extension() {
+    let entry = |name: &str| Entry {
+        name: name.into(),
+        ..Default::default()
+    };
+    let mut entries = [entry("b.mp3"), entry("a.FLAC"), entry("c"), entry("a.mp3")];
+    entries.sort_by(|a, b| {
+        cmp_entry(
+            a,
+            b,
+            &[
+                (SortBy::Extension, Direction::Ascending),
+                (SortBy::Name, Direction::Ascending),
+            ],
+        )
+    });
+    assert_eq!(
+        entries.iter().map(|e| e.name.to_str().unwrap()).collect::<Vec<_>>(),
+        vec!["c", "a.FLAC", "a.mp3", "b.mp3"]
+    );
+}
+
+#[test]
+fn test_cmp_depth() {
+    let results = list(
+        &vec![test_data_dir().into_os_string().into_string().unwrap()],
+        &ListOptions {
+            recursive: &true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let mut entries: Vec<_> = results
+        .iter()
+        .flat_map(|info| info.entries.iter().map(move |e| (info.path.as_str(), e)))
+        .collect();
+    entries.reverse();
+    entries.sort_by(|(_, a), (_, b)| cmp_entry(a, b, &[(SortBy::Depth, Direction::Ascending)]));
+    let root = test_data_dir().into_os_string().into_string().unwrap();
+    let first_nested = entries.iter().position(|(path, _)| *path != root).unwrap();
+    assert!(first_nested > 0);
+    assert!(entries[..first_nested].iter().all(|(_, e)| e.depth == 0));
+    assert!(entries[first_nested..]
+        .iter()
+        .all(|(path, e)| *path != root && e.depth == 1));
+}
+
+#[test]
+fn test_list_deterministic() {
+    let (path1, path2) = (
+        test_data_dir()
+            .join("some_tags")
+            .into_os_string()
+            .into_string()
+            .unwrap(),
+        test_data_dir().into_os_string().into_string().unwrap(),
+    );
+    let results = list(
+        &vec![path1.clone(), path2.clone()],
+        &ListOptions {
+            sort_by: &[(SortBy::Year, Direction::Ascending)],
+            recursive: &true,
+            deterministic: &true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    // The directories are ordered by path, and entries with equal years by name.
+    assert_eq!(
+        results.iter().map(|i| i.path.as_str()).collect::<Vec<_>>(),
+        vec![
+            path2.as_str(),
+            test_data_dir().join("most_tags").to_str().unwrap(),
+            path1.as_str(),
+            path1.as_str()
+        ]
+    );
+    assert_eq!(
+        results[0]
+            .entries
+            .iter()
+            .map(|e| e.name.to_str().unwrap())
+            .collect::<Vec<_>>(),
+        vec![
+            "id3v23_no_tags.mp3",
+            "id3v24_no_tags.mp3",
+            "id3v23_all_tags.mp3",
+            "id3v23_most_tags.mp3",
+            "id3v23_some_tags.mp3",
+            "id3v24_all_tags.mp3",
+            "id3v24_most_tags.mp3",
+            "id3v24_some_tags.mp3"
+        ]
+    );
+}
+
+#[test]
+fn test_list_original_artist_and_album() {
+    let path = tagged_copy("original", "cover.mp3", |tag| {
+        tag.set_text("TOPE", "The Originals");
+        tag.set_text_values("TOAL", ["First Album", "Reissue"]);
+    })
+    .into_os_string()
+    .into_string()
+    .unwrap();
+    let results = list(&vec![path], &Default::default()).unwrap();
+    assert_eq!(
+        results[0].entries[0].original_artist,
+        vec![s!("The Originals")] as Vec<String>
+    );
+    assert_eq!(
+        results[0].entries[0].original_album,
+        vec![s!("First Album"), s!("Reissue")] as Vec<String>
+    );
+}
+
+#[test]
+fn test_list_conductor_and_remixer() {
+    let dir = tagged_copy("credits", "b.mp3", |tag| {
+        tag.set_text("TPE3", "Karajan");
+        tag.set_text("TPE4", "Someone Else");
+    });
+    tagged_copy("credits", "a.mp3", |tag| tag.set_text("TPE3", "Bernstein"));
+    let results = list(
+        &vec![dir.parent().unwrap().to_str().unwrap().to_owned()],
+        &ListOptions {
+            sort_by: &[(SortBy::Conductor, Direction::Ascending)],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(results[0].entries[0].conductor, vec![s!("Bernstein")] as Vec<String>);
+    assert_eq!(results[0].entries[0].remixer, Vec::<String>::new());
+    assert_eq!(results[0].entries[1].conductor, vec![s!("Karajan")] as Vec<String>);
+    assert_eq!(results[0].entries[1].remixer, vec![s!("Someone Else")] as Vec<String>);
+}
+
+#[test]
+fn test_list_each() {
+    let path = test_data_dir().into_os_string().into_string().unwrap();
+    let options = ListOptions {
+        recursive: &true,
+        ..Default::default()
+    };
+
+    // Results are passed in the same order `list` returns them.
+    let mut streamed = Vec::new();
+    list_each(&vec![path.clone()], &options, |info| streamed.push(info)).unwrap();
+    assert_eq!(streamed, list(&vec![path], &options).unwrap());
+
+    let mut streamed = Vec::new();
+    assert!(list_each(&vec![s!("does_not_exist")], &options, |info| streamed.push(info)).is_err());
+    assert!(streamed.is_empty());
+}
+
+#[test]
+fn test_list_audio_properties() {
+    let path = test_data_dir()
+        .join("id3v24_most_tags.mp3")
+        .into_os_string()
+        .into_string()
+        .unwrap();
+    let entry = |audio_properties| {
+        list(
+            &vec![path.clone()],
+            &ListOptions {
+                audio_properties,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .remove(0)
+        .entries
+        .remove(0)
+    };
+
+    let entry_without = entry(&false);
+    assert_eq!((entry_without.sample_rate, entry_without.channel_mode), (None, None));
+    let entry_with = entry(&true);
+    assert_eq!(
+        (entry_with.sample_rate, entry_with.channel_mode),
+        (Some(44100), Some(ChannelMode::Mono))
+    );
+    assert_eq!(
+        serde_json::to_value(&entry_with).unwrap()["channel_mode"],
+        serde_json::json!("mono")
+    );
+}
+
+#[test]
+fn test_validate_tagged_length() {
+    let entry = |tagged_length_ms: u32| {
+        let path = tagged_copy("validate_tagged_length", "length.mp3", |tag| {
+            tag.set_text("TLEN", tagged_length_ms.to_string())
+        });
+        list(
+            &vec![path.into_os_string().into_string().unwrap()],
+            &ListOptions {
+                duration: &true,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .remove(0)
+        .entries
+        .remove(0)
+    };
+
+    let matching = entry(5000);
+    assert_eq!(
+        (matching.tagged_length_ms, matching.duration_ms),
+        (Some(5000), Some(5067))
+    );
+    assert!(validate_entry(&matching).is_empty());
+    let mismatched = entry(240_000);
+    assert_eq!(validate_entry(&mismatched), vec![Issue::LengthMismatch(240_000, 5067)]);
+}
+
+#[test]
+fn test_list_frame_count() {
+    let path = test_data_dir()
+        .join("id3v24_no_tags.mp3")
+        .into_os_string()
+        .into_string()
+        .unwrap();
+    let entry = list(
+        &vec![path],
+        &ListOptions {
+            frame_count: &true,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+    .remove(0)
+    .entries
+    .remove(0);
+    // 194 frames of 1152 samples, the first being the Info frame, with 576 samples of encoder delay and 1260 of padding.
+    assert_eq!(
+        (entry.frame_count, entry.sample_count, entry.duration_ms),
+        (Some(193), Some(220_500), None)
+    );
+    let json = serde_json::to_value(&entry).unwrap();
+    assert_eq!(
+        (json["frame_count"].as_u64(), json["sample_count"].as_u64()),
+        (Some(193), Some(220_500))
+    );
+}
+
+#[test]
+fn test_list_file_timeout() {
+    let path = test_data_dir()
+        .join("some_tags")
+        .into_os_string()
+        .into_string()
+        .unwrap();
+    let list_with_timeout = |timeout| {
+        list_lenient(
+            &vec![path.clone()],
+            &ListOptions {
+                file_timeout: &Some(timeout),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let (results, errors) = list_with_timeout(Duration::from_secs(60));
+    assert_eq!((results[0].entries.len(), errors.len()), (4, 0));
+    // Without any time to wait, each file is skipped with a timeout, unless its read happened to finish before the result
+    // was checked.
+    let (results, errors) = list_with_timeout(Duration::ZERO);
+    assert_eq!(results.iter().map(|r| r.entries.len()).sum::<usize>() + errors.len(), 4);
+    assert!(errors.iter().all(|err| matches!(err, LsError::Timeout(_))));
+}
+
+#[test]
+fn test_list_modified() {
+    let path = tagged_copy("modified", "a.mp3", |tag| tag.set_title("New"));
+    let mtime = fs::metadata(&path)
+        .unwrap()
+        .modified()
+        .unwrap()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let paths = vec![path.into_os_string().into_string().unwrap()];
+    let entry = |modified| {
+        list(
+            &paths,
+            &ListOptions {
+                modified,
+                ..Default::default()
+            },
+        )
+        .unwrap()[0]
+            .entries[0]
+            .clone()
+    };
+    assert_eq!(entry(&false).modified, None);
+    assert_eq!(entry(&true).modified, Some(mtime));
+}
+
+#[test]
+fn test_validate_clipping() {
+    let entry = |peak: &str| {
+        let path = tagged_copy("validate_clipping", "peak.mp3", |tag| {
+            tag.add_frame(id3::frame::ExtendedText {
+                description: s!("replaygain_track_peak"),
+                value: peak.to_owned(),
+            });
+        });
+        list(&vec![path.into_os_string().into_string().unwrap()], &Default::default())
+            .unwrap()
+            .remove(0)
+            .entries
+            .remove(0)
+    };
+
+    let quiet = entry("0.988525");
+    assert_eq!(quiet.replaygain_track_peak, Some(s!("0.988525")));
+    assert!(validate_entry(&quiet).is_empty());
+    assert_eq!(
+        validate_entry(&entry(" 1.000000")),
+        vec![Issue::Clipping(s!("1.000000"))]
+    );
+    assert_eq!(validate_entry(&entry("1.2")), vec![Issue::Clipping(s!("1.2"))]);
+    assert!(validate_entry(&entry("loud")).is_empty());
+}
+
+#[test]
+fn test_validate_track_total() {
+    let entry = |number, total| Entry {
+        track: Track { number, total },
+        ..Default::default()
+    };
+    assert_eq!(
+        validate_entry(&entry(Some(14), Some(12))),
+        vec![Issue::TrackExceedsTotal(14, 12)]
+    );
+    assert!(validate_entry(&entry(Some(12), Some(12))).is_empty());
+    assert!(validate_entry(&entry(Some(14), None)).is_empty());
+    assert!(validate_entry(&entry(None, Some(12))).is_empty());
+}
+
+#[test]
+fn test_list_skipped() {
+    let path = test_data_dir().into_os_string().into_string().unwrap();
+    let results = list(&vec![path.clone()], &Default::default()).unwrap();
+    assert!(results[0].skipped.is_empty());
+
+    let results = list(
+        &vec![path],
+        &ListOptions {
+            skipped: &true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        results[0]
+            .skipped
+            .iter()
+            .map(|s| s.name.as_os_str())
+            .collect::<Vec<_>>(),
+        vec!["no_id3.mp3"]
+    );
+    assert!(results[0].skipped[0].reason.contains("NoTag"));
+}
+
+#[test]
+fn test_cmp_direction() {
+    let entry = |year, track| Entry {
+        year: Some(year),
+        track: Track {
+            number: Some(track),
+            total: None,
+        },
+        ..Default::default()
+    };
+    let mut entries = [entry(2002, 1), entry(2001, 1), entry(2002, 2), entry(2001, 2)];
+    entries.sort_by(|a, b| {
+        cmp_entry(
+            a,
+            b,
+            &[
+                (SortBy::Year, Direction::Ascending),
+                (SortBy::Track, Direction::Descending),
+            ],
+        )
+    });
+    assert_eq!(
+        entries,
+        [entry(2001, 2), entry(2001, 1), entry(2002, 2), entry(2002, 1)]
+    );
+}
+
+#[test]
+fn test_cmp_untagged_last() {
+    let entry = |title: &str| Entry {
+        title: if title.is_empty() {
+            vec![]
+        } else {
+            vec![title.to_owned()]
+        },
+        ..Default::default()
+    };
+    let sorted = |direction, reverse| {
+        let mut entries = [entry("b"), entry(""), entry("a")];
+        sort_entries(
+            &mut entries,
+            &ListOptions {
+                sort_by: &[(SortBy::Title, direction)],
+                reverse: &reverse,
+                untagged_last: &true,
+                ..Default::default()
+            },
+        );
+        entries
+    };
+    assert_eq!(sorted(Direction::Ascending, false), [entry("a"), entry("b"), entry("")]);
+    assert_eq!(
+        sorted(Direction::Descending, false),
+        [entry("b"), entry("a"), entry("")]
+    );
+    assert_eq!(sorted(Direction::Ascending, true), [entry("b"), entry("a"), entry("")]);
+    assert_eq!(sorted(Direction::Descending, true), [entry("a"), entry("b"), entry("")]);
+}
+
+#[test]
+fn test_cmp_entry_each_key() {
+    use clap::ValueEnum;
+    use std::cmp::Ordering;
+
+    let track = |number| Track { number, total: None };
+    // For each key, an entry without a value, one with a low value and one with a high value, all of which are equal
+    // in every other key.
+    let cases = [
+        (
+            SortBy::Name,
+            Entry::named(""),
+            Entry::named("a.mp3"),
+            Entry::named("b.mp3"),
+        ),
+        (
+            SortBy::Size,
+            Entry::named("a.mp3"),
+            Entry {
+                size: 1,
+                ..Entry::named("a.mp3")
+            },
+            Entry {
+                size: 2,
+                ..Entry::named("a.mp3")
+            },
+        ),
+        (
+            SortBy::Title,
+            Entry::named("a.mp3"),
+            Entry {
+                title: vec![s!("zebra")],
+                title_sort_order: Some(vec![s!("Aardvark")]),
+                ..Entry::named("a.mp3")
+            },
+            Entry {
+                title: vec![s!("b")],
+                ..Entry::named("a.mp3")
+            },
+        ),
+        (
+            SortBy::Artist,
+            Entry::named("a.mp3"),
+            Entry {
+                artist: vec![s!("The Beatles")],
+                artist_sort_order: Some(vec![s!("Beatles, The")]),
+                ..Entry::named("a.mp3")
+            },
+            Entry {
+                artist: vec![s!("cream")],
+                ..Entry::named("a.mp3")
+            },
+        ),
+        (
+            SortBy::Album,
+            Entry::named("a.mp3"),
+            Entry {
+                album: vec![s!("a")],
+                ..Entry::named("a.mp3")
+            },
+            Entry {
+                album: vec![s!("Z")],
+                album_sort_order: Some(vec![s!("b")]),
+                ..Entry::named("a.mp3")
+            },
+        ),
+        (
+            SortBy::Year,
+            Entry::named("a.mp3"),
+            Entry {
+                year: Some(1999),
+                ..Entry::named("a.mp3")
+            },
+            Entry {
+                year: Some(2002),
+                ..Entry::named("a.mp3")
+            },
+        ),
+        (
+            SortBy::Track,
+            Entry::named("a.mp3"),
+            Entry {
+                track: track(Some(2)),
+                ..Entry::named("a.mp3")
+            },
+            Entry {
+                track: track(Some(10)),
+                ..Entry::named("a.mp3")
+            },
+        ),
+        (
+            SortBy::Genre,
+            Entry::named("a.mp3"),
+            Entry {
+                genre: vec![s!("jazz")],
+                ..Entry::named("a.mp3")
+            },
+            Entry {
+                genre: vec![s!("Pop")],
+                ..Entry::named("a.mp3")
+            },
+        ),
+        (
+            SortBy::OriginalArtist,
+            Entry::named("a.mp3"),
+            Entry {
+                original_artist: vec![s!("a")],
+                ..Entry::named("a.mp3")
+            },
+            Entry {
+                original_artist: vec![s!("b")],
+                ..Entry::named("a.mp3")
+            },
+        ),
+        (
+            SortBy::OriginalAlbum,
+            Entry::named("a.mp3"),
+            Entry {
+                original_album: vec![s!("a")],
+                ..Entry::named("a.mp3")
+            },
+            Entry {
+                original_album: vec![s!("a"), s!("b")],
+                ..Entry::named("a.mp3")
+            },
+        ),
+        (
+            SortBy::Conductor,
+            Entry::named("a.mp3"),
+            Entry {
+                conductor: vec![s!("Karajan")],
+                ..Entry::named("a.mp3")
+            },
+            Entry {
+                conductor: vec![s!("Solti")],
+                ..Entry::named("a.mp3")
+            },
+        ),
+        (
+            SortBy::Publisher,
+            Entry::named("a.mp3"),
+            Entry {
+                publisher: vec![s!("EMI")],
+                ..Entry::named("a.mp3")
+            },
+            Entry {
+                publisher: vec![s!("motown")],
+                ..Entry::named("a.mp3")
+            },
+        ),
+        (
+            SortBy::Extension,
+            Entry::named("a"),
+            Entry::named("a.FLAC"),
+            Entry::named("a.mp3"),
+        ),
+        (
+            SortBy::Depth,
+            Entry::named("a.mp3"),
+            Entry {
+                depth: 1,
+                ..Entry::named("a.mp3")
+            },
+            Entry {
+                depth: 2,
+                ..Entry::named("a.mp3")
+            },
+        ),
+        (
+            SortBy::Type,
+            Entry::named("a.mp3"),
+            Entry {
+                format: AudioFormat::Wav,
+                ..Entry::named("a.mp3")
+            },
+            Entry {
+                format: AudioFormat::Aiff,
+                ..Entry::named("a.mp3")
+            },
+        ),
+    ];
+    assert_eq!(
+        cases.iter().map(|(key, ..)| *key).collect::<Vec<_>>(),
+        SortBy::value_variants(),
+        "every key must be covered"
+    );
+
+    for (key, empty, low, high) in &cases {
+        let cmp = |a, b| cmp_entry(a, b, &[(*key, Direction::Ascending)]);
+        assert_eq!(cmp(low, high), Ordering::Less, "{:?}", key);
+        assert_eq!(cmp(high, low), Ordering::Greater, "{:?}", key);
+        assert_eq!(cmp(low, low), Ordering::Equal, "{:?}", key);
+        // Missing values sort before any value in ascending order.
+        assert_eq!(cmp(empty, low), Ordering::Less, "{:?}", key);
+        assert_eq!(
+            cmp_entry(low, high, &[(*key, Direction::Descending)]),
+            Ordering::Greater,
+            "{:?}",
+            key
+        );
+    }
+}
+
+#[test]
+fn test_list_isrc() {
+    let path = tagged_copy("isrc", "a.mp3", |tag| tag.set_text("TSRC", "USRC17607839"))
+        .into_os_string()
+        .into_string()
+        .unwrap();
+    let results = list(&vec![path], &Default::default()).unwrap();
+    assert_eq!(results[0].entries[0].isrc, vec![s!("USRC17607839")] as Vec<String>);
+    assert_eq!(
+        serde_json::to_value(&results[0].entries[0]).unwrap()["isrc"],
+        serde_json::json!("USRC17607839")
+    );
+}
+
+#[test]
+fn test_list_publisher_and_copyright() {
+    let dir = tagged_copy("label", "b.mp3", |tag| {
+        tag.set_text("TPUB", "Motown");
+        tag.set_text("TCOP", "2002 Motown Records");
+    });
+    tagged_copy("label", "a.mp3", |tag| tag.set_text("TPUB", "Atlantic"));
+    let results = list(
+        &vec![dir.parent().unwrap().to_str().unwrap().to_owned()],
+        &ListOptions {
+            sort_by: &[(SortBy::Publisher, Direction::Descending)],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(results[0].entries[0].publisher, vec![s!("Motown")] as Vec<String>);
+    assert_eq!(
+        results[0].entries[0].copyright,
+        vec![s!("2002 Motown Records")] as Vec<String>
+    );
+    assert_eq!(results[0].entries[1].publisher, vec![s!("Atlantic")] as Vec<String>);
+    assert_eq!(results[0].entries[1].copyright, Vec::<String>::new());
+}
+
+#[test]
+fn test_list_lenient() {
+    let (invalid, valid) = (
+        test_data_dir()
+            .join("no_id3.mp3")
+            .into_os_string()
+            .into_string()
+            .unwrap(),
+        test_data_dir()
+            .join("id3v24_most_tags.mp3")
+            .into_os_string()
+            .into_string()
+            .unwrap(),
+    );
+    let paths = vec![invalid, valid.clone()];
+    assert!(matches!(list(&paths, &Default::default()), Err(LsError::NotAnMp3(..))));
+
+    let (results, errors) = list_lenient(&paths, &Default::default()).unwrap();
+    assert_eq!(
+        results.iter().map(|i| i.path.as_str()).collect::<Vec<_>>(),
+        vec![valid.as_str()]
+    );
+    assert!(matches!(errors[..], [LsError::NotAnMp3(..)]));
+    assert_eq!(errors[0].kind(), "not_an_mp3");
+    assert_eq!(errors[0].path(), Some(std::ffi::OsStr::new(&paths[0])));
+
+    // Paths that don't exist are fatal regardless.
+    let err = list_lenient(&vec![s!("does_not_exist"), valid], &Default::default()).unwrap_err();
+    assert!(matches!(err, LsError::InvalidPath(..)));
+    assert_eq!(
+        (err.kind(), err.path()),
+        ("invalid_path", Some(std::ffi::OsStr::new("does_not_exist")))
+    );
+}
+
+#[test]
+fn test_list_no_tags() {
+    let path = test_data_dir().into_os_string().into_string().unwrap();
+    let results = list(
+        &vec![path],
+        &ListOptions {
+            no_tags: &true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    // Files are recognized by extension only, so an .mp3 without a tag is listed too.
+    assert_eq!(results[0].entries.len(), 9);
+    let entry = results[0]
+        .entries
+        .iter()
+        .find(|e| e.name == "id3v24_most_tags.mp3")
+        .unwrap();
+    assert_eq!(
+        entry,
+        &Entry {
+            name: s!("id3v24_most_tags.mp3"),
+            size: 23017,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn test_estimate() {
+    let paths = vec![test_data_dir().into_os_string().into_string().unwrap()];
+    let options = ListOptions {
+        no_tags: &true,
+        ..Default::default()
+    };
+    let listed = &list(&paths, &options).unwrap()[0].entries;
+
+    // The estimate counts exactly the files that a listing without tags finds.
+    assert_eq!(
+        estimate(&paths, &options).unwrap(),
+        Estimate {
+            files: listed.len(),
+            size: listed.iter().map(|e| e.size).sum(),
+        }
+    );
+    assert!(matches!(
+        estimate(&vec![s!("does/not/exist")], &options),
+        Err(LsError::InvalidPath(_))
+    ));
+}
+
+#[test]
+fn test_list_cache() {
+    let path = tagged_copy("cache", "a.mp3", |tag| tag.set_title("Cached"));
+    let cache_path = path.with_file_name("cache.json");
+    _ = fs::remove_file(&cache_path);
+    let paths = vec![path.to_str().unwrap().to_owned()];
+    let list_cached = || {
+        let cache = Some(Cache::load(&cache_path).unwrap());
+        let results = list(
+            &paths,
+            &ListOptions {
+                cache: &cache,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        if let Some(cache) = cache {
+            cache.save(&cache_path).unwrap();
+        }
+        results[0].entries[0].title.clone()
+    };
+    assert_eq!(list_cached(), vec![s!("Cached")] as Vec<String>);
+    assert!(cache_path.exists());
+
+    // Overwrite the tag while keeping the size and modification time, so only the cached tags can be listed.
+    let modified = fs::metadata(&path).unwrap().modified().unwrap();
+    let mut tag = id3::Tag::read_from_path(&path).unwrap();
+    tag.set_title("Change");
+    tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    assert_eq!(list_cached(), vec![s!("Cached")] as Vec<String>);
+
+    // Once the file is modified, the tags are read again.
+    let mut tag = id3::Tag::read_from_path(&path).unwrap();
+    tag.set_title("Changed again");
+    tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+    assert_eq!(list_cached(), vec![s!("Changed again")] as Vec<String>);
+}
+
+#[test]
+fn test_list_cache_sort_order() {
+    tagged_copy("cache_sort_order", "a.mp3", |tag| {
+        tag.set_artist("Aardvark");
+        tag.set_text("TSOP", "Zed");
+    });
+    let path = tagged_copy("cache_sort_order", "b.mp3", |tag| tag.set_artist("Bee"));
+    let dir = path.parent().unwrap();
+    let cache_path = env::temp_dir().join("lsmp3-tests").join("cache_sort_order.json");
+    _ = fs::remove_file(&cache_path);
+    let list_cached = || {
+        let cache = Some(Cache::load(&cache_path).unwrap());
+        let results = list(
+            &vec![dir.to_str().unwrap().to_owned()],
+            &ListOptions {
+                sort_by: &[(SortBy::Artist, Direction::Ascending)],
+                cache: &cache,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        if let Some(cache) = cache {
+            cache.save(&cache_path).unwrap();
+        }
+        results[0].entries.iter().map(|e| e.name.clone()).collect::<Vec<_>>()
+    };
+    assert_eq!(list_cached(), vec!["b.mp3", "a.mp3"]);
+    // The sort order is kept in the cache, so the order is the same when the tags are read from it.
+    assert_eq!(list_cached(), vec!["b.mp3", "a.mp3"]);
+}
+
+#[test]
+fn test_list_cache_settings() {
+    let path = tagged_copy("cache_settings", "a.mp3", |tag| tag.set_title("Long title"));
+    let cache_path = path.with_file_name("cache.json");
+    _ = fs::remove_file(&cache_path);
+    let paths = vec![path.to_str().unwrap().to_owned()];
+    let list_cached = |max_frame_bytes: usize| {
+        let cache = Some(Cache::load(&cache_path).unwrap());
+        let results = list(
+            &paths,
+            &ListOptions {
+                cache: &cache,
+                max_frame_bytes: &max_frame_bytes,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        if let Some(cache) = cache {
+            cache.save(&cache_path).unwrap();
+        }
+        results[0].entries[0].title.clone()
+    };
+    assert_eq!(list_cached(1024), vec![s!("Long title")] as Vec<String>);
+    // Tags cached with another limit aren't reused.
+    assert_eq!(list_cached(4), vec![s!("Long…")] as Vec<String>);
+    assert_eq!(list_cached(1024), vec![s!("Long title")] as Vec<String>);
+}
+
+#[test]
+fn test_list_year_policy() {
+    let path = tagged_copy("list_year_policy", "reissue.mp3", |tag| {
+        tag.set_text_values("TDRC", ["2005-03", "1999", "2012"])
+    })
+    .into_os_string()
+    .into_string()
+    .unwrap();
+    let entry = |year_policy| {
+        list(
+            &vec![path.clone()],
+            &ListOptions {
+                year_policy,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .remove(0)
+        .entries
+        .remove(0)
+    };
+
+    let first = entry(&YearPolicy::First);
+    assert_eq!((first.year, first.years), (Some(2005), vec![2005, 1999, 2012]));
+    assert_eq!(entry(&YearPolicy::Earliest).year, Some(1999));
+    assert_eq!(entry(&YearPolicy::Latest).year, Some(2012));
+}
+
+#[test]
+fn test_list_max_frame_bytes() {
+    let path = tagged_copy("list_max_frame_bytes", "huge.mp3", |tag| {
+        tag.set_album("é".repeat(1000));
+        tag.set_title("Short");
+    })
+    .into_os_string()
+    .into_string()
+    .unwrap();
+    let entry = list(
+        &vec![path],
+        &ListOptions {
+            max_frame_bytes: &11,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+    .remove(0)
+    .entries
+    .remove(0);
+
+    // The text is cut at a character boundary.
+    assert_eq!(entry.album, [format!("{}…", "é".repeat(5))]);
+    assert_eq!(entry.title, ["Short"]);
+    assert_eq!(entry.truncated_frames, ["TALB"]);
+}
+
+#[test]
+fn test_list_oversized_tag() {
+    let dir = env::temp_dir().join("lsmp3-tests").join("list_oversized_tag");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("oversized.mp3");
+    // The header declares the largest possible tag, which the file doesn't actually contain.
+    let mut data = b"ID3\x04\x00\x00\x7f\x7f\x7f\x7f".to_vec();
+    data.extend(fs::read(test_data_dir().join("id3v24_no_tags.mp3")).unwrap());
+    fs::write(&path, data).unwrap();
+
+    let result = list(&vec![path.into_os_string().into_string().unwrap()], &Default::default());
+    assert!(matches!(
+        result,
+        Err(LsError::Id3Error(_, id3::Error { kind: id3::ErrorKind::Io(ref err), .. }))
+            if err.kind() == std::io::ErrorKind::InvalidData
+    ));
+}
+
+#[test]
+fn test_list_wav_aiff() {
+    let dir = env::temp_dir().join("lsmp3-tests").join("list_wav_aiff");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("master.wav");
+    // A mono 8 kHz, 8 bit PCM WAV file without samples.
+    let mut wav = b"RIFF\x24\0\0\0WAVEfmt \x10\0\0\0\x01\0\x01\0\x40\x1f\0\0\x40\x1f\0\0\x01\0\x08\0".to_vec();
+    wav.extend_from_slice(b"data\0\0\0\0");
+    fs::write(&path, wav).unwrap();
+    let mut tag = id3::Tag::new();
+    tag.set_title("Master");
+    tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+
+    let titles = |wav_aiff| {
+        list(
+            &vec![dir.clone().into_os_string().into_string().unwrap()],
+            &ListOptions {
+                wav_aiff,
+                audio_properties: &true,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .remove(0)
+        .entries
+        .into_iter()
+        .map(|e| (e.title, e.sample_rate, e.format))
+        .collect::<Vec<_>>()
+    };
+    assert!(titles(&false).is_empty());
+    assert_eq!(titles(&true), [(vec![s!("Master")], None, AudioFormat::Wav)]);
+}
+
+#[test]
+fn test_list_splits_legacy_values() {
+    let list_version = |file: &str, version| {
+        let path = tagged_copy("splits_legacy_values", file, |_| {});
+        let mut tag = id3::Tag::new();
+        tag.set_artist("One/Two");
+        tag.set_title("AC/DC Live");
+        tag.set_album("Either/Or");
+        tag.set_text("TPE3", "Karajan/Solti");
+        tag.write_to_path(&path, version).unwrap();
+        let paths = vec![path.into_os_string().into_string().unwrap()];
+        let entry = list(&paths, &Default::default()).unwrap().remove(0).entries.remove(0);
+        (entry.artist, entry.title, entry.album, entry.conductor)
+    };
+
+    // ID3v2.3 separates values with a "/" in the frames of people, not only in the frames the id3 crate splits, while
+    // titles and albums may contain one.
+    assert_eq!(
+        list_version("v23.mp3", id3::Version::Id3v23),
+        (
+            vec![s!("One"), s!("Two")],
+            vec![s!("AC/DC Live")],
+            vec![s!("Either/Or")],
+            vec![s!("Karajan"), s!("Solti")]
+        )
+    );
+    // ID3v2.4 separates them with a null character, so a "/" is part of the value.
+    assert_eq!(
+        list_version("v24.mp3", id3::Version::Id3v24),
+        (
+            vec![s!("One/Two")],
+            vec![s!("AC/DC Live")],
+            vec![s!("Either/Or")],
+            vec![s!("Karajan/Solti")]
+        )
+    );
+}
+
+#[test]
+fn test_list_merge_tags() {
+    let path = tagged_copy("merge_tags", "a.mp3", |tag| tag.set_title("From v2"));
+
+    // Append an ID3v1 tag, which is a fixed 128 byte block at the end of the file.
+    let field = |s: &str, len: usize| {
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.resize(len, 0);
+        bytes
+    };
+    let mut v1 = b"TAG".to_vec();
+    v1.extend(field("From v1", 30));
+    v1.extend(field("Someone", 30));
+    v1.extend(field("Old Album", 30));
+    v1.extend(field("1999", 4));
+    v1.extend(field("", 30));
+    v1.push(255);
+    let mut bytes = fs::read(&path).unwrap();
+    bytes.extend(v1);
+    fs::write(&path, bytes).unwrap();
+
+    let paths = vec![path.into_os_string().into_string().unwrap()];
+    let entry = list(&paths, &Default::default()).unwrap()[0].entries[0].clone();
+    assert_eq!(
+        (entry.title, entry.artist, entry.year),
+        (vec![s!("From v2")], vec![], None)
+    );
+
+    let entry = list(
+        &paths,
+        &ListOptions {
+            merge_tags: &true,
+            ..Default::default()
+        },
+    )
+    .unwrap()[0]
+        .entries[0]
+        .clone();
+    assert_eq!(entry.title, vec![s!("From v2")] as Vec<String>);
+    assert_eq!(entry.artist, vec![s!("Someone")] as Vec<String>);
+    assert_eq!(entry.album, vec![s!("Old Album")] as Vec<String>);
+    assert_eq!(entry.year, Some(1999));
+}
+
+#[test]
+fn test_list_merge_tags_wav_aiff() {
+    let dir = env::temp_dir().join("lsmp3-tests").join("merge_tags_wav_aiff");
+    fs::create_dir_all(&dir).unwrap();
+    // A WAV file without samples or an ID3 chunk, but with an ID3v1 tag at the end.
+    let mut wav = b"RIFF\x24\0\0\0WAVEfmt \x10\0\0\0\x01\0\x01\0\x40\x1f\0\0\x40\x1f\0\0\x01\0\x08\0".to_vec();
+    wav.extend_from_slice(b"data\0\0\0\0TAG");
+    wav.resize(wav.len() + 125, 0);
+    fs::write(dir.join("master.wav"), wav).unwrap();
+
+    // WAV and AIFF files aren't listed unless enabled, even if they have an ID3v1 tag to fall back to.
+    let results = list(
+        &vec![dir.into_os_string().into_string().unwrap()],
+        &ListOptions {
+            merge_tags: &true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(results[0].entries.is_empty());
+}
+
+#[cfg(feature = "remote")]
+#[test]
+fn test_is_url() {
+    assert!(is_url("http://example.com/a.mp3"));
+    assert!(is_url("https://example.com/music/b.mp3"));
+    assert!(!is_url("music/http:/a.mp3"));
+    assert!(!is_url("ftp://example.com/a.mp3"));
+}
+
+#[test]
+fn test_list_strips_bom() {
+    let path = tagged_copy("bom", "a.mp3", |tag| {
+        tag.set_title("\u{feff}Song");
+        tag.set_artist("\u{200b}\u{1}Someone");
+        tag.set_album("\u{feff}");
+        tag.set_genre("Pop\u{feff}");
+    })
+    .into_os_string()
+    .into_string()
+    .unwrap();
+    let entry = &list(&vec![path], &Default::default()).unwrap()[0].entries[0];
+    assert_eq!(entry.title, ["Song"]);
+    assert_eq!(entry.artist, ["Someone"]);
+    assert!(entry.album.is_empty());
+    // Only a prefix is stripped.
+    assert_eq!(entry.genre, ["Pop\u{feff}"]);
+}
+
+#[test]
+fn test_read_artwork() {
+    use id3::frame::{Picture, PictureType};
+    let picture = |picture_type, mime_type: &str, data: Vec<u8>| Picture {
+        mime_type: mime_type.to_owned(),
+        picture_type,
+        description: String::new(),
+        data,
+    };
+    let png = [
+        b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".as_slice(),
+        &640u32.to_be_bytes(),
+        &480u32.to_be_bytes(),
+    ]
+    .concat();
+    // An APP0 segment followed by a baseline start of frame of 400x300.
+    let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe0, 0, 16];
+    jpeg.extend([0; 14]);
+    jpeg.extend([0xff, 0xc0, 0, 17, 8, 0x01, 0x2c, 0x01, 0x90]);
+
+    let path = tagged_copy("artwork", "a.mp3", |tag| {
+        tag.add_frame(picture(PictureType::Artist, "image/png", png.clone()));
+        tag.add_frame(picture(PictureType::CoverFront, "image/jpeg", jpeg));
+    });
+    assert_eq!(
+        read_artwork(&path).unwrap(),
+        Some(Artwork {
+            mime: s!("image/jpeg"),
+            width: Some(400),
+            height: Some(300),
+        })
+    );
+    let path = tagged_copy("artwork", "b.mp3", |tag| {
+        tag.add_frame(picture(PictureType::Artist, "image/png", png));
+    });
+    assert_eq!(
+        read_artwork(&path).unwrap().map(|a| (a.width, a.height)),
+        Some((Some(640), Some(480)))
+    );
+    let path = tagged_copy("artwork", "c.mp3", |tag| {
+        tag.add_frame(picture(PictureType::CoverFront, "image/webp", b"RIFF".to_vec()));
+    });
+    assert_eq!(read_artwork(&path).unwrap().map(|a| a.width), Some(None));
+    assert_eq!(
+        read_artwork(&test_data_dir().join("id3v24_most_tags.mp3")).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn test_list_trim() {
+    let path = tagged_copy("trim", "a.mp3", |tag| {
+        tag.set_artist("  Pink   Floyd ");
+        tag.set_title("\tTime");
+        tag.set_album("   ");
+    })
+    .into_os_string()
+    .into_string()
+    .unwrap();
+    let paths = vec![path];
+
+    let entry = &list(&paths, &Default::default()).unwrap()[0].entries[0];
+    assert_eq!(entry.artist, vec![s!("  Pink   Floyd ")] as Vec<String>);
+    assert_eq!(entry.album, vec![s!("   ")] as Vec<String>);
+
+    let entry = &list(
+        &paths,
+        &ListOptions {
+            trim: &true,
+            ..Default::default()
+        },
+    )
+    .unwrap()[0]
+        .entries[0];
+    assert_eq!(entry.artist, vec![s!("Pink Floyd")] as Vec<String>);
+    assert_eq!(entry.title, vec![s!("Time")] as Vec<String>);
+    assert_eq!(entry.album, vec![] as Vec<String>);
+}
+
+#[test]
+fn test_list_split_on() {
+    let path = tagged_copy("split_on", "a.mp3", |tag| {
+        tag.set_artist("A; B;C");
+        tag.set_genre("Rock\\\\Pop");
+        tag.set_title("Rock & Roll");
+    })
+    .into_os_string()
+    .into_string()
+    .unwrap();
+    let paths = vec![path];
+
+    let entry = &list(&paths, &Default::default()).unwrap()[0].entries[0];
+    assert_eq!(entry.artist, ["A; B;C"]);
+
+    let entry = &list(
+        &paths,
+        &ListOptions {
+            split_on: &[';', '\\'],
+            ..Default::default()
+        },
+    )
+    .unwrap()[0]
+        .entries[0];
+    assert_eq!(entry.artist, ["A", "B", "C"]);
+    assert_eq!(entry.genre, ["Rock", "Pop"]);
+    assert_eq!(entry.title, ["Rock & Roll"]);
+}
+
+#[test]
+fn test_list_compilation() {
+    let dir = tagged_copy("compilation", "a.mp3", |tag| tag.set_text("TCMP", "1"));
+    tagged_copy("compilation", "b.mp3", |tag| tag.set_text("TCMP", "0"));
+    tagged_copy("compilation", "c.mp3", |tag| tag.set_title("Not flagged"));
+    let path = dir.parent().unwrap().to_str().unwrap().to_owned();
+    let results = list(&vec![path], &Default::default()).unwrap();
+    assert_eq!(
+        results[0].entries.iter().map(|e| e.compilation).collect::<Vec<_>>(),
+        [true, false, false]
+    );
+    assert_eq!(
+        serde_json::to_value(&results[0].entries[0]).unwrap()["compilation"],
+        serde_json::json!(true)
+    );
+    assert!(serde_json::to_value(&results[0].entries[1])
+        .unwrap()
+        .get("compilation")
+        .is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_list_unsupported_file_type() {
+    let err = list(&vec![s!("/dev/null")], &Default::default()).unwrap_err();
+    assert!(matches!(err, LsError::UnsupportedFileType(..)));
+    assert_eq!(
+        err.to_string(),
+        "cannot list \"/dev/null\": a character device is neither a file nor a directory"
+    );
+    assert!(matches!(
+        estimate(&vec![s!("/dev/null")], &Default::default()),
+        Err(LsError::UnsupportedFileType(..))
+    ));
+}
+
+#[test]
+fn test_list_max_results() {
+    let path = test_data_dir().into_os_string().into_string().unwrap();
+    let results = list(
+        &vec![path.clone(), path],
+        &ListOptions {
+            recursive: &true,
+            deterministic: &true,
+            max_results: &Some(10),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(results.iter().map(|info| info.entries.len()).sum::<usize>(), 10);
+    // The first path alone has more than enough files, so listing stops before the second one.
+    assert!(results.iter().all(|info| info.path.starts_with(&results[0].path)));
+
+    let results = list(
+        &vec![test_data_dir().into_os_string().into_string().unwrap()],
+        &ListOptions {
+            max_results: &Some(0),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(results.is_empty());
+}
+
+#[test]
+fn test_list_invalid_text_encoding() {
+    let path = tagged_copy("invalid_text_encoding", "a.mp3", |tag| {
+        tag.set_artist("Someone");
+        tag.set_title("Mislabeled XXXX");
+    });
+    // ID3v2.4 text frames are written as UTF-8, so replace part of the title with a sequence that isn't valid UTF-8.
+    let mut bytes = fs::read(&path).unwrap();
+    let at = bytes.windows(4).position(|w| w == b"XXXX").unwrap();
+    bytes[at..at + 4].copy_from_slice(b"\xe9t\xe9!");
+    fs::write(&path, bytes).unwrap();
+
+    let results = list(&vec![path.into_os_string().into_string().unwrap()], &Default::default()).unwrap();
+    let entry = &results[0].entries[0];
+    assert!(entry.invalid_text_encoding);
+    assert_eq!(validate_entry(entry), vec![Issue::InvalidTextEncoding]);
+    assert_eq!(
+        serde_json::to_value(entry).unwrap()["invalid_text_encoding"],
+        serde_json::json!(true)
+    );
+}
+
+#[test]
+fn test_list_sort_disc_and_track() {
+    let track = |disc, number| {
+        move |tag: &mut id3::Tag| {
+            tag.set_album("Box Set");
+            tag.set_disc(disc);
+            tag.set_track(number);
+        }
+    };
+    // Named so that sorting by name would interleave the discs.
+    let dir = tagged_copy("disc_and_track", "a.mp3", track(2, 1));
+    tagged_copy("disc_and_track", "b.mp3", track(1, 2));
+    tagged_copy("disc_and_track", "c.mp3", track(2, 2));
+    tagged_copy("disc_and_track", "d.mp3", track(1, 1));
+    let path = dir.parent().unwrap().to_str().unwrap().to_owned();
+
+    let results = list(
+        &vec![path],
+        &ListOptions {
+            sort_by: &[
+                (SortBy::Album, Direction::Ascending),
+                (SortBy::Track, Direction::Ascending),
+            ],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        results[0]
+            .entries
+            .iter()
+            .map(|e| (e.disc, e.track.number))
+            .collect::<Vec<_>>(),
+        [
+            (Some(1), Some(1)),
+            (Some(1), Some(2)),
+            (Some(2), Some(1)),
+            (Some(2), Some(2))
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_list_dedup_targets() {
+    let pool = tagged_copy("dedup_targets", "pool.mp3", |tag| tag.set_title("Pooled"));
+    let dir = pool.parent().unwrap().join("links");
+    _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for name in ["b.mp3", "a.mp3"] {
+        std::os::unix::fs::symlink(&pool, dir.join(name)).unwrap();
+    }
+    fs::copy(&pool, dir.join("c.mp3")).unwrap();
+
+    let names = |dedup_targets| {
+        list(
+            &vec![dir.clone().into_os_string().into_string().unwrap()],
+            &ListOptions {
+                dedup_targets: &dedup_targets,
+                ..Default::default()
+            },
+        )
+        .unwrap()[0]
+            .entries
+            .iter()
+            .map(|e| e.name.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(false),
+        vec![s!("a.mp3"), s!("b.mp3"), s!("c.mp3")] as Vec<std::ffi::OsString>
+    );
+    // The copy is a different file, so only the second symlink is dropped.
+    assert_eq!(names(true), vec![s!("a.mp3"), s!("c.mp3")] as Vec<std::ffi::OsString>);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_list_dedup_inodes() {
+    let pool = tagged_copy("dedup_inodes", "pool.mp3", |tag| tag.set_title("Pooled"));
+    let dir = pool.parent().unwrap().join("library");
+    _ = fs::remove_dir_all(&dir);
+    for sub in ["a", "b"] {
+        fs::create_dir_all(dir.join(sub)).unwrap();
+        fs::hard_link(&pool, dir.join(sub).join("linked.mp3")).unwrap();
+    }
+    fs::copy(&pool, dir.join("b").join("copy.mp3")).unwrap();
+
+    let names = |dedup_inodes| {
+        list(
+            &vec![
+                dir.clone().into_os_string().into_string().unwrap(),
+                pool.clone().into_os_string().into_string().unwrap(),
+            ],
+            &ListOptions {
+                recursive: &true,
+                dedup_inodes: &dedup_inodes,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .iter()
+        .flat_map(|info| info.entries.iter().map(|e| e.name.clone()))
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(false),
+        vec![s!("linked.mp3"), s!("copy.mp3"), s!("linked.mp3"), s!("pool.mp3")] as Vec<std::ffi::OsString>
+    );
+    // Only the first hard link is kept, across directories and paths given explicitly, but not the copy.
+    assert_eq!(
+        names(true),
+        vec![s!("linked.mp3"), s!("copy.mp3")] as Vec<std::ffi::OsString>
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_list_follow_first() {
+    use std::os::unix::fs::symlink;
+
+    let pool = tagged_copy("follow_first", "pool.mp3", |tag| tag.set_title("Pooled"));
+    let base = pool.parent().unwrap();
+    let (dir, link) = (base.join("library"), base.join("library-link"));
+    _ = fs::remove_dir_all(&dir);
+    _ = fs::remove_file(&link);
+    fs::create_dir_all(dir.join("real")).unwrap();
+    fs::copy(&pool, dir.join("real").join("a.mp3")).unwrap();
+    symlink(&pool, dir.join("linked.mp3")).unwrap();
+    symlink(test_data_dir(), dir.join("linked-dir")).unwrap();
+    symlink(&dir, &link).unwrap();
+
+    let names = |follow_first| {
+        list(
+            &vec![link.clone().into_os_string().into_string().unwrap()],
+            &ListOptions {
+                recursive: &true,
+                follow_first: &follow_first,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .iter()
+        .flat_map(|info| info.entries.iter().map(|e| e.name.clone()))
+        .collect::<Vec<_>>()
+    };
+    assert!(names(false).len() > 2);
+    // The symlink given as a path is followed, but not the ones inside it.
+    assert_eq!(names(true), vec![s!("a.mp3")] as Vec<std::ffi::OsString>);
+}
+
+#[test]
+fn test_predicate_parse() {
+    assert_eq!("sample_rate=48000".parse(), Ok(Predicate::SampleRate(48000)));
+    assert_eq!(
+        "channel_mode=Joint-Stereo".parse(),
+        Ok(Predicate::ChannelMode(ChannelMode::JointStereo))
+    );
+    assert!("channel_mode=quad".parse::<Predicate>().is_err());
+    assert!("bitrate=128".parse::<Predicate>().is_err());
+    assert!("mono".parse::<Predicate>().is_err());
+    assert_eq!("isrc=us-rc1-76-07839".parse(), Ok(Predicate::Isrc(s!("USRC17607839"))));
+    assert_eq!("compilation=true".parse(), Ok(Predicate::Compilation(true)));
+    assert!("compilation=yes".parse::<Predicate>().is_err());
+}
+
+#[test]
+fn test_filter_entries() {
+    let entry = |name: &str, channel_mode, isrc: &str, modified| Entry {
+        name: name.into(),
+        channel_mode,
+        isrc: vec![isrc.to_owned()],
+        modified,
+        ..Default::default()
+    };
+    let entries = vec![
+        entry("a.mp3", Some(ChannelMode::Mono), "US-RC1-76-07839", Some(100)),
+        entry("b.mp3", Some(ChannelMode::Stereo), "GBAYE0601498", Some(99)),
+        entry("c.mp3", None, "usrc17607839", None),
+    ];
+    let names = |predicates: &[Predicate]| {
+        filter_entries(entries.clone(), predicates)
+            .into_iter()
+            .map(|e| e.name)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(names(&[]).len(), 3);
+    // ISRCs are compared without hyphens and case, even if the predicate wasn't parsed.
+    assert_eq!(
+        names(&[Predicate::Isrc(s!("us-rc1-76-07839"))]),
+        vec![s!("a.mp3"), s!("c.mp3")] as Vec<std::ffi::OsString>
+    );
+    assert_eq!(
+        names(&[Predicate::ChannelMode(ChannelMode::Mono)]),
+        vec![s!("a.mp3")] as Vec<std::ffi::OsString>
+    );
+    assert_eq!(
+        names(&[Predicate::ModifiedSince(100)]),
+        vec![s!("a.mp3")] as Vec<std::ffi::OsString>
+    );
+    // Entries without a modification time match neither end of a range.
+    assert_eq!(
+        names(&[Predicate::ModifiedUntil(100)]),
+        vec![s!("b.mp3")] as Vec<std::ffi::OsString>
+    );
+    // All the predicates must match.
+    assert!(names(&[Predicate::Compilation(false), Predicate::SampleRate(44100)]).is_empty());
+    assert!(!Predicate::Compilation(true).matches(&entries[0]));
+}
+
+#[test]
+fn test_verify_audio() {
+    let path = tagged_copy("verify_audio", "intact.mp3", |tag| tag.set_title("Intact"));
+    assert_eq!(verify_audio(&path).unwrap(), vec![]);
+    let data = fs::read(&path).unwrap();
+    // The audio starts after the ID3v2 tag, whose size is a synchsafe integer, and ends before the ID3v1 tag.
+    let start = 10
+        + data[6..10]
+            .iter()
+            .fold(0, |size, b| (size << 7) | usize::from(b & 0x7f));
+    let end = data.len() - 128;
+    let verify = |name: &str, parts: &[&[u8]]| {
+        let path = path.with_file_name(name);
+        fs::write(&path, parts.concat()).unwrap();
+        verify_audio(&path).unwrap()
+    };
+
+    assert_eq!(
+        verify("leading.mp3", &[&data[..start], &[0; 7], &data[start..]]),
+        vec![AudioDefect::Garbage(start as u64, 7)]
+    );
+    assert_eq!(
+        verify("trailing.mp3", &[&data[..end], b"junk", &data[end..]]),
+        vec![AudioDefect::Garbage(end as u64, 4)]
+    );
+    let truncated = verify("truncated.mp3", &[&data[..end - 100]]);
+    assert!(matches!(truncated[..], [AudioDefect::Truncated(_, 100)]));
+    assert_eq!(verify("empty.mp3", &[&data[..start]]), vec![AudioDefect::NoFrames]);
+    // Invalid side information makes the first frame undecodable, while its framing is intact.
+    let mut corrupt = data.clone();
+    corrupt[start + 4..start + 36].fill(0xff);
+    assert_eq!(
+        verify("undecodable.mp3", &[&corrupt]),
+        vec![AudioDefect::Undecodable(start as u64)]
+    );
+}
+
+#[test]
+fn test_set_tags() {
+    let path = tagged_copy("set_tags", "a.mp3", |tag| {
+        tag.set_artist("Someone");
+        tag.set_title("Old");
+        tag.set_year(1999);
+    });
+    let values = vec![
+        (SortBy::Title, s!("New")),
+        (SortBy::Artist, s!("Someone")),
+        (SortBy::Track, s!("3/12")),
+        (SortBy::Year, s!("")),
+    ];
+    let changes = set_tags(&path, &values, true).unwrap();
+    assert_eq!(
+        changes.iter().map(|c| c.field.as_str()).collect::<Vec<_>>(),
+        vec!["title", "track", "year"]
+    );
+    let listed = |path: &std::path::Path| {
+        list(&vec![path.to_str().unwrap().to_owned()], &Default::default()).unwrap()[0].entries[0].clone()
+    };
+    // A dry run leaves the file untouched.
+    assert_eq!(listed(&path).title, vec!["Old"]);
+
+    assert_eq!(set_tags(&path, &values, false).unwrap(), changes);
+    let entry = listed(&path);
+    assert_eq!(entry.title, vec!["New"]);
+    assert_eq!(entry.artist, vec!["Someone"]);
+    assert_eq!(
+        entry.track,
+        Track {
+            number: Some(3),
+            total: Some(12)
+        }
+    );
+    assert_eq!(entry.year, None);
+    assert!(set_tags(&path, &values, false).unwrap().is_empty());
+
+    assert!(matches!(
+        set_tags(&path, &[(SortBy::Year, s!("soon"))], false),
+        Err(LsError::InvalidTagValue(..))
+    ));
+    assert!(matches!(
+        set_tags(&path, &[(SortBy::Size, s!("1"))], false),
+        Err(LsError::InvalidTagValue(..))
+    ));
+}
+
+#[test]
+fn test_template_render_path() {
+    let template: Template = "{artist}/{album}/{track} {title}.mp3".parse().unwrap();
+    let entry = Entry {
+        title: vec![s!("Who?")],
+        artist: vec![s!("AC/DC")],
+        album: vec![s!("..")],
+        track: Track {
+            number: Some(3),
+            total: None,
+        },
+        ..Default::default()
+    };
+    assert_eq!(
+        template.render_path(&entry),
+        ["AC_DC", "_", "3 Who_.mp3"].iter().collect::<PathBuf>()
+    );
+    // Missing values leave empty components, which are dropped instead of making the path absolute.
+    assert_eq!(template.render_path(&Entry::default()), PathBuf::from(".mp3"));
+}
+
+#[test]
+fn test_organize_file() {
+    let from = tagged_copy("organize_file", "a.mp3", |tag| tag.set_title("A"));
+    let to = from.parent().unwrap().join("out").join("Artist").join("a.mp3");
+    _ = fs::remove_dir_all(from.parent().unwrap().join("out"));
+
+    organize_file(&from, &to, false).unwrap();
+    assert!(from.exists() && to.exists());
+    assert!(matches!(
+        organize_file(&from, &to, true),
+        Err(LsError::IoWriteError(..))
+    ));
+
+    fs::remove_file(&to).unwrap();
+    organize_file(&from, &to, true).unwrap();
+    assert!(!from.exists() && to.exists());
+}
+
+#[test]
+fn test_entry_json_round_trip() {
+    let entries = vec![
+        Entry::named("none.mp3"),
+        Entry {
+            size: 1,
+            title: vec![s!("One")],
+            artist: vec![s!("Someone")],
+            year: Some(2002),
+            track: Track {
+                number: Some(3),
+                total: Some(12),
+            },
+            disc: Some(1),
+            compilation: true,
+            channel_mode: Some(ChannelMode::JointStereo),
+            ..Entry::named("one.mp3")
+        },
+        Entry {
+            title: vec![s!("Two"), s!("titles")],
+            artist: vec![s!("Three"), s!("cool"), s!("artists")],
+            genre: vec![s!("Trip-Hop"), s!("Hip-Hop")],
+            isrc: vec![s!("US-RC1-76-07839"), s!("GB-AYE-69-00531")],
+            ..Entry::named("many.mp3")
+        },
+    ];
+    let json = serde_json::to_value(&entries).unwrap();
+    // A single value is written as a plain string and multiple values as a list, and both are read back the same.
+    assert_eq!(json[1]["title"], serde_json::json!("One"));
+    assert_eq!(json[2]["title"], serde_json::json!(["Two", "titles"]));
+    assert_eq!(serde_json::from_value::<Vec<Entry>>(json).unwrap(), entries);
+
+    let entry: Entry = serde_json::from_str(r#"{"name": "a.mp3", "title": ["One"], "artist": null}"#).unwrap();
+    assert_eq!(entry.title, vec!["One"]);
+    assert!(entry.artist.is_empty());
+}
+
+#[test]
+fn test_read_frame_encodings() {
+    let encodings = |file: &str| {
+        read_frame_encodings(&test_data_dir().join(file))
+            .unwrap()
+            .into_iter()
+            .map(|frame| (frame.id, frame.encoding))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        encodings("id3v23_some_tags.mp3"),
+        [
+            (s!("TIT2"), Some(TextEncoding::Utf16)),
+            (s!("TPE1"), Some(TextEncoding::Utf16)),
+            (s!("TDAT"), Some(TextEncoding::Utf16)),
+            (s!("TYER"), Some(TextEncoding::Utf16)),
+        ]
+    );
+    assert_eq!(
+        encodings("id3v24_some_tags.mp3"),
+        [
+            (s!("TIT2"), Some(TextEncoding::Utf8)),
+            (s!("TPE1"), Some(TextEncoding::Utf8)),
+            (s!("TDRC"), Some(TextEncoding::Utf8)),
+        ]
+    );
+    assert!(encodings("id3v24_no_tags.mp3").is_empty());
+    assert!(encodings("no_id3.mp3").is_empty());
+}
+
+#[test]
+fn test_read_chapters() {
+    let path = tagged_copy("read_chapters", "book.mp3", |tag| {
+        tag.set_title("Book");
+        for (id, title, start, end) in [("ch2", "Two", 60_000, 125_500), ("ch1", "One", 0, 60_000)] {
+            tag.add_frame(id3::frame::Chapter {
+                element_id: id.to_owned(),
+                start_time: start,
+                end_time: end,
+                start_offset: u32::MAX,
+                end_offset: u32::MAX,
+                frames: vec![id3::Frame::text("TIT2", title)],
+            });
+        }
+    });
+    assert_eq!(
+        read_chapters(&path).unwrap(),
+        vec![
+            Chapter {
+                title: Some(s!("One")),
+                start_ms: 0,
+                end_ms: 60_000,
+            },
+            Chapter {
+                title: Some(s!("Two")),
+                start_ms: 60_000,
+                end_ms: 125_500,
+            },
+        ]
+    );
+    let results = list(&vec![path.into_os_string().into_string().unwrap()], &Default::default()).unwrap();
+    assert_eq!(results[0].entries[0].chapter_count, Some(2));
+
+    let path = test_data_dir().join("id3v24_most_tags.mp3");
+    assert!(read_chapters(&path).unwrap().is_empty());
+}
+
+#[test]
+fn test_fuzzy_score() {
+    let entry = |title: &str, album: &str| Entry {
+        title: vec![title.to_owned()],
+        artist: vec![s!("Pink Floyd")],
+        album: vec![album.to_owned()],
+        ..Default::default()
+    };
+    let moon = entry("Time", "The Dark Side of the Moon");
+    let wall = entry("Hey You", "The Wall");
+    let scattered = entry("Dreaming", "Ark Sides");
+
+    assert!(fuzzy_score("darkside", &moon).is_some());
+    assert!(fuzzy_score("DARK side", &moon).is_some());
+    assert_eq!(fuzzy_score("darkside", &wall), None);
+    // Matching whole words beats matching scattered letters.
+    assert!(fuzzy_score("darkside", &moon) > fuzzy_score("darkside", &scattered));
+    assert!(fuzzy_score("floyd", &wall).is_some());
+    assert_eq!(fuzzy_score("", &wall), Some(0));
+}
+
+#[test]
+fn test_sort_ignore_articles() {
+    let entry = |artist: &str, sort_order: Option<&str>| Entry {
+        artist: vec![artist.to_owned()],
+        artist_sort_order: sort_order.map(|s| vec![s.to_owned()]),
+        ..Entry::named(artist)
+    };
+    let sorted = |articles: &[String]| {
+        let mut entries = [
+            entry("The Beatles", None),
+            entry("Cream", None),
+            entry("A Tribe Called Quest", None),
+            entry("The The", None),
+            entry("The Zombies", Some("The Zombies")),
+            entry("Abba", None),
+        ];
+        sort_entries(
+            &mut entries,
+            &ListOptions {
+                sort_by: &[(SortBy::Artist, Direction::Ascending)],
+                ignore_articles: articles,
+                ..Default::default()
+            },
+        );
+        entries.map(|e| e.artist[0].clone())
+    };
+    assert_eq!(
+        sorted(&[]),
+        [
+            "A Tribe Called Quest",
+            "Abba",
+            "Cream",
+            "The Beatles",
+            "The The",
+            "The Zombies"
+        ]
+    );
+    // An explicit sort order is used as is, and an article that is the whole rest of the value is kept.
+    assert_eq!(
+        sorted(&[s!("the"), s!("a")]),
+        [
+            "Abba",
+            "The Beatles",
+            "Cream",
+            "The The",
+            "The Zombies",
+            "A Tribe Called Quest"
+        ]
+    );
+}
+
+#[test]
+fn test_sort_genre_priority() {
+    let entry = |genre: &str| Entry {
+        genre: vec![genre.to_owned()],
+        ..Entry::named(genre)
+    };
+    let mut entries = [
+        entry("Ambient"),
+        entry("jazz"),
+        entry("Pop"),
+        entry("Rock"),
+        entry("Blues"),
+        entry("Classical"),
+    ];
+    sort_entries(
+        &mut entries,
+        &ListOptions {
+            sort_by: &[(SortBy::Genre, Direction::Ascending)],
+            genre_priority: &[s!("Rock"), s!("Jazz"), s!("Classical")],
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        entries.map(|e| e.genre[0].clone()),
+        ["Rock", "jazz", "Classical", "Ambient", "Blues", "Pop"]
+    );
+}
+
+#[test]
+fn test_list_sample() {
+    let path = test_data_dir().into_os_string().into_string().unwrap();
+    let names = |sample: Option<Sample>, seed: u64| {
+        list(
+            &vec![path.clone()],
+            &ListOptions {
+                sample: &sample,
+                sample_seed: &seed,
+                ..Default::default()
+            },
+        )
+        .unwrap()[0]
+            .entries
+            .iter()
+            .map(|e| e.name.clone())
+            .collect::<Vec<_>>()
+    };
+    let all = names(None, 0);
+    assert_eq!(names(Some(Sample::Percent(100.0)), 0), all);
+    assert!(names(Some(Sample::Percent(0.0)), 0).is_empty());
+    assert_eq!(names(Some(Sample::Count(100)), 0), all);
+
+    // Only files with an .mp3 extension are candidates, but not all of them are MP3s with tags.
+    for seed in 0..20 {
+        let sampled = names(Some(Sample::Count(4)), seed);
+        assert!(sampled.len() <= 4);
+        assert!(sampled.iter().all(|name| all.contains(name)));
+        assert_eq!(names(Some(Sample::Count(4)), seed), sampled);
+    }
+}